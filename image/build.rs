@@ -1,4 +1,51 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use symtab::Symbol;
+
+/// Section the kernel reserves for its symbol table, see kernel/src/symbols.rs
+const SYMBOL_SECTION: &str = ".ksymtab";
+
+/// Copy of the kernel with its function symbols written into the reserved
+/// section, so backtraces can name the functions they pass through
+fn embed_symbols(kernel: &Path, out: &Path) {
+    let mut image = std::fs::read(kernel).unwrap();
+    let elf = object::File::parse(&*image).unwrap();
+
+    let section = elf
+        .section_by_name(SYMBOL_SECTION)
+        .unwrap_or_else(|| panic!("kernel has no {} section", SYMBOL_SECTION));
+    let (offset, size) = section
+        .file_range()
+        .unwrap_or_else(|| panic!("{} section has no file data", SYMBOL_SECTION));
+
+    let symbols = elf
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter_map(|symbol| {
+            let name = symbol.name().ok()?;
+            Some(Symbol {
+                address: symbol.address(),
+                size: u32::try_from(symbol.size()).unwrap_or(0),
+                // `{:#}` leaves out the hash of legacy mangled names
+                name: format!("{:#}", rustc_demangle::demangle(name)),
+            })
+        })
+        .collect();
+    let table = symtab::encode(section.address(), symbols);
+    if table.len() as u64 > size {
+        panic!(
+            "symbol table needs {} bytes, {} reserves {}",
+            table.len(),
+            SYMBOL_SECTION,
+            size
+        );
+    }
+
+    let offset = offset as usize;
+    image[offset..offset + table.len()].copy_from_slice(&table);
+    std::fs::write(out, image).unwrap();
+}
 
 fn main() {
     // set by cargo, build scripts should use this directory for output files
@@ -7,9 +54,12 @@ fn main() {
     // https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies
     let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_kernel").unwrap());
 
+    let symbolized = out_dir.join("kernel");
+    embed_symbols(&kernel, &symbolized);
+
     // create a BIOS disk image
     let bios_path = out_dir.join("bios.img");
-    bootloader::BiosBoot::new(&kernel)
+    bootloader::BiosBoot::new(&symbolized)
         .create_disk_image(&bios_path)
         .unwrap();
 
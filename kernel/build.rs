@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=msrs.def");
+
+    let source = std::fs::read_to_string("msrs.def").unwrap();
+    let db = match msrdb::parse(&source) {
+        Ok(db) => db,
+        Err(e) => panic!("msrs.def: {}", e),
+    };
+    let problems = db.check();
+    if !problems.is_empty() {
+        panic!("msrs.def:\n{}", problems.join("\n"));
+    }
+
+    // set by cargo, build scripts should use this directory for output files
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("msrdb.rs"), db.to_rust()).unwrap();
+}
@@ -0,0 +1,154 @@
+//! Live kernel heap statistics
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+
+use crate::memory::{self, HEAP_MAX_SIZE};
+use crate::pane::{self, ScrollHints, Scrollable, size_str};
+
+const BAR_WIDTH: usize = 50;
+const FIELD_WIDTH: usize = 22;
+
+/// Bytes with their share of `total`
+fn share(bytes: usize, total: usize) -> String {
+    let percent = (bytes * 100).checked_div(total).unwrap_or(0);
+    format!("{} ({}%)", size_str(bytes as u64), percent)
+}
+
+fn usage_bar(used: usize, total: usize) -> Line<'static> {
+    let filled = (used * BAR_WIDTH)
+        .checked_div(total)
+        .unwrap_or(0)
+        .min(BAR_WIDTH);
+    Line::from(vec![
+        Span::raw("  ["),
+        Span::styled("#".repeat(filled), Style::default().fg(Color::Yellow)),
+        Span::styled(
+            "-".repeat(BAR_WIDTH - filled),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::raw("]"),
+    ])
+}
+
+pub struct AllocatorPane {
+    scroll: ScrollHints,
+}
+
+impl AllocatorPane {
+    pub fn new() -> Self {
+        Self {
+            scroll: ScrollHints::default(),
+        }
+    }
+}
+
+impl Scrollable for AllocatorPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut AllocatorPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // taken before any line is allocated so they do not show up in it
+        let stats = memory::heap_stats();
+        let (free_frames, total_frames) = memory::frames().totals();
+
+        let mut lines = Vec::new();
+        lines.push(Line::styled("Heap", Style::default().bold()));
+        lines.push(usage_bar(stats.used, stats.size));
+        lines.push(pane::field_line(
+            "Region",
+            format!("0x{:x}-0x{:x}", stats.bottom, stats.bottom + stats.size),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Size",
+            format!(
+                "{} of {} reserved",
+                size_str(stats.size as u64),
+                size_str(HEAP_MAX_SIZE as u64)
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Used",
+            share(stats.used, stats.size),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Free",
+            share(stats.free, stats.size),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Largest free block",
+            size_str(stats.largest_free as u64),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "High-water mark",
+            share(stats.high_water, stats.size),
+            FIELD_WIDTH,
+        ));
+        lines.push(Line::raw(""));
+
+        lines.push(Line::styled("Counters", Style::default().bold()));
+        lines.push(pane::field_line(
+            "Allocations",
+            format!("{}", stats.allocations),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Deallocations",
+            format!("{}", stats.deallocations),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Live",
+            format!("{}", stats.allocations - stats.deallocations),
+            FIELD_WIDTH,
+        ));
+        let failures_style = if stats.failures > 0 {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        };
+        lines.push(
+            pane::field_line("Failed", format!("{}", stats.failures), FIELD_WIDTH)
+                .style(failures_style),
+        );
+        lines.push(pane::field_line(
+            "Growths",
+            format!("{}", stats.growths),
+            FIELD_WIDTH,
+        ));
+        lines.push(Line::raw(""));
+
+        lines.push(Line::styled("Physical frames", Style::default().bold()));
+        lines.push(pane::field_line(
+            "Free",
+            format!(
+                "{} of {}",
+                size_str(free_frames as u64 * 4096),
+                size_str(total_frames as u64 * 4096)
+            ),
+            FIELD_WIDTH,
+        ));
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
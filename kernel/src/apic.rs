@@ -0,0 +1,341 @@
+//! Local APIC registers: identification, priorities, the in-service,
+//! request and trigger mode bitmaps, and the local vector table
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+
+use crate::cpuid;
+use crate::interrupts;
+use crate::lapic::{
+    self, ApicMode, REG_APR, REG_ESR, REG_ID, REG_IRR, REG_ISR, REG_LVT_CMCI, REG_LVT_ERROR,
+    REG_LVT_LINT0, REG_LVT_LINT1, REG_LVT_PMC, REG_LVT_THERMAL, REG_LVT_TIMER, REG_PPR, REG_SVR,
+    REG_TMR, REG_TPR, REG_VERSION,
+};
+use crate::pane::{self, PromptResult, Promptable, ScrollHints, Scrollable};
+
+/// LVT entries with their register and the Max LVT Entry value from which
+/// they exist
+const LVT: [(&str, u16, u32); 7] = [
+    ("Timer", REG_LVT_TIMER, 0),
+    ("Thermal", REG_LVT_THERMAL, 5),
+    ("PMC", REG_LVT_PMC, 4),
+    ("LINT0", REG_LVT_LINT0, 0),
+    ("LINT1", REG_LVT_LINT1, 0),
+    ("Error", REG_LVT_ERROR, 0),
+    ("CMCI", REG_LVT_CMCI, 6),
+];
+
+/// Delivery modes of bits 10:8 of LVT entries and the ICR
+const DELIVERY_MODES: [&str; 8] = [
+    "Fixed", "Lowest", "SMI", "Reserved", "NMI", "INIT", "Start-up", "ExtINT",
+];
+
+const TIMER_MODES: [&str; 4] = ["one-shot", "periodic", "TSC-deadline", "reserved"];
+
+const SHORTHANDS: [&str; 4] = ["none", "self", "all", "all but self"];
+
+const FIELD_WIDTH: usize = 18;
+
+fn bit(value: u64, bit: u32) -> bool {
+    value & (1 << bit) != 0
+}
+
+fn delivery_mode(value: u32) -> &'static str {
+    DELIVERY_MODES[(value >> 8) as usize & 0x7]
+}
+
+fn priority(offset: u16) -> String {
+    match lapic::read_register(offset) {
+        Some(value) => format!(
+            "0x{:02x} (class {}, subclass {})",
+            value & 0xFF,
+            (value >> 4) & 0xF,
+            value & 0xF
+        ),
+        None => String::from("not available in this mode"),
+    }
+}
+
+fn lvt_line(name: &str, offset: u16, max_lvt: u32, min_lvt: u32) -> Line<'static> {
+    let value = if max_lvt >= min_lvt {
+        lapic::read_register(offset)
+    } else {
+        None
+    };
+    let Some(value) = value else {
+        return Line::styled(
+            format!("  {:<8}not present", name),
+            Style::default().fg(Color::DarkGray),
+        );
+    };
+
+    let value64 = value as u64;
+    let masked = bit(value64, 16);
+    let lint = offset == REG_LVT_LINT0 || offset == REG_LVT_LINT1;
+    // only the LINT pins have a trigger mode and polarity
+    let trigger = if lint {
+        format!(
+            "{}/{}",
+            if bit(value64, 15) { "level" } else { "edge" },
+            if bit(value64, 13) { "low" } else { "high" }
+        )
+    } else {
+        String::from("edge")
+    };
+    let mut status = String::from(if bit(value64, 12) { "pending" } else { "idle" });
+    if offset == REG_LVT_TIMER {
+        status += ", ";
+        status += TIMER_MODES[(value >> 17) as usize & 0x3];
+    }
+    if lint && bit(value64, 14) {
+        status += ", remote IRR";
+    }
+
+    let line = Line::raw(format!(
+        "  {:<8}0x{:08x}  0x{:02x}   {:<8}{:<7}{:<11}{}",
+        name,
+        value,
+        value & 0xFF,
+        delivery_mode(value),
+        if masked { "yes" } else { "no" },
+        trigger,
+        status
+    ));
+    if masked {
+        line.style(Style::default().fg(Color::DarkGray))
+    } else {
+        line
+    }
+}
+
+/// ICR destination: 32 bits in x2APIC mode, the top byte in xAPIC mode
+fn destination(icr: u64) -> u64 {
+    match lapic::mode() {
+        ApicMode::XApic => icr >> 56,
+        ApicMode::X2Apic => icr >> 32,
+    }
+}
+
+/// The APIC ID register holds the full 32-bit x2APIC ID, or the 8-bit
+/// xAPIC ID in its top byte
+fn apic_id(id: u32) -> u32 {
+    match lapic::mode() {
+        ApicMode::XApic => id >> 24,
+        ApicMode::X2Apic => id,
+    }
+}
+
+fn icr_lines(lines: &mut Vec<Line<'static>>) {
+    let Some(icr) = lapic::read_icr() else {
+        lines.push(pane::field_line(
+            "ICR",
+            String::from("unreadable"),
+            FIELD_WIDTH,
+        ));
+        return;
+    };
+    lines.push(pane::field_line(
+        "ICR",
+        format!("0x{:016x}", icr),
+        FIELD_WIDTH,
+    ));
+    lines.push(pane::field_line(
+        "",
+        format!(
+            "vector 0x{:02x}, {}, {}, {}, {} triggered",
+            icr & 0xFF,
+            delivery_mode(icr as u32),
+            if bit(icr, 11) { "logical" } else { "physical" },
+            if bit(icr, 14) { "assert" } else { "de-assert" },
+            if bit(icr, 15) { "level" } else { "edge" },
+        ),
+        FIELD_WIDTH,
+    ));
+    lines.push(pane::field_line(
+        "",
+        format!(
+            "shorthand {}, destination 0x{:x}",
+            SHORTHANDS[(icr >> 18) as usize & 0x3],
+            destination(icr)
+        ),
+        FIELD_WIDTH,
+    ));
+}
+
+/// ISR, IRR and TMR side by side, one row per 16 vectors
+fn bitmap_lines(lines: &mut Vec<Line<'static>>) {
+    let bitmaps = [REG_ISR, REG_IRR, REG_TMR].map(lapic::read_bitmap);
+    lines.push(Line::raw(format!(
+        "  {:<6}{:<19}{:<19}{}",
+        "", "ISR", "IRR", "TMR"
+    )));
+    for row in 0..16 {
+        let mut spans = vec![Span::raw(format!("  0x{:02x}  ", row * 16))];
+        for bitmap in &bitmaps {
+            let mut cells = String::new();
+            for column in 0..16 {
+                let vector = row * 16 + column;
+                let set =
+                    bitmap.is_some_and(|words| words[vector / 32] & (1 << (vector % 32)) != 0);
+                cells.push(if set { '●' } else { '·' });
+            }
+            let style = if bitmap.is_some() {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            spans.push(Span::styled(cells, style));
+            spans.push(Span::raw("   "));
+        }
+        lines.push(Line::from(spans));
+    }
+}
+
+pub struct ApicPane {
+    scroll: ScrollHints,
+}
+
+impl ApicPane {
+    pub fn new() -> Self {
+        Self {
+            scroll: ScrollHints::default(),
+        }
+    }
+}
+
+impl Promptable for ApicPane {
+    fn prompt_label(&self) -> String {
+        "mode (xapic/x2apic) ".into()
+    }
+
+    fn submit_prompt(&mut self, input: &str) -> PromptResult {
+        let mode = match input.trim().to_ascii_lowercase().as_str() {
+            "xapic" => ApicMode::XApic,
+            "x2apic" => ApicMode::X2Apic,
+            _ => return Err(format!("unknown mode: {}", input)),
+        };
+        lapic::set_mode(mode)?;
+        Ok(format!("LAPIC in {} mode", mode.name()))
+    }
+}
+
+impl Scrollable for ApicPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut ApicPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = Vec::new();
+
+        let base = lapic::apic_base();
+        let mode = match (bit(base, 11), bit(base, 10)) {
+            (false, _) => "disabled",
+            (true, false) => "xAPIC",
+            (true, true) => "x2APIC",
+        };
+        lines.push(pane::section("Local APIC"));
+        lines.push(pane::field_line(
+            "Access",
+            format!(
+                "{}, x2APIC supported: {}",
+                match lapic::mode() {
+                    ApicMode::XApic => "xAPIC (MMIO)",
+                    ApicMode::X2Apic => "x2APIC (MSR)",
+                },
+                if cpuid::has_x2apic() { "yes" } else { "no" }
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "IA32_APIC_BASE",
+            format!(
+                "0x{:x}: {} mode, base 0x{:x}{}",
+                base,
+                mode,
+                lapic::base_address(),
+                if bit(base, 8) { ", BSP" } else { "" }
+            ),
+            FIELD_WIDTH,
+        ));
+        let id = apic_id(lapic::read_register(REG_ID).unwrap_or(0));
+        lines.push(pane::field_line("ID", format!("0x{:x}", id), FIELD_WIDTH));
+        let version = lapic::read_register(REG_VERSION).unwrap_or(0);
+        let max_lvt = (version >> 16) & 0xFF;
+        lines.push(pane::field_line(
+            "Version",
+            format!(
+                "0x{:02x}, max LVT entry {}, EOI broadcast suppression: {}",
+                version & 0xFF,
+                max_lvt,
+                if bit(version as u64, 24) { "yes" } else { "no" }
+            ),
+            FIELD_WIDTH,
+        ));
+        let svr = lapic::read_register(REG_SVR).unwrap_or(0) as u64;
+        lines.push(pane::field_line(
+            "SVR",
+            format!("0x{:08x}, spurious vector 0x{:02x}", svr, svr & 0xFF),
+            FIELD_WIDTH,
+        ));
+        let mut flags = vec![if bit(svr, 8) {
+            "software enabled"
+        } else {
+            "software disabled"
+        }];
+        if bit(svr, 9) {
+            flags.push("no focus checking");
+        }
+        if bit(svr, 12) {
+            flags.push("EOI broadcast suppressed");
+        }
+        lines.push(pane::field_line("", flags.join(", "), FIELD_WIDTH));
+        lines.push(pane::field_line("TPR", priority(REG_TPR), FIELD_WIDTH));
+        lines.push(pane::field_line("PPR", priority(REG_PPR), FIELD_WIDTH));
+        lines.push(pane::field_line("APR", priority(REG_APR), FIELD_WIDTH));
+        let esr = lapic::read_register(REG_ESR).unwrap_or(0) as u8;
+        lines.push(pane::field_line(
+            "ESR",
+            format!("0x{:02x} ({})", esr, lapic::error_names(esr)),
+            FIELD_WIDTH,
+        ));
+        let latched = interrupts::lapic_errors();
+        lines.push(pane::field_line(
+            "Errors since boot",
+            format!("0x{:02x} ({})", latched, lapic::error_names(latched)),
+            FIELD_WIDTH,
+        ));
+        icr_lines(&mut lines);
+        lines.push(Line::raw(""));
+
+        lines.push(pane::section("Local Vector Table"));
+        lines.push(Line::raw(format!(
+            "  {:<8}{:<12}{:<7}{:<8}{:<7}{:<11}{}",
+            "Entry", "Raw", "Vector", "Delivery", "Masked", "Trigger", "Status"
+        )));
+        for (name, offset, min_lvt) in LVT {
+            lines.push(lvt_line(name, offset, max_lvt, min_lvt));
+        }
+        lines.push(Line::raw(""));
+
+        lines.push(pane::section(
+            "In-service, request and trigger mode registers",
+        ));
+        bitmap_lines(&mut lines);
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
@@ -1,23 +1,38 @@
 use alloc::format;
 use alloc::string::String;
 use alloc::vec;
+use alloc::vec::Vec;
 use core::borrow::BorrowMut;
 use core::sync::atomic::Ordering;
 use x86_64::instructions::{self, interrupts::without_interrupts};
 
+use crate::allocator::AllocatorPane;
+use crate::apic::ApicPane;
+use crate::boot::BootPane;
 use crate::cpuid::CpuidPane;
+use crate::descriptors::DescriptorPane;
 use crate::fpu::FpuState;
+use crate::hex::HexPane;
 use crate::input::{Input, InputEvent};
 use crate::interrupts;
+use crate::irq::IrqPane;
+use crate::memory::Mappings;
 #[cfg(feature = "msr")]
 use crate::msr::MsrPane;
-use crate::pane::{MIN_SEARCH_LEN, ScrollDirection, Scrollable, Searchable};
+use crate::paging::PagingPane;
+use crate::pane::{
+    MIN_SEARCH_LEN, PromptResult, Promptable, ScrollDirection, Scrollable, Searchable,
+};
+use crate::pkru::{PkruPane, Protection};
 use crate::qemu::{self, QemuExitCode};
 use crate::ratatui_backend::SerialAnsiBackend;
 use crate::serial::{self, SerialPort};
+use crate::system::SystemPane;
 use crate::timer::TimerState;
+use crate::vuln::VulnPane;
 use crate::xsave::XsaveState;
 
+use bootloader_api::BootInfo;
 use ratatui::Terminal;
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
@@ -33,6 +48,68 @@ pub enum Pane {
     Timer,
     #[cfg(feature = "msr")]
     Msr,
+    Pkru,
+    Vuln,
+    System,
+    Descriptors,
+    Paging,
+    Hex,
+    Boot,
+    Allocator,
+    Irq,
+    Apic,
+}
+
+impl Pane {
+    /// Label in the caption, the bracketed letter selects the pane
+    fn caption(self) -> &'static str {
+        match self {
+            Pane::Cpuid => "[c]puid",
+            Pane::Fpu => "[f]pu",
+            Pane::Xsave => "[x]save",
+            Pane::Timer => "[t]imer",
+            #[cfg(feature = "msr")]
+            Pane::Msr => "[m]sr",
+            Pane::Pkru => "pk[u]",
+            Pane::Vuln => "[v]ulns",
+            Pane::System => "[s]ystem",
+            Pane::Descriptors => "[d]escriptors",
+            Pane::Paging => "[p]aging",
+            Pane::Hex => "[h]ex",
+            Pane::Boot => "[b]oot",
+            Pane::Allocator => "[a]lloc",
+            Pane::Irq => "[i]rq",
+            Pane::Apic => "[l]apic",
+        }
+    }
+}
+
+/// Panes in caption order
+fn caption_panes() -> Vec<Pane> {
+    let mut panes = vec![Pane::Cpuid, Pane::Fpu, Pane::Xsave, Pane::Timer];
+    #[cfg(feature = "msr")]
+    panes.push(Pane::Msr);
+    panes.extend([
+        Pane::Pkru,
+        Pane::Vuln,
+        Pane::System,
+        Pane::Descriptors,
+        Pane::Paging,
+        Pane::Hex,
+        Pane::Boot,
+        Pane::Allocator,
+        Pane::Irq,
+        Pane::Apic,
+    ]);
+    panes
+}
+
+/// Bracketed key of a caption label
+fn caption_key(label: &str) -> &str {
+    match (label.find('['), label.find(']')) {
+        (Some(start), Some(end)) => &label[start..=end],
+        _ => label,
+    }
 }
 
 #[derive(Default, PartialEq, Clone, Copy)]
@@ -41,6 +118,7 @@ pub enum Mode {
     Navigation,
     Search,
     SearchResults,
+    Prompt,
 }
 
 pub struct App {
@@ -51,12 +129,24 @@ pub struct App {
     timer_state: TimerState,
     #[cfg(feature = "msr")]
     msr_pane: MsrPane,
+    pkru_pane: PkruPane,
+    vuln_pane: VulnPane,
+    system_pane: SystemPane,
+    descriptor_pane: DescriptorPane,
+    paging_pane: PagingPane,
+    hex_pane: HexPane,
+    boot_pane: BootPane,
+    allocator_pane: AllocatorPane,
+    irq_pane: IrqPane,
+    apic_pane: ApicPane,
     mode: Mode,
     search_buffer: String,
+    prompt_buffer: String,
+    prompt_result: Option<PromptResult>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(boot_info: &'static BootInfo, mappings: &Mappings, protection: Protection) -> Self {
         let cpuid_pane = CpuidPane::new();
 
         let timer_state = TimerState::new(
@@ -68,7 +158,10 @@ impl App {
         let msr_pane = MsrPane::new(cpuid_pane.state().cpu_features());
 
         let fpu_state = FpuState::new(cpuid_pane.state());
+        let pkru_pane = PkruPane::new(protection);
         let xsave_state = XsaveState::new(cpuid_pane.state());
+        let vuln_pane = VulnPane::new(cpuid_pane.state());
+        let paging_pane = PagingPane::new(cpuid_pane.state());
 
         Self {
             pane: Pane::Cpuid,
@@ -78,8 +171,20 @@ impl App {
             timer_state,
             #[cfg(feature = "msr")]
             msr_pane,
+            pkru_pane,
+            vuln_pane,
+            system_pane: SystemPane::new(),
+            descriptor_pane: DescriptorPane::new(),
+            paging_pane,
+            hex_pane: HexPane::new(),
+            boot_pane: BootPane::new(boot_info, mappings),
+            allocator_pane: AllocatorPane::new(),
+            irq_pane: IrqPane::new(),
+            apic_pane: ApicPane::new(),
             mode: Mode::default(),
             search_buffer: String::new(),
+            prompt_buffer: String::new(),
+            prompt_result: None,
         }
     }
 
@@ -101,6 +206,16 @@ impl App {
             Pane::Fpu => self.fpu_state.scroll(direction),
             #[cfg(feature = "msr")]
             Pane::Msr => self.msr_pane.scroll(direction),
+            Pane::Pkru => self.pkru_pane.scroll(direction),
+            Pane::Vuln => self.vuln_pane.scroll(direction),
+            Pane::System => self.system_pane.scroll(direction),
+            Pane::Descriptors => self.descriptor_pane.scroll(direction),
+            Pane::Paging => self.paging_pane.scroll(direction),
+            Pane::Hex => self.hex_pane.scroll(direction),
+            Pane::Boot => self.boot_pane.scroll(direction),
+            Pane::Allocator => self.allocator_pane.scroll(direction),
+            Pane::Irq => self.irq_pane.scroll(direction),
+            Pane::Apic => self.apic_pane.scroll(direction),
             _ => {}
         }
     }
@@ -113,9 +228,96 @@ impl App {
             Pane::Timer => "Timer",
             #[cfg(feature = "msr")]
             Pane::Msr => "MSR",
+            Pane::Pkru => "PKRU",
+            Pane::Vuln => "Vulnerabilities",
+            Pane::System => "System",
+            Pane::Descriptors => "Descriptor Tables",
+            Pane::Paging => "Paging",
+            Pane::Hex => "Hex",
+            Pane::Boot => "Boot Info",
+            Pane::Allocator => "Allocator",
+            Pane::Irq => "Interrupts",
+            Pane::Apic => "Local APIC",
+        }
+    }
+
+    /// Prompt of `pane`, None if it does not accept commands. The one list
+    /// of panes with a prompt.
+    fn prompt_of(pane: Pane) -> Option<fn(&mut App) -> &mut dyn Promptable> {
+        match pane {
+            #[cfg(feature = "msr")]
+            Pane::Msr => Some(|app| &mut app.msr_pane),
+            Pane::Pkru => Some(|app| &mut app.pkru_pane),
+            Pane::Paging => Some(|app| &mut app.paging_pane),
+            Pane::Hex => Some(|app| &mut app.hex_pane),
+            Pane::Apic => Some(|app| &mut app.apic_pane),
+            _ => None,
+        }
+    }
+
+    /// Current pane's prompt, None if it does not accept commands
+    fn promptable(&mut self) -> Option<&mut dyn Promptable> {
+        App::prompt_of(self.pane).map(|prompt| prompt(self))
+    }
+
+    pub fn has_prompt(&self) -> bool {
+        App::prompt_of(self.pane).is_some()
+    }
+
+    fn prompt_label(&mut self) -> String {
+        self.promptable()
+            .map_or_else(String::new, |p| p.prompt_label())
+    }
+
+    fn submit_prompt(&mut self) {
+        let input = core::mem::take(&mut self.prompt_buffer);
+        let Some(promptable) = self.promptable() else {
+            return;
+        };
+        let result = promptable.submit_prompt(&input);
+
+        // a follow-up question keeps the prompt open
+        if promptable.prompt_pending() {
+            self.mode = Mode::Prompt;
+        } else {
+            self.prompt_result = Some(result);
         }
     }
 
+    fn cancel_prompt(&mut self) {
+        if let Some(promptable) = self.promptable() {
+            promptable.cancel_prompt();
+        }
+    }
+
+    /// Pane keys for the bottom bar. Labels are spelled out if they all fit,
+    /// otherwise only the current pane's is and the others show their key.
+    fn caption_line(&self, width: u16) -> Line<'static> {
+        let panes = caption_panes();
+        let full: Vec<&str> = panes
+            .iter()
+            .map(|pane| pane.caption())
+            .chain(["[q]uit"])
+            .collect();
+        let full = full.join(" ");
+        if full.len() <= width as usize {
+            return Line::raw(full);
+        }
+
+        let mut spans = Vec::new();
+        for pane in panes {
+            let label = pane.caption();
+            if pane == self.pane {
+                spans.push(Span::styled(label, Style::default().bold()));
+            } else {
+                spans.push(Span::raw(caption_key(label)));
+            }
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::raw("[q]uit"));
+        Line::from(spans)
+    }
+
     fn handle_input(&mut self, input: &mut Input) -> Option<InputEvent> {
         let mut event = None;
 
@@ -137,6 +339,13 @@ impl App {
 
     fn handle_ticks(&mut self) -> bool {
         let second_events = interrupts::SECOND_EVENTS.swap(0, Ordering::AcqRel);
+        if second_events > 0 {
+            self.irq_pane.refresh(second_events);
+        }
+        #[cfg(feature = "msr")]
+        if second_events > 0 {
+            self.msr_pane.refresh(second_events);
+        }
         second_events > 0
     }
 
@@ -224,9 +433,17 @@ impl App {
             self.timer_state.refresh();
             let mut needs_redraw = self.handle_ticks();
 
+            /* background work, progress shows up with the per-second redraw */
+            #[cfg(feature = "msr")]
+            if self.msr_pane.scan_step() {
+                needs_redraw = true;
+            }
+
             /* react to input */
             let event = self.handle_input(&mut input);
             if let Some(event) = event {
+                // a prompt result stays visible until the next key press
+                self.prompt_result = None;
                 match event {
                     InputEvent::Quit => qemu::exit(QemuExitCode::Success),
                     InputEvent::ScrollToTop => self.scroll(ScrollDirection::Top),
@@ -259,6 +476,34 @@ impl App {
                     }
                     InputEvent::NextMatch => self.next_match(),
                     InputEvent::PrevMatch => self.prev_match(),
+                    InputEvent::EnterPromptMode => {
+                        self.mode = Mode::Prompt;
+                        self.prompt_buffer.clear();
+                    }
+                    InputEvent::ConfirmPrompt => {
+                        self.mode = Mode::Navigation;
+                        self.submit_prompt();
+                    }
+                    InputEvent::ExitPromptMode => {
+                        self.mode = Mode::Navigation;
+                        self.cancel_prompt();
+                    }
+                    InputEvent::PromptInput(b) => {
+                        // Limit prompt length to what fits next to the label
+                        let max_len = 78 - self.prompt_label().len();
+                        if self.prompt_buffer.len() < max_len {
+                            self.prompt_buffer.push(b as char);
+                        }
+                    }
+                    InputEvent::PromptBackspace => {
+                        self.prompt_buffer.pop();
+                    }
+                    #[cfg(feature = "msr")]
+                    InputEvent::ToggleExpand => self.msr_pane.toggle_expanded(),
+                    #[cfg(feature = "msr")]
+                    InputEvent::ToggleView => self.msr_pane.toggle_view(),
+                    InputEvent::Descend => self.paging_pane.descend(),
+                    InputEvent::Ascend => self.paging_pane.ascend(),
                     InputEvent::ClearScreen => {
                         terminal.clear().unwrap();
                     }
@@ -295,6 +540,16 @@ impl Widget for &mut App {
             Pane::Timer => (&self.timer_state).render(block_inner, buf),
             #[cfg(feature = "msr")]
             Pane::Msr => (&mut self.msr_pane).render(block_inner, buf),
+            Pane::Pkru => (&mut self.pkru_pane).render(block_inner, buf),
+            Pane::Vuln => (&mut self.vuln_pane).render(block_inner, buf),
+            Pane::System => (&mut self.system_pane).render(block_inner, buf),
+            Pane::Descriptors => (&mut self.descriptor_pane).render(block_inner, buf),
+            Pane::Paging => (&mut self.paging_pane).render(block_inner, buf),
+            Pane::Hex => (&mut self.hex_pane).render(block_inner, buf),
+            Pane::Boot => (&mut self.boot_pane).render(block_inner, buf),
+            Pane::Allocator => (&mut self.allocator_pane).render(block_inner, buf),
+            Pane::Irq => (&mut self.irq_pane).render(block_inner, buf),
+            Pane::Apic => (&mut self.apic_pane).render(block_inner, buf),
         }
 
         if self.mode == Mode::Search {
@@ -316,12 +571,22 @@ impl Widget for &mut App {
                 ),
             ]);
             search_line.render(bottom_bar, buf);
+        } else if self.mode == Mode::Prompt {
+            let prompt_line = Line::from(vec![
+                Span::styled(":", Style::default().bold()),
+                Span::styled(self.prompt_label(), Style::default().fg(Color::DarkGray)),
+                Span::raw(self.prompt_buffer.as_str()),
+                Span::styled("_", Style::default().fg(Color::Gray)), // cursor
+            ]);
+            prompt_line.render(bottom_bar, buf);
+        } else if let Some(result) = &self.prompt_result {
+            let result_line = match result {
+                Ok(msg) => Line::raw(msg.as_str()),
+                Err(msg) => Line::styled(msg.as_str(), Style::default().fg(Color::Red)),
+            };
+            result_line.render(bottom_bar, buf);
         } else {
-            #[cfg(feature = "msr")]
-            let caption = "CPUID (c) | FPU (f) | XSAVE (x) | Timer (t) | MSR (m) | Quit (q)";
-            #[cfg(not(feature = "msr"))]
-            let caption = "CPUID (c) | FPU (f) | XSAVE (x) | Timer (t) | Quit (q)";
-            caption.render(bottom_bar, buf);
+            self.caption_line(bottom_bar.width).render(bottom_bar, buf);
         }
     }
 }
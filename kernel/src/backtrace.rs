@@ -0,0 +1,95 @@
+//! Stack walking along the frame pointer chain
+//!
+//! The kernel is built with frame pointers (see `.cargo/config.toml`), so
+//! every frame starts with the caller's RBP followed by the return address.
+//! The chain is read with guarded loads, a corrupt stack ends the walk
+//! instead of faulting again.
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+
+use crate::{recovery, symbols};
+
+/// Deepest backtrace recorded
+const MAX_FRAMES: usize = 32;
+
+pub struct Backtrace {
+    addresses: heapless::Vec<u64, MAX_FRAMES>,
+    /// The first address is the faulting instruction rather than a return
+    /// address
+    exact_first: bool,
+}
+
+/// Frames as (RBP, return address), from the frame at `rbp` outwards
+fn frames(mut rbp: u64) -> impl Iterator<Item = (u64, u64)> {
+    core::iter::from_fn(move || {
+        if rbp == 0 || rbp % 8 != 0 {
+            return None;
+        }
+        let next = recovery::read_u64(rbp as *const u64).ok()?;
+        let ret = recovery::read_u64((rbp + 8) as *const u64).ok()?;
+        if ret == 0 {
+            return None;
+        }
+        let frame = (rbp, ret);
+        // callers' frames are further up the stack, anything else is a loop
+        // or garbage
+        rbp = if next > rbp { next } else { 0 };
+        Some(frame)
+    })
+    .take(MAX_FRAMES)
+}
+
+fn read_rbp() -> u64 {
+    let rbp: u64;
+    unsafe {
+        asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+    }
+    rbp
+}
+
+impl Backtrace {
+    /// Backtrace of the caller
+    #[inline(never)]
+    pub fn capture() -> Self {
+        Self {
+            addresses: frames(read_rbp()).map(|(_, ret)| ret).collect(),
+            exact_first: false,
+        }
+    }
+
+    /// Backtrace of the code an exception interrupted at `rip`, walking
+    /// the frames from the RBP the entry stub saved
+    pub fn interrupted(rip: u64, rbp: u64) -> Self {
+        let mut addresses = heapless::Vec::new();
+        let _ = addresses.push(rip);
+        for (_, ret) in frames(rbp) {
+            if addresses.push(ret).is_err() {
+                break;
+            }
+        }
+
+        Self {
+            addresses,
+            exact_first: true,
+        }
+    }
+
+    /// One line per frame with the function it is in. Does not allocate.
+    pub fn write(&self, out: &mut impl Write) -> fmt::Result {
+        for (i, &address) in self.addresses.iter().enumerate() {
+            write!(out, "  #{:<2} 0x{:016x}", i, address)?;
+            // a return address can be just past the end of a call to a
+            // function that does not return, look up the call instead
+            let exact = i == 0 && self.exact_first;
+            let site = if exact { address } else { address - 1 };
+            match symbols::lookup(site) {
+                Some((name, offset)) => {
+                    writeln!(out, "  {}+0x{:x}", name, offset + address - site)?
+                }
+                None => writeln!(out, "  ??")?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+//! What the bootloader handed over: the memory map, boot info fields and
+//! where the kernel placed its own heap, MMIO mappings and stack
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::asm;
+
+use bootloader_api::BootInfo;
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind, Optional, PixelFormat};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Widget};
+use x86_64::VirtAddr;
+
+use crate::ioapic::IOAPIC_PHYS;
+use crate::lapic;
+use crate::memory::{self, HEAP_MAX_SIZE, HEAP_START, Mappings, PageTableManager};
+use crate::pane::{self, ScrollHints, Scrollable, size_str};
+
+const PAGE_SIZE: u64 = 4096;
+const FIELD_WIDTH: usize = 24;
+/// Pages probed on each side of RSP when looking for the stack's guard pages
+const MAX_STACK_PAGES: u64 = 1024;
+
+/// Names of the UEFI memory types, indexed by type
+const UEFI_TYPES: &[&str] = &[
+    "Reserved",
+    "LoaderCode",
+    "LoaderData",
+    "BootServicesCode",
+    "BootServicesData",
+    "RuntimeServicesCode",
+    "RuntimeServicesData",
+    "Conventional",
+    "Unusable",
+    "ACPIReclaim",
+    "ACPINVS",
+    "MMIO",
+    "MMIOPortSpace",
+    "PalCode",
+    "Persistent",
+    "Unaccepted",
+];
+
+fn kind_str(kind: MemoryRegionKind) -> String {
+    match kind {
+        MemoryRegionKind::Usable => String::from("Usable"),
+        MemoryRegionKind::Bootloader => String::from("Bootloader"),
+        MemoryRegionKind::UnknownBios(e820) => match e820 {
+            2 => String::from("Reserved"),
+            3 => String::from("ACPI reclaimable"),
+            4 => String::from("ACPI NVS"),
+            5 => String::from("Bad memory"),
+            7 => String::from("Persistent"),
+            _ => format!("E820 type {}", e820),
+        },
+        MemoryRegionKind::UnknownUefi(uefi) => match UEFI_TYPES.get(uefi as usize) {
+            Some(name) => format!("UEFI {}", name),
+            None => format!("UEFI type 0x{:x}", uefi),
+        },
+        _ => String::from("Unknown"),
+    }
+}
+
+fn optional_addr(value: Optional<u64>) -> String {
+    value
+        .into_option()
+        .map_or_else(|| String::from("none"), |addr| format!("0x{:x}", addr))
+}
+
+fn read_rsp() -> u64 {
+    let rsp: u64;
+    unsafe {
+        asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+    rsp
+}
+
+/// Mapped range around `addr`, bounded by unmapped pages such as the guard
+/// page below the stack
+fn mapped_extent(tables: &PageTableManager, addr: u64) -> (u64, u64) {
+    let mapped = |page: u64| {
+        VirtAddr::try_new(page)
+            .ok()
+            .and_then(|page| tables.walk(page).1)
+            .is_some()
+    };
+    let page = addr & !(PAGE_SIZE - 1);
+    let mut start = page;
+    while page - start < MAX_STACK_PAGES * PAGE_SIZE && mapped(start - PAGE_SIZE) {
+        start -= PAGE_SIZE;
+    }
+    let mut end = page + PAGE_SIZE;
+    while end - page < MAX_STACK_PAGES * PAGE_SIZE && mapped(end) {
+        end += PAGE_SIZE;
+    }
+    (start, end)
+}
+
+pub struct BootPane {
+    boot_info: &'static BootInfo,
+    ioapic_base: VirtAddr,
+    lapic_base: VirtAddr,
+    scroll: ScrollHints,
+}
+
+impl BootPane {
+    pub fn new(boot_info: &'static BootInfo, mappings: &Mappings) -> Self {
+        Self {
+            boot_info,
+            ioapic_base: mappings.ioapic_base(),
+            lapic_base: mappings.lapic_base(),
+            scroll: ScrollHints::default(),
+        }
+    }
+
+    fn memory_map_lines(&self) -> Vec<Line<'static>> {
+        let mut regions: Vec<&MemoryRegion> = self.boot_info.memory_regions.iter().collect();
+        regions.sort_by_key(|region| region.start);
+
+        let mut lines = Vec::new();
+        lines.push(pane::section("Memory map"));
+        lines.push(Line::styled(
+            format!("  {:<20}{:<20}{:>10}  {}", "Start", "End", "Size", "Kind"),
+            Style::default().fg(Color::DarkGray),
+        ));
+
+        // totals per kind, in order of first appearance
+        let mut totals: Vec<(String, u64)> = Vec::new();
+        for region in regions {
+            let kind = kind_str(region.kind);
+            let size = region.end - region.start;
+            let color = match region.kind {
+                MemoryRegionKind::Usable => Color::Green,
+                MemoryRegionKind::Bootloader => Color::Yellow,
+                _ => Color::Reset,
+            };
+            lines.push(Line::styled(
+                format!(
+                    "  {:<20}{:<20}{:>10}  {}",
+                    format!("0x{:x}", region.start),
+                    format!("0x{:x}", region.end),
+                    size_str(size),
+                    kind
+                ),
+                Style::default().fg(color),
+            ));
+            match totals.iter_mut().find(|(name, _)| *name == kind) {
+                Some((_, total)) => *total += size,
+                None => totals.push((kind, size)),
+            }
+        }
+
+        lines.push(Line::raw(""));
+        lines.push(pane::section("Totals"));
+        let all: u64 = totals.iter().map(|(_, size)| size).sum();
+        for (kind, size) in totals {
+            lines.push(pane::field_line(&kind, size_str(size), FIELD_WIDTH));
+        }
+        lines.push(pane::field_line("All regions", size_str(all), FIELD_WIDTH));
+        lines
+    }
+
+    /// Frames handed out by the allocator, per usable region
+    fn frame_lines(&self) -> Vec<Line<'static>> {
+        let frames = memory::frames();
+        let mut lines = Vec::new();
+        lines.push(pane::section("Frame allocator"));
+        lines.push(Line::styled(
+            format!(
+                "  {:<20}{:<20}{:>10}{:>10}{:>10}",
+                "Start", "End", "Frames", "Used", "Free"
+            ),
+            Style::default().fg(Color::DarkGray),
+        ));
+        for region in frames.regions() {
+            lines.push(Line::raw(format!(
+                "  {:<20}{:<20}{:>10}{:>10}{:>10}",
+                format!("0x{:x}", region.start.as_u64()),
+                format!("0x{:x}", region.end.as_u64()),
+                region.frames,
+                region.frames - region.free,
+                region.free
+            )));
+        }
+        let (free, total) = frames.totals();
+        lines.push(pane::field_line(
+            "Free",
+            format!(
+                "{} of {}",
+                size_str(free as u64 * PAGE_SIZE),
+                size_str(total as u64 * PAGE_SIZE)
+            ),
+            FIELD_WIDTH,
+        ));
+        let dropped = frames.dropped();
+        if dropped > 0 {
+            lines.push(Line::styled(
+                format!(
+                    "  {} of usable memory in regions past the last tracked one",
+                    size_str(dropped as u64 * PAGE_SIZE)
+                ),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        lines
+    }
+
+    fn boot_info_lines(&self) -> Vec<Line<'static>> {
+        let info = self.boot_info;
+        let version = &info.api_version;
+        let mut lines = Vec::new();
+        lines.push(pane::section("Boot info"));
+        lines.push(pane::field_line(
+            "API version",
+            format!(
+                "{}.{}.{}{}",
+                version.version_major(),
+                version.version_minor(),
+                version.version_patch(),
+                if version.pre_release() {
+                    " (pre-release)"
+                } else {
+                    ""
+                }
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Physical memory offset",
+            optional_addr(info.physical_memory_offset),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "Recursive index",
+            info.recursive_index
+                .into_option()
+                .map_or_else(|| String::from("none"), |index| format!("{}", index)),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "RSDP",
+            optional_addr(info.rsdp_addr),
+            FIELD_WIDTH,
+        ));
+
+        match info.framebuffer.as_ref() {
+            Some(framebuffer) => {
+                let fb = framebuffer.info();
+                let format = match fb.pixel_format {
+                    PixelFormat::Rgb => "RGB",
+                    PixelFormat::Bgr => "BGR",
+                    PixelFormat::U8 => "grayscale",
+                    _ => "unknown",
+                };
+                lines.push(pane::field_line(
+                    "Framebuffer",
+                    format!(
+                        "0x{:x}, {}",
+                        framebuffer.buffer().as_ptr() as u64,
+                        size_str(fb.byte_len as u64)
+                    ),
+                    FIELD_WIDTH,
+                ));
+                lines.push(pane::field_line(
+                    "",
+                    format!(
+                        "{}x{}, stride {}, {} bytes/pixel {}",
+                        fb.width, fb.height, fb.stride, fb.bytes_per_pixel, format
+                    ),
+                    FIELD_WIDTH,
+                ));
+            }
+            None => lines.push(pane::field_line(
+                "Framebuffer",
+                String::from("none"),
+                FIELD_WIDTH,
+            )),
+        }
+
+        match info.tls_template.as_ref() {
+            Some(tls) => lines.push(pane::field_line(
+                "TLS template",
+                format!(
+                    "0x{:x}, {} bytes in file, {} in memory",
+                    tls.start_addr, tls.file_size, tls.mem_size
+                ),
+                FIELD_WIDTH,
+            )),
+            None => lines.push(pane::field_line(
+                "TLS template",
+                String::from("none"),
+                FIELD_WIDTH,
+            )),
+        }
+
+        match info.ramdisk_addr.into_option() {
+            Some(addr) => lines.push(pane::field_line(
+                "Ramdisk",
+                format!("0x{:x}, {}", addr, size_str(info.ramdisk_len)),
+                FIELD_WIDTH,
+            )),
+            None => lines.push(pane::field_line(
+                "Ramdisk",
+                String::from("none"),
+                FIELD_WIDTH,
+            )),
+        }
+        lines.push(pane::field_line(
+            "Kernel image",
+            format!(
+                "phys 0x{:x}, {}, virt offset 0x{:x}",
+                info.kernel_addr,
+                size_str(info.kernel_len),
+                info.kernel_image_offset
+            ),
+            FIELD_WIDTH,
+        ));
+        lines
+    }
+
+    fn layout_lines(&self) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+        lines.push(pane::section("Kernel layout"));
+        let heap = HEAP_START as u64;
+        let (size, _, _) = memory::heap_usage().unwrap_or_default();
+        lines.push(pane::field_line(
+            "Heap",
+            format!(
+                "0x{:x}-0x{:x}, {} mapped",
+                heap,
+                heap + size as u64,
+                size_str(size as u64)
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "",
+            format!(
+                "grows up to 0x{:x}, {}",
+                heap + HEAP_MAX_SIZE as u64,
+                size_str(HEAP_MAX_SIZE as u64)
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "IOAPIC",
+            format!(
+                "0x{:x} -> phys 0x{:x}",
+                self.ioapic_base.as_u64(),
+                IOAPIC_PHYS
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "LAPIC",
+            format!(
+                "0x{:x} -> phys 0x{:x}",
+                self.lapic_base.as_u64(),
+                lapic::base_address()
+            ),
+            FIELD_WIDTH,
+        ));
+
+        let rsp = read_rsp();
+        let (start, end) = mapped_extent(&memory::page_tables(), rsp);
+        lines.push(pane::field_line(
+            "Stack",
+            format!(
+                "0x{:x}-0x{:x}, {} mapped",
+                start,
+                end,
+                size_str(end - start)
+            ),
+            FIELD_WIDTH,
+        ));
+        lines.push(pane::field_line(
+            "",
+            format!(
+                "RSP 0x{:x}, {} configured",
+                rsp,
+                size_str(crate::BOOTLOADER_CONFIG.kernel_stack_size)
+            ),
+            FIELD_WIDTH,
+        ));
+        lines
+    }
+}
+
+impl Scrollable for BootPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut BootPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = self.memory_map_lines();
+        lines.push(Line::raw(""));
+        lines.extend(self.frame_lines());
+        lines.push(Line::raw(""));
+        lines.extend(self.boot_info_lines());
+        lines.push(Line::raw(""));
+        lines.extend(self.layout_lines());
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
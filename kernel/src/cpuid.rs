@@ -1,7 +1,7 @@
 use alloc::format;
 use alloc::vec;
 use alloc::vec::Vec;
-use raw_cpuid::{CpuId, CpuIdReaderNative};
+use raw_cpuid::{CpuId, CpuIdReaderNative, Hypervisor};
 
 use ratatui::buffer::Buffer;
 use ratatui::layout::Rect;
@@ -34,6 +34,10 @@ impl CpuidState {
         self.features.extended_state_features()
     }
 
+    pub fn svm_info(&self) -> Option<&SvmInfo> {
+        self.features.svm_info()
+    }
+
     pub fn vendor_info(&self) -> &VendorInfo {
         self.features.vendor_info()
     }
@@ -87,9 +91,17 @@ pub struct CpuFeatures {
     features: Vec<(&'static str, bool)>,
     extended_features: Vec<(&'static str, bool)>,
     extended_state_features: ExtendedStateFeatures,
+    svm_info: Option<SvmInfo>,
     cpuid: CpuId<CpuIdReaderNative>,
 }
 
+/// SVM revision, ASID count and features from leaf 0x8000_000A
+pub struct SvmInfo {
+    pub revision: u8,
+    pub asids: u32,
+    pub features: Vec<(&'static str, bool)>,
+}
+
 impl CpuFeatures {
     pub fn new() -> Self {
         let cpuid = CpuId::new();
@@ -97,12 +109,14 @@ impl CpuFeatures {
         let extended_features = build_extended_features(&cpuid);
         let extended_state_features = build_extended_state_features(&cpuid);
         let vendor_info = build_vendor_info(&cpuid);
+        let svm_info = build_svm_info(&cpuid, &vendor_info);
         CpuFeatures {
             cpuid,
             vendor_info,
             features,
             extended_features,
             extended_state_features,
+            svm_info,
         }
     }
 
@@ -122,6 +136,10 @@ impl CpuFeatures {
         &self.extended_state_features
     }
 
+    pub fn svm_info(&self) -> Option<&SvmInfo> {
+        self.svm_info.as_ref()
+    }
+
     pub fn has_xsave(&self) -> bool {
         let cpuid = CpuId::new();
         let fi = cpuid.get_feature_info().unwrap();
@@ -177,6 +195,55 @@ impl CpuFeatures {
             .get_feature_info()
             .is_some_and(|fi| fi.has_tsc_deadline())
     }
+
+    pub fn has_aperfmperf(&self) -> bool {
+        self.cpuid
+            .get_thermal_power_info()
+            .is_some_and(|tpi| tpi.has_hw_coord_feedback())
+    }
+
+    pub fn has_vmx(&self) -> bool {
+        self.cpuid.get_feature_info().is_some_and(|fi| fi.has_vmx())
+    }
+
+    pub fn is_hyperv(&self) -> bool {
+        self.cpuid
+            .get_hypervisor_info()
+            .is_some_and(|hv| hv.identify() == Hypervisor::HyperV)
+    }
+
+    // AMD-specific checks
+    pub fn has_tsc_rate_msr(&self) -> bool {
+        self.cpuid
+            .get_svm_info()
+            .is_some_and(|svm| svm.has_tsc_rate_msr())
+    }
+
+    pub fn has_perf_cntr_extensions(&self) -> bool {
+        self.cpuid
+            .get_extended_processor_and_feature_identifiers()
+            .is_some_and(|efi| efi.has_perf_cntr_extensions())
+    }
+
+    /// Implemented physical address bits, 36 if leaf 0x8000_0008 is missing
+    pub fn physical_address_bits(&self) -> u8 {
+        self.cpuid
+            .get_processor_capacity_feature_info()
+            .map_or(36, |pcfi| pcfi.physical_address_bits())
+    }
+
+    // Protection key checks
+    pub fn has_pku(&self) -> bool {
+        self.cpuid
+            .get_extended_feature_info()
+            .is_some_and(|efi| efi.has_pku())
+    }
+
+    pub fn xcr0_supports_pkru(&self) -> bool {
+        self.cpuid
+            .get_extended_state_info()
+            .is_some_and(|esi| esi.xcr0_supports_pkru())
+    }
 }
 
 /// Returns TSC frequency in Hz from CPUID leaf 0x15, or processor base
@@ -201,6 +268,20 @@ pub fn tsc_frequency() -> Option<u64> {
     None
 }
 
+/// Returns whether OSPKE is reported. It mirrors CR4.PKE, so unlike the
+/// cached feature lists it is read from CPUID on every call.
+pub fn has_ospke() -> bool {
+    CpuId::new()
+        .get_extended_feature_info()
+        .is_some_and(|efi| efi.has_ospke())
+}
+
+pub fn has_x2apic() -> bool {
+    CpuId::new()
+        .get_feature_info()
+        .is_some_and(|fi| fi.has_x2apic())
+}
+
 pub struct VendorInfo {
     pub intel: bool,
     pub amd: bool,
@@ -222,6 +303,46 @@ fn build_vendor_info(cpuid: &CpuId<CpuIdReaderNative>) -> VendorInfo {
     }
 }
 
+fn build_svm_info(cpuid: &CpuId<CpuIdReaderNative>, vendor_info: &VendorInfo) -> Option<SvmInfo> {
+    if !vendor_info.amd {
+        return None;
+    }
+    let svm = cpuid.get_svm_info()?;
+    let mut features: Vec<(&str, bool)> = Vec::new();
+
+    macro_rules! push_has {
+        ($m:ident) => {
+            let name = stringify!($m).strip_prefix("has_").unwrap();
+            features.push((name, svm.$m()));
+        };
+    }
+
+    push_has!(has_nested_paging);
+    push_has!(has_lbr_virtualization);
+    push_has!(has_svm_lock);
+    push_has!(has_nrip);
+    push_has!(has_tsc_rate_msr);
+    push_has!(has_vmcb_clean_bits);
+    push_has!(has_flush_by_asid);
+    push_has!(has_decode_assists);
+    push_has!(has_pause_filter);
+    push_has!(has_pause_filter_threshold);
+    push_has!(has_avic);
+    push_has!(has_vmsave_virtualization);
+    push_has!(has_gif);
+    push_has!(has_gmet);
+    push_has!(has_sss_check);
+    push_has!(has_spec_ctrl);
+    push_has!(has_host_mce_override);
+    push_has!(has_tlb_ctrl);
+
+    Some(SvmInfo {
+        revision: svm.revision(),
+        asids: svm.supported_asids(),
+        features,
+    })
+}
+
 fn build_extended_state_features(cpuid: &CpuId<CpuIdReaderNative>) -> ExtendedStateFeatures {
     let esfi = cpuid.get_extended_state_info().unwrap();
 
@@ -461,6 +582,12 @@ impl CpuidPane {
     fn extended_state_start(&self) -> u16 {
         self.extended_features_start() + self.state.extended_features().len() as u16 + 2
     }
+
+    fn svm_features_start(&self) -> u16 {
+        // supports + empty(1) + sizes + empty(1) + header(1) + revision(1) + asids(1)
+        let esf = self.state.extended_state_features();
+        self.extended_state_start() + (esf.supports().len() + esf.sizes().len()) as u16 + 5
+    }
 }
 
 impl Scrollable for CpuidPane {
@@ -498,6 +625,15 @@ impl Searchable for CpuidPane {
             line += 1;
         }
 
+        // SVM features
+        if let Some(svm) = self.state.svm_info() {
+            line = self.svm_features_start();
+            for (name, _) in &svm.features {
+                items.push((*name, line));
+                line += 1;
+            }
+        }
+
         items
     }
 }
@@ -551,13 +687,25 @@ impl Widget for &mut CpuidPane {
             lines.push(highlight_line(feature.0, &suffix, 30, query));
         }
 
-        lines.push(empty_line);
+        lines.push(empty_line.clone());
 
         for size_feature in esf.sizes() {
             let line = Line::raw(format!("{:<34} = {} bytes", size_feature.0, size_feature.1));
             lines.push(line);
         }
 
+        if let Some(svm) = self.state.svm_info() {
+            lines.push(empty_line);
+            lines.push(Line::styled("SVM Features:", Style::default().bold()));
+            lines.push(Line::raw(format!("{:<24} = {}", "revision", svm.revision)));
+            lines.push(Line::raw(format!("{:<24} = {}", "asids", svm.asids)));
+            for feature in &svm.features {
+                let yes_no = if feature.1 { "Yes" } else { "No" };
+                let suffix = format!(" = {}", yes_no);
+                lines.push(highlight_line(feature.0, &suffix, 24, query));
+            }
+        }
+
         let n_lines = lines.len();
         let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
 
@@ -0,0 +1,334 @@
+//! Report of a panic or of an exception no handler recovered from: a plain
+//! text report on serial, then a full-screen crash report in place of the TUI
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use ratatui::Terminal;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::idt::InterruptStackFrameValue;
+
+use crate::backtrace::Backtrace;
+use crate::interrupts::{EXCEPTION_NAMES, EXCEPTIONS, ExceptionFrame, Registers};
+use crate::qemu::{self, QemuExitCode};
+use crate::ratatui_backend::SerialAnsiBackend;
+use crate::{memory, recovery, serial};
+
+/// Qwords dumped from the interrupted stack
+const STACK_WORDS: usize = 16;
+
+/// Set by the first crash, a crash while reporting it only goes to serial
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// #PF error code flags reported only when set
+const PAGE_FAULT_FLAGS: &[(u8, &str)] = &[
+    (3, "reserved bit set"),
+    (4, "instruction fetch"),
+    (5, "protection key"),
+    (6, "shadow stack"),
+    (7, "HLAT"),
+    (15, "SGX"),
+];
+
+/// Exceptions whose error code is a segment selector index
+const SELECTOR_ERRORS: [u8; 4] = [10, 11, 12, 13];
+
+/// Turns the `\n` of the report into `\r\n` for the serial console
+struct CrLf<W: Write>(W);
+
+impl<W: Write> Write for CrLf<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.0.write_str("\r\n")?;
+            }
+            self.0.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Machine state at the exception, captured before anything else runs
+struct Exception {
+    vector: u8,
+    error_code: Option<u64>,
+    frame: InterruptStackFrameValue,
+    registers: Registers,
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    cr4: u64,
+    /// Qwords at the interrupted RSP, None where the read faulted
+    stack: [Option<u64>; STACK_WORDS],
+}
+
+impl Exception {
+    fn capture(exception: &ExceptionFrame) -> Self {
+        let frame = &exception.frame;
+        let rsp = frame.stack_pointer.as_u64();
+        let stack =
+            core::array::from_fn(|i| recovery::read_u64((rsp + i as u64 * 8) as *const u64).ok());
+        let (cr3, low) = Cr3::read_raw();
+        Self {
+            vector: exception.vector as u8,
+            error_code: exception.error_code(),
+            frame: **frame,
+            registers: exception.registers,
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read_raw(),
+            cr3: cr3.start_address().as_u64() | low as u64,
+            cr4: Cr4::read_raw(),
+            stack,
+        }
+    }
+
+    fn write_error_code(&self, out: &mut impl Write, code: u64) -> fmt::Result {
+        if self.vector == 14 {
+            let flag = |bit: u8, set: &'static str, clear: &'static str| {
+                if code & (1 << bit) != 0 { set } else { clear }
+            };
+            write!(
+                out,
+                "    {}, {}, {}",
+                flag(0, "protection violation", "not present"),
+                flag(1, "write", "read"),
+                flag(2, "user", "supervisor")
+            )?;
+            for (bit, name) in PAGE_FAULT_FLAGS {
+                if code & (1 << bit) != 0 {
+                    write!(out, ", {}", name)?;
+                }
+            }
+            writeln!(out, ", address 0x{:x}", self.cr2)?;
+        } else if SELECTOR_ERRORS.contains(&self.vector) && code != 0 {
+            let table = match (code >> 1) & 0b11 {
+                0 => "GDT",
+                2 => "LDT",
+                _ => "IDT",
+            };
+            let external = if code & 1 != 0 { ", external" } else { "" };
+            writeln!(
+                out,
+                "    selector index {} in the {}{}",
+                (code >> 3) & 0x1FFF,
+                table,
+                external
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_summary(&self, out: &mut impl Write) -> fmt::Result {
+        let vector = self.vector as usize;
+        write!(
+            out,
+            "{} {}, vector {}",
+            EXCEPTIONS.get(vector).copied().unwrap_or(""),
+            EXCEPTION_NAMES.get(vector).copied().unwrap_or("Interrupt"),
+            vector
+        )?;
+        match self.error_code {
+            Some(code) => {
+                writeln!(out, ", error code 0x{:x}", code)?;
+                self.write_error_code(out, code)?;
+            }
+            None => writeln!(out)?,
+        }
+        writeln!(out)?;
+
+        let frame = &self.frame;
+        writeln!(out, "Saved by the CPU")?;
+        writeln!(
+            out,
+            "  RIP    0x{:016x}  CS 0x{:04x}",
+            frame.instruction_pointer.as_u64(),
+            frame.code_segment.0
+        )?;
+        writeln!(
+            out,
+            "  RSP    0x{:016x}  SS 0x{:04x}",
+            frame.stack_pointer.as_u64(),
+            frame.stack_segment.0
+        )?;
+        writeln!(out, "  RFLAGS 0x{:016x}", frame.cpu_flags.bits())?;
+        writeln!(out, "General purpose registers")?;
+        let r = &self.registers;
+        let registers = [
+            ("RAX", r.rax),
+            ("RBX", r.rbx),
+            ("RCX", r.rcx),
+            ("RDX", r.rdx),
+            ("RSI", r.rsi),
+            ("RDI", r.rdi),
+            ("RBP", r.rbp),
+            ("R8", r.r8),
+            ("R9", r.r9),
+            ("R10", r.r10),
+            ("R11", r.r11),
+            ("R12", r.r12),
+            ("R13", r.r13),
+            ("R14", r.r14),
+            ("R15", r.r15),
+        ];
+        for row in registers.chunks(3) {
+            write!(out, " ")?;
+            for (name, value) in row {
+                write!(out, " {:<3} 0x{:016x} ", name, value)?;
+            }
+            writeln!(out)?;
+        }
+        writeln!(out, "Control registers")?;
+        writeln!(out, "  CR0 0x{:016x}  CR2 0x{:016x}", self.cr0, self.cr2)?;
+        writeln!(out, "  CR3 0x{:016x}  CR4 0x{:016x}", self.cr3, self.cr4)
+    }
+
+    fn write_stack(&self, out: &mut impl Write) -> fmt::Result {
+        writeln!(out, "Stack at RSP")?;
+        for (i, pair) in self.stack.chunks(2).enumerate() {
+            write!(out, "  +0x{:02x}", i * 16)?;
+            for word in pair {
+                match word {
+                    Some(word) => write!(out, "  0x{:016x}", word)?,
+                    None => write!(out, "  {:<18}", "unreadable")?,
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+// built once per crash on the stack, boxing would need the heap
+#[allow(clippy::large_enum_variant)]
+enum Cause<'a> {
+    Exception(Exception),
+    Panic(&'a PanicInfo<'a>),
+}
+
+struct Crash<'a> {
+    cause: Cause<'a>,
+    backtrace: Backtrace,
+}
+
+impl Crash<'_> {
+    fn title(&self) -> &'static str {
+        match self.cause {
+            Cause::Exception(_) => "CPU Exception",
+            Cause::Panic(_) => "Kernel Panic",
+        }
+    }
+
+    /// The report, one `\n` terminated line at a time. Nothing here may
+    /// allocate, the heap may be what failed.
+    fn write_report(&self, out: &mut impl Write) -> fmt::Result {
+        match &self.cause {
+            Cause::Exception(exception) => exception.write_summary(out)?,
+            Cause::Panic(info) => writeln!(out, "{}", info)?,
+        }
+        writeln!(out, "Backtrace")?;
+        self.backtrace.write(out)?;
+        if let Cause::Exception(exception) = &self.cause {
+            exception.write_stack(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// The crash report scrolled down by `scroll` lines
+struct Screen<'a> {
+    crash: &'a Crash<'a>,
+    scroll: u16,
+}
+
+impl Widget for Screen<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut report = String::new();
+        let _ = self.crash.write_report(&mut report);
+        let mut lines: Vec<Line> = report.lines().map(Line::raw).collect();
+        if let Some(title) = lines.first_mut() {
+            *title = title.clone().style(Style::default().fg(Color::Red).bold());
+        }
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            "j/k to scroll, q to exit",
+            Style::default().fg(Color::DarkGray),
+        ));
+
+        let block = Block::default()
+            .title(self.crash.title())
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Red));
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((self.scroll, 0))
+            .render(area, buf);
+    }
+}
+
+/// Report an exception and wait for `q` to exit. Called by the exception
+/// handler after recovery declined the fault.
+pub fn report(frame: &ExceptionFrame) -> ! {
+    let exception = Exception::capture(frame);
+    let backtrace = Backtrace::interrupted(
+        frame.frame.instruction_pointer.as_u64(),
+        frame.registers.rbp,
+    );
+    show(Crash {
+        cause: Cause::Exception(exception),
+        backtrace,
+    })
+}
+
+/// Report a panic and wait for `q` to exit
+pub fn panic(info: &PanicInfo) -> ! {
+    show(Crash {
+        cause: Cause::Panic(info),
+        backtrace: Backtrace::capture(),
+    })
+}
+
+fn show(crash: Crash) -> ! {
+    let mut port = CrLf(serial::port());
+    if CRASHED.swap(true, Ordering::SeqCst) {
+        let _ = write!(port, "\nCRASH WHILE REPORTING A CRASH\n");
+        let _ = crash.write_report(&mut port);
+        qemu::exit(QemuExitCode::Failed);
+    }
+    let _ = write!(port, "\n{}\n", crash.title());
+    let _ = crash.write_report(&mut port);
+
+    // the screen needs the heap, which is unusable if the crash hit while
+    // the allocator held it
+    let mut terminal = memory::heap_usage().map(|_| {
+        let Ok(terminal) = Terminal::new(SerialAnsiBackend::new(serial::port(), 80, 24));
+        terminal
+    });
+    let mut scroll: u16 = 0;
+    loop {
+        if let Some(terminal) = &mut terminal {
+            let screen = Screen {
+                crash: &crash,
+                scroll,
+            };
+            let _ = terminal.draw(|frame| frame.render_widget(screen, frame.area()));
+        }
+
+        while !serial::uart_rx_ready() {
+            core::hint::spin_loop();
+        }
+        match serial::uart_read_byte() {
+            b'q' => qemu::exit(QemuExitCode::Failed),
+            b'j' => scroll = scroll.saturating_add(1),
+            b'k' => scroll = scroll.saturating_sub(1),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Descriptor tables: the GDT, LDT, TSS and IDT the CPU is using, read via
+//! SGDT/SIDT/STR/SLDT
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Widget};
+use x86_64::instructions::tables::{sgdt, sidt};
+
+use crate::interrupts::{self, EXCEPTIONS};
+use crate::pane::{ScrollHints, Scrollable};
+use crate::recovery::{self, Fault};
+
+/// Offsets of the stack pointers in the 64-bit TSS
+const TSS_RSP0: u64 = 4;
+const TSS_IST1: u64 = 36;
+const TSS_IOMAP_BASE: u64 = 102;
+
+/// Gate types of 64-bit IDT entries
+const GATE_INTERRUPT: u64 = 0xE;
+const GATE_TRAP: u64 = 0xF;
+
+fn str_selector() -> u16 {
+    let selector: u16;
+    unsafe {
+        asm!("str {0:x}", out(reg) selector, options(nomem, nostack, preserves_flags));
+    }
+    selector
+}
+
+fn sldt_selector() -> u16 {
+    let selector: u16;
+    unsafe {
+        asm!("sldt {0:x}", out(reg) selector, options(nomem, nostack, preserves_flags));
+    }
+    selector
+}
+
+/// Legacy 8-byte segment descriptor
+#[derive(Clone, Copy)]
+struct Descriptor(u64);
+
+impl Descriptor {
+    fn base(self) -> u64 {
+        ((self.0 >> 16) & 0xFF_FFFF) | ((self.0 >> 56) & 0xFF) << 24
+    }
+
+    /// Limit in bytes, scaled by 4 KiB with the granularity flag
+    fn limit(self) -> u64 {
+        let limit = (self.0 & 0xFFFF) | ((self.0 >> 48) & 0xF) << 16;
+        if self.flag(55) {
+            (limit << 12) | 0xFFF
+        } else {
+            limit
+        }
+    }
+
+    fn flag(self, bit: u64) -> bool {
+        self.0 & (1 << bit) != 0
+    }
+
+    fn kind(self) -> u64 {
+        (self.0 >> 40) & 0xF
+    }
+
+    /// Code or data segment, as opposed to a system segment or gate
+    fn is_segment(self) -> bool {
+        self.flag(44)
+    }
+
+    fn dpl(self) -> u64 {
+        (self.0 >> 45) & 0b11
+    }
+
+    /// System descriptors take two slots in long mode
+    fn is_wide(self) -> bool {
+        !self.is_segment() && self.flag(47)
+    }
+
+    fn type_str(self) -> String {
+        let kind = self.kind();
+        if !self.is_segment() {
+            return String::from(match kind {
+                0x2 => "LDT",
+                0x9 => "TSS available",
+                0xB => "TSS busy",
+                0xC => "Call gate",
+                0xE => "Interrupt gate",
+                0xF => "Trap gate",
+                _ => "Reserved",
+            });
+        }
+
+        // code: conforming, readable; data: expand-down, writable
+        let (name, bit2, bit1) = if kind & 0b1000 != 0 {
+            ("Code", "C", "R")
+        } else {
+            ("Data", "E", "W")
+        };
+        let mut s = String::from(name);
+        for (set, attribute) in [
+            (kind & 0b100 != 0, bit2),
+            (kind & 0b10 != 0, bit1),
+            (kind & 0b1 != 0, "A"),
+        ] {
+            if set {
+                s.push(' ');
+                s.push_str(attribute);
+            }
+        }
+        s
+    }
+
+    fn flags_str(self) -> String {
+        let flags: Vec<&str> = [(47, "P"), (52, "AVL"), (53, "L"), (54, "D"), (55, "G")]
+            .iter()
+            .filter(|(bit, _)| self.flag(*bit))
+            .map(|(_, name)| *name)
+            .collect();
+        flags.join(" ")
+    }
+}
+
+/// Base of a system descriptor, whose upper 32 bits are in the second slot
+fn wide_base(low: Descriptor, high: u64) -> u64 {
+    low.base() | (high & 0xFFFF_FFFF) << 32
+}
+
+fn header(text: &str) -> Line<'static> {
+    Line::styled(String::from(text), Style::default().bold())
+}
+
+fn fault_line(what: &str, fault: &Fault) -> Line<'static> {
+    Line::styled(
+        format!("{}: vector {} reading the table", what, fault.vector),
+        Style::default().fg(Color::Red),
+    )
+}
+
+/// Lines for the descriptors of a GDT or LDT, selectors get `ti` ORed in
+fn segment_lines(base: u64, limit: u64, ti: u16) -> Vec<Line<'static>> {
+    let mut lines = vec![header(&format!(
+        "{:<8}{:<20}{:<12}{:<4}{:<16}{}",
+        "Sel", "Base", "Limit", "DPL", "Type", "Flags"
+    ))];
+
+    let mut offset = 0;
+    while offset + 7 <= limit {
+        let selector = offset as u16 | ti;
+        let raw = match recovery::read_u64((base + offset) as *const u64) {
+            Ok(raw) => raw,
+            Err(fault) => {
+                lines.push(fault_line(&format!("0x{:04x}", selector), &fault));
+                break;
+            }
+        };
+        let descriptor = Descriptor(raw);
+        offset += 8;
+
+        if raw == 0 {
+            lines.push(Line::styled(
+                format!("0x{:04x}  null", selector),
+                Style::default().fg(Color::DarkGray),
+            ));
+            continue;
+        }
+
+        let mut segment_base = descriptor.base();
+        if descriptor.is_wide() && offset + 7 <= limit {
+            segment_base = wide_base(
+                descriptor,
+                recovery::read_u64((base + offset) as *const u64).unwrap_or(0),
+            );
+            offset += 8;
+        }
+        let style = if descriptor.flag(47) {
+            Style::default()
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        lines.push(Line::styled(
+            format!(
+                "0x{:04x}  {:<20}{:<12}{:<4}{:<16}{}",
+                selector,
+                format!("0x{:x}", segment_base),
+                format!("0x{:x}", descriptor.limit()),
+                descriptor.dpl(),
+                descriptor.type_str(),
+                descriptor.flags_str()
+            ),
+            style,
+        ));
+    }
+    lines
+}
+
+/// Base and limit of the system descriptor `selector` refers to in the GDT
+fn system_segment(gdt_base: u64, selector: u16) -> Result<(u64, u64), Fault> {
+    let offset = (selector & !0b111) as u64;
+    let low = Descriptor(recovery::read_u64((gdt_base + offset) as *const u64)?);
+    let high = recovery::read_u64((gdt_base + offset + 8) as *const u64)?;
+    Ok((wide_base(low, high), low.limit()))
+}
+
+fn tss_lines(gdt_base: u64, tr: u16) -> Vec<Line<'static>> {
+    let mut lines = vec![header(&format!("TSS (TR = 0x{:04x})", tr))];
+    if tr & !0b111 == 0 {
+        lines.push(Line::raw("No TSS loaded"));
+        return lines;
+    }
+
+    let (base, limit) = match system_segment(gdt_base, tr) {
+        Ok(segment) => segment,
+        Err(fault) => return vec![fault_line("TSS descriptor", &fault)],
+    };
+    lines.push(Line::raw(format!(
+        "{:<12}0x{:x}, limit 0x{:x}",
+        "Base:", base, limit
+    )));
+
+    let stacks = (0..3)
+        .map(|i| (format!("RSP{}:", i), TSS_RSP0 + i * 8))
+        .chain((0..7).map(|i| (format!("IST{}:", i + 1), TSS_IST1 + i * 8)));
+    for (name, offset) in stacks {
+        let value = match recovery::read_u64((base + offset) as *const u64) {
+            Ok(0) => String::from("-"),
+            Ok(value) => format!("0x{:x}", value),
+            Err(fault) => format!("vector {}", fault.vector),
+        };
+        lines.push(Line::raw(format!("{:<12}{}", name, value)));
+    }
+    if let Ok(iomap) = recovery::read_u16((base + TSS_IOMAP_BASE) as *const u16) {
+        lines.push(Line::raw(format!("{:<12}0x{:x}", "I/O map:", iomap)));
+    }
+    lines
+}
+
+fn ldt_lines(gdt_base: u64, ldtr: u16) -> Vec<Line<'static>> {
+    let mut lines = vec![header(&format!("LDT (LDTR = 0x{:04x})", ldtr))];
+    if ldtr & !0b111 == 0 {
+        lines.push(Line::raw("No LDT loaded"));
+        return lines;
+    }
+
+    match system_segment(gdt_base, ldtr) {
+        Ok((base, limit)) => {
+            lines.push(Line::raw(format!(
+                "{:<12}0x{:x}, limit 0x{:x}",
+                "Base:", base, limit
+            )));
+            lines.extend(segment_lines(base, limit, 0b100));
+        }
+        Err(fault) => lines.push(fault_line("LDT descriptor", &fault)),
+    }
+    lines
+}
+
+fn idt_lines(base: u64, limit: u64) -> Vec<Line<'static>> {
+    let handlers = interrupts::handlers();
+    let mut lines = vec![header(&format!(
+        "{:<9}{:<8}{:<4}{:<5}{:<3}{:<19}{}",
+        "Vector", "Sel", "IST", "Type", "DPL", "Handler", "Name"
+    ))];
+
+    let mut absent = 0;
+    for vector in 0..((limit + 1) / 16).min(256) {
+        let (low, high) = match (
+            recovery::read_u64((base + vector * 16) as *const u64),
+            recovery::read_u64((base + vector * 16 + 8) as *const u64),
+        ) {
+            (Ok(low), Ok(high)) => (low, high),
+            (Err(fault), _) | (_, Err(fault)) => {
+                lines.push(fault_line(&format!("Vector {}", vector), &fault));
+                break;
+            }
+        };
+        // present
+        if low & (1 << 47) == 0 {
+            absent += 1;
+            continue;
+        }
+
+        let handler = (low & 0xFFFF) | ((low >> 48) & 0xFFFF) << 16 | (high & 0xFFFF_FFFF) << 32;
+        let kind = match (low >> 40) & 0xF {
+            GATE_INTERRUPT => "Int",
+            GATE_TRAP => "Trap",
+            _ => "?",
+        };
+        let exception = EXCEPTIONS.get(vector as usize).copied().unwrap_or("");
+        let name = handlers
+            .iter()
+            .find(|(address, _)| *address == handler)
+            .map_or("", |(_, name)| *name);
+        lines.push(Line::raw(format!(
+            "{:<9}{:<8}{:<4}{:<5}{:<3}{:<19}{}",
+            format!("{:<3} {}", vector, exception),
+            format!("0x{:04x}", (low >> 16) & 0xFFFF),
+            (low >> 32) & 0b111,
+            kind,
+            (low >> 45) & 0b11,
+            format!("0x{:x}", handler),
+            name
+        )));
+    }
+    lines.push(Line::styled(
+        format!("{} gates not present", absent),
+        Style::default().fg(Color::DarkGray),
+    ));
+    lines
+}
+
+pub struct DescriptorPane {
+    scroll: ScrollHints,
+}
+
+impl DescriptorPane {
+    pub fn new() -> Self {
+        Self {
+            scroll: ScrollHints::default(),
+        }
+    }
+}
+
+impl Scrollable for DescriptorPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut DescriptorPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let gdtr = sgdt();
+        let idtr = sidt();
+        let gdt_base = gdtr.base.as_u64();
+        let mut lines = Vec::new();
+
+        lines.push(header(&format!(
+            "GDT (base 0x{:x}, limit 0x{:x})",
+            gdt_base, gdtr.limit
+        )));
+        lines.extend(segment_lines(gdt_base, gdtr.limit as u64, 0));
+        lines.push(Line::raw(""));
+
+        lines.extend(tss_lines(gdt_base, str_selector()));
+        lines.push(Line::raw(""));
+
+        lines.extend(ldt_lines(gdt_base, sldt_selector()));
+        lines.push(Line::raw(""));
+
+        lines.push(header(&format!(
+            "IDT (base 0x{:x}, limit 0x{:x})",
+            idtr.base.as_u64(),
+            idtr.limit
+        )));
+        lines.extend(idt_lines(idtr.base.as_u64(), idtr.limit as u64));
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
@@ -0,0 +1,61 @@
+//! Kernel GDT with a TSS, whose interrupt stack table gives the double fault
+//! handler a known good stack even when the fault came from a stack overflow
+
+use spin::Once;
+use x86_64::VirtAddr;
+use x86_64::instructions::segmentation::{CS, DS, ES, SS, Segment};
+use x86_64::instructions::tables::load_tss;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+
+/// IST slot of the double fault handler, IST1 in the TSS
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 5 * 4096;
+
+/// Only ever addressed through the TSS
+#[repr(align(16))]
+#[allow(dead_code)]
+struct Stack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+static TSS: Once<TaskStateSegment> = Once::new();
+static GDT: Once<(GlobalDescriptorTable, Selectors)> = Once::new();
+
+struct Selectors {
+    code: SegmentSelector,
+    data: SegmentSelector,
+    tss: SegmentSelector,
+}
+
+/// Replace the bootloader's GDT with ours and load the TSS
+pub fn init() {
+    let tss = TSS.call_once(|| {
+        let mut tss = TaskStateSegment::new();
+        // stacks grow down, the IST entry holds the end of the stack
+        let stack = VirtAddr::from_ptr(&raw const DOUBLE_FAULT_STACK);
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            stack + DOUBLE_FAULT_STACK_SIZE as u64;
+        tss
+    });
+
+    let (gdt, selectors) = GDT.call_once(|| {
+        let mut gdt = GlobalDescriptorTable::new();
+        let selectors = Selectors {
+            code: gdt.append(Descriptor::kernel_code_segment()),
+            data: gdt.append(Descriptor::kernel_data_segment()),
+            tss: gdt.append(Descriptor::tss_segment(tss)),
+        };
+        (gdt, selectors)
+    });
+
+    gdt.load();
+    unsafe {
+        CS::set_reg(selectors.code);
+        SS::set_reg(selectors.data);
+        DS::set_reg(selectors.data);
+        ES::set_reg(selectors.data);
+        load_tss(selectors.tss);
+    }
+}
@@ -0,0 +1,313 @@
+//! Hexdump of virtual memory, or of physical memory through the bootloader's
+//! mapping of it. Every page is checked in the page tables before it is read
+//! and the reads themselves are guarded against faults.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+use x86_64::VirtAddr;
+
+use crate::memory::{self, HEAP_START, PageTableManager};
+use crate::pane::{
+    PromptResult, Promptable, ScrollDirection, ScrollHints, Scrollable, parse_number,
+};
+use crate::recovery::{self, Fault};
+
+const ADDRESS_WIDTH: usize = 16;
+/// Rows start on this alignment so every group is naturally aligned
+const ROW_ALIGN: u64 = 16;
+const PAGE_SIZE: u64 = 4096;
+/// Physical addresses are at most 52 bits wide
+const MAX_PHYS: u64 = 1 << 52;
+const MAX_HISTORY: usize = 32;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Space {
+    Virtual,
+    Physical,
+}
+
+impl Space {
+    fn name(self) -> &'static str {
+        match self {
+            Space::Virtual => "Virtual",
+            Space::Physical => "Physical",
+        }
+    }
+}
+
+/// Read `size` bytes with a single access of that size
+fn read_group(ptr: u64, size: usize) -> Result<u64, Fault> {
+    match size {
+        1 => recovery::read_u8(ptr as *const u8).map(u64::from),
+        2 => recovery::read_u16(ptr as *const u16).map(u64::from),
+        4 => recovery::read_u32(ptr as *const u32).map(u64::from),
+        _ => recovery::read_u64(ptr as *const u64),
+    }
+}
+
+/// Width of the hex column of a row
+fn hex_width(row_bytes: usize, group: usize) -> usize {
+    row_bytes * 2 + row_bytes / group - 1
+}
+
+pub struct HexPane {
+    space: Space,
+    /// Address the view was opened at, its group is highlighted
+    target: u64,
+    /// First byte shown
+    address: u64,
+    /// Bytes per group, read with one access of that size
+    group: usize,
+    /// Locations left by goto and follow, most recent last
+    history: Vec<(Space, u64)>,
+    /// Row geometry of the last render
+    row_bytes: u64,
+    rows: u64,
+    scroll: ScrollHints,
+}
+
+impl HexPane {
+    pub fn new() -> Self {
+        Self {
+            space: Space::Virtual,
+            target: HEAP_START as u64,
+            address: HEAP_START as u64,
+            group: 1,
+            history: Vec::new(),
+            row_bytes: ROW_ALIGN,
+            rows: 0,
+            scroll: ScrollHints::default(),
+        }
+    }
+
+    fn goto(&mut self, space: Space, address: u64) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push((self.space, self.target));
+        self.show(space, address);
+    }
+
+    fn show(&mut self, space: Space, address: u64) {
+        self.space = space;
+        self.target = address;
+        self.address = address & !(ROW_ALIGN - 1);
+    }
+
+    /// Virtual address `address` of `space` is reachable at, None if it is
+    /// not mapped
+    fn pointer(tables: &PageTableManager, space: Space, address: u64) -> Option<u64> {
+        let virt = match space {
+            Space::Virtual => VirtAddr::try_new(address).ok()?,
+            Space::Physical if address < MAX_PHYS => {
+                let offset = tables.phys_offset().as_u64();
+                VirtAddr::try_new(offset.checked_add(address)?).ok()?
+            }
+            Space::Physical => return None,
+        };
+        tables.walk(virt).1.map(|_| virt.as_u64())
+    }
+
+    /// Where the view starts: its translation or the mapping used to reach it
+    fn location_line(&self, tables: &PageTableManager) -> Line<'static> {
+        let detail = match (self.space, Self::pointer(tables, self.space, self.target)) {
+            (_, None) => String::from("not mapped"),
+            (Space::Virtual, Some(virt)) => match tables.walk(VirtAddr::new(virt)).1 {
+                Some(leaf) => format!("phys 0x{:x}", leaf.phys.as_u64()),
+                None => String::from("not mapped"),
+            },
+            (Space::Physical, Some(virt)) => format!("mapped at 0x{:x}", virt),
+        };
+        Line::from(vec![
+            Span::styled(
+                format!("{} 0x{:x}", self.space.name(), self.target),
+                Style::default().bold(),
+            ),
+            Span::raw(format!("  {}", detail)),
+        ])
+    }
+
+    fn row_line(&self, tables: &PageTableManager, row: u64) -> Line<'static> {
+        let group = self.group;
+        let width = group * 2;
+        let mut spans = vec![Span::styled(
+            format!("{:0w$x}  ", row, w = ADDRESS_WIDTH),
+            Style::default().fg(Color::DarkGray),
+        )];
+        let mut ascii = String::new();
+
+        // one lookup per row, rows never cross a page
+        let base = Self::pointer(tables, self.space, row);
+        for offset in (0..self.row_bytes).step_by(group) {
+            let address = row.wrapping_add(offset);
+            let value = base.and_then(|ptr| read_group(ptr + offset, group).ok());
+
+            let (hex, style) = match value {
+                Some(value) => (format!("{:0w$x}", value, w = width), Style::default()),
+                None => ("-".repeat(width), Style::default().fg(Color::DarkGray)),
+            };
+            let highlighted = (address..address + group as u64).contains(&self.target);
+            spans.push(Span::styled(
+                hex,
+                if highlighted { style.reversed() } else { style },
+            ));
+            if offset + (group as u64) < self.row_bytes {
+                spans.push(Span::raw(" "));
+            }
+
+            match value {
+                Some(value) => ascii.extend(value.to_le_bytes()[..group].iter().map(|&b| {
+                    if (0x20..0x7F).contains(&b) {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })),
+                None => ascii.push_str(&" ".repeat(group)),
+            }
+        }
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(ascii, Style::default().fg(Color::Cyan)));
+        Line::from(spans)
+    }
+
+    /// Read the pointer at `offset` from the view's address and show where
+    /// it points
+    fn follow(&mut self, offset: u64) -> PromptResult {
+        let address = self.target.wrapping_add(offset);
+        let value = {
+            let tables = memory::page_tables();
+            let ptr = Self::pointer(&tables, self.space, address)
+                .ok_or_else(|| format!("0x{:x} is not mapped", address))?;
+            recovery::read_u64(ptr as *const u64).map_err(|fault| {
+                format!("reading 0x{:x} raised vector {}", address, fault.vector)
+            })?
+        };
+        if VirtAddr::try_new(value).is_err() {
+            return Err(format!("0x{:x} is not a canonical address", value));
+        }
+        self.goto(Space::Virtual, value);
+        Ok(format!("followed 0x{:x} -> 0x{:x}", address, value))
+    }
+}
+
+impl Scrollable for HexPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+
+    /// Scrolling moves through memory, top and bottom are the ends of the
+    /// current page
+    fn scroll(&mut self, direction: ScrollDirection) {
+        let page = self.row_bytes * self.rows.max(1);
+        self.address = match direction {
+            ScrollDirection::Up => self.address.wrapping_sub(self.row_bytes),
+            ScrollDirection::Down => self.address.wrapping_add(self.row_bytes),
+            ScrollDirection::PageUp => self.address.wrapping_sub(page),
+            ScrollDirection::PageDown => self.address.wrapping_add(page),
+            ScrollDirection::Top => self.address & !(PAGE_SIZE - 1),
+            ScrollDirection::Bottom => {
+                let end = (self.address | (PAGE_SIZE - 1)).wrapping_add(1);
+                end.wrapping_sub(page.min(PAGE_SIZE))
+            }
+        };
+    }
+}
+
+impl Promptable for HexPane {
+    fn prompt_label(&self) -> String {
+        String::from("hex: ")
+    }
+
+    /// `<virt>` or `v <virt>`, `p <phys>`, `heap`, `b`/`w`/`d`/`q` grouping,
+    /// `follow [offset]` and `back`
+    fn submit_prompt(&mut self, input: &str) -> PromptResult {
+        let mut words = input.split_whitespace();
+        let parse =
+            |word: &str| parse_number(word).ok_or_else(|| format!("invalid number: {}", word));
+
+        match (words.next(), words.next(), words.next()) {
+            (Some(unit @ ("b" | "w" | "d" | "q")), None, None) => {
+                self.group = match unit {
+                    "b" => 1,
+                    "w" => 2,
+                    "d" => 4,
+                    _ => 8,
+                };
+                Ok(format!("grouping by {} bytes", self.group))
+            }
+            (Some("p"), Some(address), None) => {
+                let address = parse(address)?;
+                if address >= MAX_PHYS {
+                    return Err(format!(
+                        "0x{:x} is above the physical address space",
+                        address
+                    ));
+                }
+                self.goto(Space::Physical, address);
+                Ok(format!("physical 0x{:x}", address))
+            }
+            (Some("heap"), None, None) => {
+                self.goto(Space::Virtual, HEAP_START as u64);
+                Ok(format!("heap at 0x{:x}", HEAP_START))
+            }
+            (Some("follow"), offset, None) => self.follow(offset.map_or(Ok(0), parse)?),
+            (Some("back"), None, None) => {
+                let (space, address) = self.history.pop().ok_or("no previous location")?;
+                self.show(space, address);
+                Ok(format!("back to 0x{:x}", address))
+            }
+            (Some("v"), Some(address), None) | (Some(address), None, None) => {
+                let address = parse(address)?;
+                if VirtAddr::try_new(address).is_err() {
+                    return Err(format!("0x{:x} is not a canonical address", address));
+                }
+                self.goto(Space::Virtual, address);
+                Ok(format!("virtual 0x{:x}", address))
+            }
+            _ => Err(String::from(
+                "usage: <virt> | p <phys> | heap | b/w/d/q | follow [offset] | back",
+            )),
+        }
+    }
+}
+
+impl Widget for &mut HexPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let tables = memory::page_tables();
+
+        // 16 bytes per row when they fit next to the address and ASCII
+        let fits = |row_bytes: usize| {
+            ADDRESS_WIDTH + 2 + hex_width(row_bytes, self.group) + 2 + row_bytes
+                <= area.width as usize
+        };
+        self.row_bytes = if fits(16) { 16 } else { 8 };
+
+        let mut lines = vec![
+            self.location_line(&tables),
+            Line::styled(
+                "<virt> | p <phys> | heap | b/w/d/q | follow [offset] | back",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ];
+        self.rows = area.height.saturating_sub(lines.len() as u16) as u64;
+        for i in 0..self.rows {
+            let row = self.address.wrapping_add(i * self.row_bytes);
+            lines.push(self.row_line(&tables, row));
+        }
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines);
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
@@ -28,6 +28,17 @@ pub enum InputEvent {
     SearchBackspace,
     NextMatch,
     PrevMatch,
+    EnterPromptMode,
+    ConfirmPrompt,
+    ExitPromptMode,
+    PromptInput(u8),
+    PromptBackspace,
+    #[cfg(feature = "msr")]
+    ToggleExpand,
+    #[cfg(feature = "msr")]
+    ToggleView,
+    Descend,
+    Ascend,
     ClearScreen,
 }
 
@@ -92,6 +103,14 @@ impl Input {
                     _ => None,
                 }
             }
+            // Prompt input mode - typing a pane command
+            Mode::Prompt => match byte {
+                0x1B => Some(InputEvent::ExitPromptMode),         // ESC
+                0x7F | 0x08 => Some(InputEvent::PromptBackspace), // Backspace/DEL
+                0x0D => Some(InputEvent::ConfirmPrompt),          // Enter
+                b if (0x20..0x7F).contains(&b) => Some(InputEvent::PromptInput(b)),
+                _ => None,
+            },
             Mode::Navigation => {
                 // Navigation mode input handling
                 match byte {
@@ -99,12 +118,29 @@ impl Input {
                     b'/' if app.pane() == Pane::Cpuid => Some(InputEvent::EnterSearchMode),
                     #[cfg(feature = "msr")]
                     b'/' if app.pane() == Pane::Msr => Some(InputEvent::EnterSearchMode),
+                    b':' if app.has_prompt() => Some(InputEvent::EnterPromptMode),
+                    #[cfg(feature = "msr")]
+                    0x0D if app.pane() == Pane::Msr => Some(InputEvent::ToggleExpand), // Enter
+                    #[cfg(feature = "msr")]
+                    0x09 if app.pane() == Pane::Msr => Some(InputEvent::ToggleView), // Tab
+                    0x0D if app.pane() == Pane::Paging => Some(InputEvent::Descend), // Enter
+                    0x7F | 0x08 if app.pane() == Pane::Paging => Some(InputEvent::Ascend), // Backspace/DEL
                     b'c' => Some(InputEvent::SelectPane(Pane::Cpuid)),
                     b'f' => Some(InputEvent::SelectPane(Pane::Fpu)),
                     b'x' => Some(InputEvent::SelectPane(Pane::Xsave)),
                     b't' => Some(InputEvent::SelectPane(Pane::Timer)),
                     #[cfg(feature = "msr")]
                     b'm' => Some(InputEvent::SelectPane(Pane::Msr)),
+                    b'u' => Some(InputEvent::SelectPane(Pane::Pkru)),
+                    b'v' => Some(InputEvent::SelectPane(Pane::Vuln)),
+                    b's' => Some(InputEvent::SelectPane(Pane::System)),
+                    b'd' => Some(InputEvent::SelectPane(Pane::Descriptors)),
+                    b'p' => Some(InputEvent::SelectPane(Pane::Paging)),
+                    b'h' => Some(InputEvent::SelectPane(Pane::Hex)),
+                    b'b' => Some(InputEvent::SelectPane(Pane::Boot)),
+                    b'a' => Some(InputEvent::SelectPane(Pane::Allocator)),
+                    b'i' => Some(InputEvent::SelectPane(Pane::Irq)),
+                    b'l' => Some(InputEvent::SelectPane(Pane::Apic)),
                     b'j' => Some(InputEvent::ScrollDown),
                     b'k' => Some(InputEvent::ScrollUp),
                     b'G' => Some(InputEvent::ScrollToBottom),
@@ -1,12 +1,69 @@
+use crate::crash;
+use crate::gdt::DOUBLE_FAULT_IST_INDEX;
 use crate::ioapic::{self, COM1_VECTOR};
-use crate::lapic::{ERROR_VECTOR, Lapic, SPURIOUS_VECTOR, TARGET_TIMER_HZ, TIMER_VECTOR};
+use crate::lapic::{self, ERROR_VECTOR, Lapic, SPURIOUS_VECTOR, TARGET_TIMER_HZ, TIMER_VECTOR};
+use crate::mca;
 use crate::memory;
+use crate::recovery;
 use crate::serial;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::naked_asm;
 use core::borrow::BorrowMut;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
 use spin::Once;
+use x86_64::VirtAddr;
 use x86_64::instructions::interrupts;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::idt::{ExceptionVector, InterruptDescriptorTable, InterruptStackFrame};
+
+/// Mnemonics of the architectural exceptions, indexed by vector
+pub const EXCEPTIONS: [&str; 32] = [
+    "#DE", "#DB", "NMI", "#BP", "#OF", "#BR", "#UD", "#NM", "#DF", "", "#TS", "#NP", "#SS", "#GP",
+    "#PF", "", "#MF", "#AC", "#MC", "#XM", "#VE", "#CP", "", "", "", "", "", "", "#HV", "#VC",
+    "#SX", "",
+];
+
+/// Names of the architectural exceptions, indexed by vector
+pub const EXCEPTION_NAMES: [&str; 32] = [
+    "Divide Error",
+    "Debug",
+    "Non-maskable Interrupt",
+    "Breakpoint",
+    "Overflow",
+    "BOUND Range Exceeded",
+    "Invalid Opcode",
+    "Device Not Available",
+    "Double Fault",
+    "Coprocessor Segment Overrun",
+    "Invalid TSS",
+    "Segment Not Present",
+    "Stack-Segment Fault",
+    "General Protection",
+    "Page Fault",
+    "Reserved",
+    "x87 Floating-Point",
+    "Alignment Check",
+    "Machine Check",
+    "SIMD Floating-Point",
+    "Virtualization",
+    "Control Protection",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Hypervisor Injection",
+    "VMM Communication",
+    "Security",
+    "Reserved",
+];
+
+/// Interrupts taken per vector since boot
+static COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+/// LAPIC error status bits reported by error interrupts since boot
+static LAPIC_ERRORS: AtomicU8 = AtomicU8::new(0);
 
 static IDT: Once<InterruptDescriptorTable> = Once::new();
 static TICK_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -20,11 +77,26 @@ pub fn tick_count() -> usize {
     TICK_COUNT.load(Ordering::Relaxed)
 }
 
+/// Interrupts taken per vector since boot
+pub fn counts() -> [u64; 256] {
+    core::array::from_fn(|vector| COUNTS[vector].load(Ordering::Relaxed))
+}
+
+/// LAPIC error status bits reported since boot
+pub fn lapic_errors() -> u8 {
+    LAPIC_ERRORS.load(Ordering::Relaxed)
+}
+
+fn count(vector: u8) {
+    COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
 fn lapic() -> Lapic {
     Lapic::new()
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_sf: InterruptStackFrame) {
+    count(TIMER_VECTOR);
     let ticks = TICK_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
 
     if ticks.is_multiple_of(TICKS_PER_SECOND) {
@@ -35,6 +107,7 @@ extern "x86-interrupt" fn timer_interrupt_handler(_sf: InterruptStackFrame) {
 }
 
 extern "x86-interrupt" fn com1_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    count(COM1_VECTOR);
     let mut queue = serial::RX_QUEUE.lock();
     let queue = queue.borrow_mut();
     let (mut prod, _cons) = queue.split();
@@ -47,13 +120,250 @@ extern "x86-interrupt" fn com1_interrupt_handler(_stack_frame: InterruptStackFra
 }
 
 extern "x86-interrupt" fn error_interrupt_handler(_sf: InterruptStackFrame) {
-    lapic().eoi();
+    count(ERROR_VECTOR);
+    let mut lapic = lapic();
+    LAPIC_ERRORS.fetch_or(lapic.error_flags(), Ordering::Relaxed);
+    lapic.eoi();
 }
 
 extern "x86-interrupt" fn spurious_interrupt_handler(_sf: InterruptStackFrame) {
+    count(SPURIOUS_VECTOR);
     lapic().eoi();
 }
 
+/// Exceptions a guarded access can raise. NMI, #DB, #BP and #MC are not
+/// caused by the interrupted instruction and never resume a guarded access.
+fn recoverable(vector: u8) -> bool {
+    vector == ExceptionVector::InvalidOpcode as u8
+        || vector == ExceptionVector::GeneralProtection as u8
+        || vector == ExceptionVector::Page as u8
+}
+
+/// General purpose registers of the interrupted code, in the order the
+/// entry stub leaves them on the stack
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Everything the entry stubs and the CPU pushed for an exception
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub registers: Registers,
+    pub vector: u64,
+    /// Pushed by the CPU, or 0 by the stub for exceptions without one
+    pub error_code: u64,
+    pub frame: InterruptStackFrame,
+}
+
+impl ExceptionFrame {
+    /// The error code, None for exceptions that do not push one
+    pub fn error_code(&self) -> Option<u64> {
+        ERROR_CODE_VECTORS
+            .contains(&(self.vector as u8))
+            .then_some(self.error_code)
+    }
+}
+
+/// Exceptions for which the CPU pushes an error code
+const ERROR_CODE_VECTORS: [u8; 10] = [8, 10, 11, 12, 13, 14, 17, 21, 29, 30];
+
+/// Entry stubs for exceptions without an error code: push a 0 in its place
+/// and the vector, then save the registers in `exception_entry`
+macro_rules! exception_stubs {
+    ($($stub:ident => $vector:ident),* $(,)?) => {
+        $(
+            #[unsafe(naked)]
+            extern "C" fn $stub() {
+                naked_asm!(
+                    "push 0",
+                    "push {vector}",
+                    "jmp {entry}",
+                    vector = const ExceptionVector::$vector as u8,
+                    entry = sym exception_entry,
+                );
+            }
+        )*
+    };
+}
+
+/// Same as `exception_stubs`, for exceptions pushing an error code
+macro_rules! exception_stubs_with_error_code {
+    ($($stub:ident => $vector:ident),* $(,)?) => {
+        $(
+            #[unsafe(naked)]
+            extern "C" fn $stub() {
+                naked_asm!(
+                    "push {vector}",
+                    "jmp {entry}",
+                    vector = const ExceptionVector::$vector as u8,
+                    entry = sym exception_entry,
+                );
+            }
+        )*
+    };
+}
+
+exception_stubs! {
+    divide_error_handler => Division,
+    debug_handler => Debug,
+    nmi_handler => NonMaskableInterrupt,
+    breakpoint_handler => Breakpoint,
+    overflow_handler => Overflow,
+    bound_range_handler => BoundRange,
+    invalid_opcode_handler => InvalidOpcode,
+    device_not_available_handler => DeviceNotAvailable,
+    x87_floating_point_handler => X87FloatingPoint,
+    simd_floating_point_handler => SimdFloatingPoint,
+    virtualization_handler => Virtualization,
+    hv_injection_handler => HypervisorInjection,
+}
+
+exception_stubs_with_error_code! {
+    invalid_tss_handler => InvalidTss,
+    segment_not_present_handler => SegmentNotPresent,
+    stack_segment_fault_handler => Stack,
+    general_protection_fault_handler => GeneralProtection,
+    page_fault_handler => Page,
+    alignment_check_handler => AlignmentCheck,
+    control_protection_handler => ControlProtection,
+    vmm_communication_handler => VmmCommunication,
+    security_handler => Security,
+    // runs on its own IST stack, so a kernel stack overflow ends here
+    // instead of in a triple fault
+    double_fault_handler => Double,
+}
+
+/// Save the general purpose registers into an `ExceptionFrame` and call
+/// `exception_handler` with it. The 22 qwords on the stack keep it 16-byte
+/// aligned for the call.
+#[unsafe(naked)]
+extern "C" fn exception_entry() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "cld",
+        "mov rdi, rsp",
+        "call {handler}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        // the vector and the error code
+        "add rsp, 16",
+        "iretq",
+        handler = sym exception_handler,
+    );
+}
+
+/// Returns only when a guarded instruction raised a recoverable exception,
+/// anything else ends in the crash screen
+extern "C" fn exception_handler(frame: &mut ExceptionFrame) {
+    let vector = frame.vector as u8;
+    count(vector);
+    if recoverable(vector) && recovery::recover(&mut frame.frame, vector, frame.error_code) {
+        return;
+    }
+    crash::report(frame);
+}
+
+/// Installed by address: the IDT types #MC as diverging, but it can return
+/// when MCG_STATUS.RIPV says the interrupted context is intact
+extern "x86-interrupt" fn machine_check_handler(_sf: InterruptStackFrame) {
+    count(ExceptionVector::MachineCheck as u8);
+    let mc = mca::capture();
+    if mc.is_recoverable() {
+        mca::acknowledge(mc);
+        return;
+    }
+
+    // leave the TUI screen, nothing here may allocate
+    let mut port = serial::port();
+    let _ = write!(port, "\x1b[2J\x1b[H\r\nMACHINE CHECK\r\n");
+    let _ = mc.write_report(&mut port);
+    panic!("unrecoverable machine check");
+}
+
+/// Addresses and names of the handlers `init` installs, to label IDT gates
+pub fn handlers() -> Vec<(u64, &'static str)> {
+    macro_rules! named {
+        ($($handler:ident),* $(,)?) => {
+            vec![$((
+                VirtAddr::from_ptr($handler as *const ()).as_u64(),
+                stringify!($handler),
+            )),*]
+        };
+    }
+    named![
+        divide_error_handler,
+        debug_handler,
+        nmi_handler,
+        breakpoint_handler,
+        overflow_handler,
+        bound_range_handler,
+        invalid_opcode_handler,
+        device_not_available_handler,
+        double_fault_handler,
+        invalid_tss_handler,
+        segment_not_present_handler,
+        stack_segment_fault_handler,
+        general_protection_fault_handler,
+        page_fault_handler,
+        x87_floating_point_handler,
+        alignment_check_handler,
+        machine_check_handler,
+        simd_floating_point_handler,
+        virtualization_handler,
+        control_protection_handler,
+        hv_injection_handler,
+        vmm_communication_handler,
+        security_handler,
+        timer_interrupt_handler,
+        error_interrupt_handler,
+        spurious_interrupt_handler,
+        com1_interrupt_handler,
+    ]
+}
+
 pub fn init(mappings: &memory::Mappings) {
     // ioapic is configured to rerouted to BSP LAPIC
     ioapic::disable_pic();
@@ -62,6 +372,53 @@ pub fn init(mappings: &memory::Mappings) {
     // the IDT is global and can be shared across BSP and APs, so we init once
     let idt = IDT.call_once(|| {
         let mut idt = InterruptDescriptorTable::new();
+        let stub = |handler: extern "C" fn()| VirtAddr::from_ptr(handler as *const ());
+        unsafe {
+            idt.divide_error
+                .set_handler_addr(stub(divide_error_handler));
+            idt.debug.set_handler_addr(stub(debug_handler));
+            idt.non_maskable_interrupt
+                .set_handler_addr(stub(nmi_handler));
+            idt.breakpoint.set_handler_addr(stub(breakpoint_handler));
+            idt.overflow.set_handler_addr(stub(overflow_handler));
+            idt.bound_range_exceeded
+                .set_handler_addr(stub(bound_range_handler));
+            idt.invalid_opcode
+                .set_handler_addr(stub(invalid_opcode_handler));
+            idt.device_not_available
+                .set_handler_addr(stub(device_not_available_handler));
+            idt.double_fault
+                .set_handler_addr(stub(double_fault_handler))
+                .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+            idt.invalid_tss.set_handler_addr(stub(invalid_tss_handler));
+            idt.segment_not_present
+                .set_handler_addr(stub(segment_not_present_handler));
+            idt.stack_segment_fault
+                .set_handler_addr(stub(stack_segment_fault_handler));
+            idt.general_protection_fault
+                .set_handler_addr(stub(general_protection_fault_handler));
+            idt.page_fault.set_handler_addr(stub(page_fault_handler));
+            idt.x87_floating_point
+                .set_handler_addr(stub(x87_floating_point_handler));
+            idt.alignment_check
+                .set_handler_addr(stub(alignment_check_handler));
+            idt.simd_floating_point
+                .set_handler_addr(stub(simd_floating_point_handler));
+            idt.virtualization
+                .set_handler_addr(stub(virtualization_handler));
+            idt.cp_protection_exception
+                .set_handler_addr(stub(control_protection_handler));
+            idt.hv_injection_exception
+                .set_handler_addr(stub(hv_injection_handler));
+            idt.vmm_communication_exception
+                .set_handler_addr(stub(vmm_communication_handler));
+            idt.security_exception
+                .set_handler_addr(stub(security_handler));
+        }
+        unsafe {
+            idt.machine_check
+                .set_handler_addr(VirtAddr::from_ptr(machine_check_handler as *const ()));
+        }
         idt[TIMER_VECTOR].set_handler_fn(timer_interrupt_handler);
         idt[ERROR_VECTOR].set_handler_fn(error_interrupt_handler);
         idt[SPURIOUS_VECTOR].set_handler_fn(spurious_interrupt_handler);
@@ -72,6 +429,8 @@ pub fn init(mappings: &memory::Mappings) {
 
     // load + enable in current CPU, this part should be loaded on BSP and APs
     idt.load();
+    mca::init();
+    lapic::init(mappings.lapic_base());
     let mut lapic = Lapic::new();
     lapic.enable();
     interrupts::enable();
@@ -7,7 +7,7 @@ use x86_64::{
     },
 };
 
-const IOAPIC_PHYS: u64 = 0xFEC0_0000;
+pub const IOAPIC_PHYS: u64 = 0xFEC0_0000;
 const IOAPIC_VIRT: u64 = 0xFFFF_FF00_FEC0_0000;
 
 const PIC_1_OFFSET: u8 = 0x20;
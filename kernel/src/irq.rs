@@ -0,0 +1,190 @@
+//! Interrupts taken per vector, with rates and their history over the last
+//! minute
+
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+
+use crate::interrupts::{self, EXCEPTION_NAMES, EXCEPTIONS};
+use crate::ioapic::COM1_VECTOR;
+use crate::lapic::{self, ERROR_VECTOR, SPURIOUS_VECTOR, TIMER_VECTOR};
+use crate::pane::{ScrollHints, Scrollable};
+
+/// Seconds of history kept and drawn for all interrupts
+const HISTORY: usize = 60;
+/// Seconds of history drawn per vector
+const ROW_HISTORY: usize = 16;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Vectors listed even while they never fired
+const DEVICE_VECTORS: [u8; 4] = [TIMER_VECTOR, ERROR_VECTOR, COM1_VECTOR, SPURIOUS_VECTOR];
+
+fn source(vector: u8) -> String {
+    match vector {
+        0..32 => format!(
+            "{} {}",
+            EXCEPTIONS[vector as usize], EXCEPTION_NAMES[vector as usize]
+        ),
+        TIMER_VECTOR => String::from("LAPIC timer"),
+        ERROR_VECTOR => String::from("LAPIC error"),
+        SPURIOUS_VECTOR => String::from("LAPIC spurious"),
+        COM1_VECTOR => String::from("COM1 via IOAPIC"),
+        _ => String::from("IPI or other"),
+    }
+}
+
+/// One bar per value scaled to the largest, right aligned in `width` columns
+fn sparkline(history: &VecDeque<u64>, width: usize) -> String {
+    let values: Vec<u64> = history
+        .iter()
+        .skip(history.len().saturating_sub(width))
+        .copied()
+        .collect();
+    let max = values.iter().copied().max().unwrap_or(0);
+    let mut line = " ".repeat(width - values.len());
+    for value in values {
+        line.push(match value {
+            0 => ' ',
+            _ => BARS[((value * BARS.len() as u64).div_ceil(max) - 1) as usize],
+        });
+    }
+    line
+}
+
+pub struct IrqPane {
+    scroll: ScrollHints,
+    /// Counts at the last refresh
+    counts: [u64; 256],
+    /// Interrupts per second over the last refresh period
+    rates: [u64; 256],
+    /// Rate of each vector per second, oldest first
+    history: [VecDeque<u64>; 256],
+    total_history: VecDeque<u64>,
+}
+
+impl IrqPane {
+    pub fn new() -> Self {
+        Self {
+            scroll: ScrollHints::default(),
+            counts: interrupts::counts(),
+            rates: [0; 256],
+            history: [const { VecDeque::new() }; 256],
+            total_history: VecDeque::new(),
+        }
+    }
+
+    /// Sample the counters, `seconds` having passed since the last call
+    pub fn refresh(&mut self, seconds: usize) {
+        let counts = interrupts::counts();
+        let seconds = seconds.max(1) as u64;
+        let mut total = 0;
+        for vector in 0..256 {
+            let rate = (counts[vector] - self.counts[vector]) / seconds;
+            self.rates[vector] = rate;
+            total += rate;
+            // vectors that never fired keep no history
+            if counts[vector] > 0 {
+                push_history(&mut self.history[vector], rate, seconds);
+            }
+        }
+        push_history(&mut self.total_history, total, seconds);
+        self.counts = counts;
+    }
+
+    fn row(&self, vector: u8) -> Line<'static> {
+        let index = vector as usize;
+        let line = Line::raw(format!(
+            "  0x{:02x}  {:<28}{:>10}{:>8}/s  {}",
+            vector,
+            source(vector),
+            self.counts[index],
+            self.rates[index],
+            sparkline(&self.history[index], ROW_HISTORY)
+        ));
+        // these only fire when something is wrong
+        let alarming = vector == ERROR_VECTOR || vector == SPURIOUS_VECTOR;
+        if alarming && self.rates[index] > 0 {
+            line.style(Style::default().fg(Color::Red))
+        } else {
+            line
+        }
+    }
+}
+
+/// Record `rate` for each of the `seconds` that passed
+fn push_history(history: &mut VecDeque<u64>, rate: u64, seconds: u64) {
+    for _ in 0..seconds.min(HISTORY as u64) {
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(rate);
+    }
+}
+
+impl Scrollable for IrqPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut IrqPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let total_rate: u64 = self.rates.iter().sum();
+        let peak = self.total_history.iter().copied().max().unwrap_or(0);
+
+        let mut lines = Vec::new();
+        lines.push(Line::styled(
+            "All interrupts, last minute",
+            Style::default().bold(),
+        ));
+        lines.push(Line::from(vec![
+            Span::raw("  "),
+            Span::styled(
+                sparkline(&self.total_history, HISTORY),
+                Style::default().fg(Color::Yellow),
+            ),
+        ]));
+        lines.push(Line::raw(format!(
+            "  {}/s now, peak {}/s",
+            total_rate, peak
+        )));
+        lines.push(Line::raw(""));
+
+        lines.push(Line::styled(
+            format!(
+                "  {:<6}{:<28}{:>10}{:>10}  Last {}s",
+                "Vector", "Source", "Count", "Rate", ROW_HISTORY
+            ),
+            Style::default().bold(),
+        ));
+        for vector in 0..=255u8 {
+            let listed = DEVICE_VECTORS.contains(&vector) || self.counts[vector as usize] > 0;
+            if listed {
+                lines.push(self.row(vector));
+            }
+        }
+        lines.push(Line::raw(""));
+
+        let errors = interrupts::lapic_errors();
+        lines.push(Line::raw(format!(
+            "  LAPIC errors seen: 0x{:02x} ({})",
+            errors,
+            lapic::error_names(errors)
+        )));
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
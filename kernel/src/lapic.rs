@@ -1,14 +1,77 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::arch::x86_64::_rdtsc;
-use x2apic::lapic::{LocalApic, LocalApicBuilder, TimerDivide, TimerMode, xapic_base};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::instructions::port::Port;
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, Page, PageTableFlags as Flags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
 
 use crate::cpuid;
+use crate::recovery::{self, Fault};
 
 pub const TIMER_VECTOR: u8 = 0x20;
 pub const ERROR_VECTOR: u8 = 0x21;
 pub const SPURIOUS_VECTOR: u8 = 0xFF;
 
-const APIC_TIMER_DIVIDE: TimerDivide = TimerDivide::Div16;
+/// Register offsets in the xAPIC page. In x2APIC mode register `offset` is
+/// MSR 0x800 + offset / 16.
+pub const REG_ID: u16 = 0x020;
+pub const REG_VERSION: u16 = 0x030;
+pub const REG_TPR: u16 = 0x080;
+pub const REG_APR: u16 = 0x090;
+pub const REG_PPR: u16 = 0x0A0;
+pub const REG_EOI: u16 = 0x0B0;
+pub const REG_SVR: u16 = 0x0F0;
+pub const REG_ISR: u16 = 0x100;
+pub const REG_TMR: u16 = 0x180;
+pub const REG_IRR: u16 = 0x200;
+pub const REG_ESR: u16 = 0x280;
+pub const REG_LVT_CMCI: u16 = 0x2F0;
+pub const REG_ICR: u16 = 0x300;
+/// Destination half of the ICR in xAPIC mode
+const REG_ICR_HIGH: u16 = 0x310;
+pub const REG_LVT_TIMER: u16 = 0x320;
+pub const REG_LVT_THERMAL: u16 = 0x330;
+pub const REG_LVT_PMC: u16 = 0x340;
+pub const REG_LVT_LINT0: u16 = 0x350;
+pub const REG_LVT_LINT1: u16 = 0x360;
+pub const REG_LVT_ERROR: u16 = 0x370;
+pub const REG_TIMER_INITIAL: u16 = 0x380;
+pub const REG_TIMER_CURRENT: u16 = 0x390;
+pub const REG_TIMER_DIVIDE: u16 = 0x3E0;
+
+/// Error Status Register bits
+const ERROR_NAMES: [&str; 8] = [
+    "send checksum",
+    "receive checksum",
+    "send accept",
+    "receive accept",
+    "redirectable IPI",
+    "send illegal vector",
+    "receive illegal vector",
+    "illegal register address",
+];
+
+const X2APIC_MSR_BASE: u32 = 0x800;
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Virtual address the xAPIC page is mapped at
+const XAPIC_VIRT: u64 = 0xFFFF_FF00_FEE0_0000;
+
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+const SVR_SOFTWARE_ENABLE: u32 = 1 << 8;
+/// Divide Configuration Register value for a divide by 16
+const TIMER_DIVIDE_16: u32 = 0b0011;
 
 /// Target timer frequency in Hz (ticks per second)
 pub const TARGET_TIMER_HZ: u64 = 100;
@@ -24,25 +87,156 @@ const PIT_CHANNEL2_DATA: u16 = 0x42;
 const PIT_COMMAND: u16 = 0x43;
 const PIT_CONTROL: u16 = 0x61;
 
-pub struct Lapic(LocalApic);
+/// How the local APIC registers are accessed
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ApicMode {
+    /// Memory mapped registers
+    XApic,
+    /// Registers are MSRs 0x800-0x8FF
+    X2Apic,
+}
+
+impl ApicMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ApicMode::XApic => "xAPIC",
+            ApicMode::X2Apic => "x2APIC",
+        }
+    }
+
+    /// Where the register at `offset` is accessed in this mode
+    pub fn register_location(self, offset: u16) -> String {
+        match self {
+            ApicMode::XApic => format!("MMIO +0x{:03X}", offset),
+            ApicMode::X2Apic => format!("MSR 0x{:03X}", x2apic_msr(offset)),
+        }
+    }
+}
+
+/// Mode the registers are accessed in, follows IA32_APIC_BASE.EXTD
+static X2APIC_MODE: AtomicBool = AtomicBool::new(false);
+/// Where `map_lapic` mapped the xAPIC page, 0 before
+static XAPIC_BASE: AtomicU64 = AtomicU64::new(0);
+/// Calibrated initial count, reprogrammed after a mode switch
+static TIMER_INITIAL: AtomicU32 = AtomicU32::new(FALLBACK_TIMER_INITIAL);
+
+fn x2apic_msr(offset: u16) -> u32 {
+    X2APIC_MSR_BASE + offset as u32 / 16
+}
+
+/// Current register access mode
+pub fn mode() -> ApicMode {
+    if X2APIC_MODE.load(Ordering::Relaxed) {
+        ApicMode::X2Apic
+    } else {
+        ApicMode::XApic
+    }
+}
+
+/// Map the xAPIC register page, needed in xAPIC mode and to switch to it
+pub fn map_lapic(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_alloc: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    let phys = PhysAddr::new(base_address());
+    let virt = VirtAddr::new(XAPIC_VIRT);
+
+    let page = Page::<Size4KiB>::containing_address(virt);
+    let frame = PhysFrame::<Size4KiB>::containing_address(phys);
+    let flags =
+        Flags::PRESENT | Flags::WRITABLE | Flags::NO_CACHE | Flags::NO_EXECUTE | Flags::GLOBAL;
+
+    unsafe {
+        mapper.map_to(page, frame, flags, frame_alloc)?.flush();
+    }
+
+    Ok(virt)
+}
+
+/// Enter the mode chosen at build time, x2APIC when the CPU supports it
+/// unless the `xapic` feature asks for xAPIC
+pub fn init(xapic_base: VirtAddr) {
+    XAPIC_BASE.store(xapic_base.as_u64(), Ordering::Relaxed);
+    // the firmware may have left the APIC in x2APIC mode already
+    X2APIC_MODE.store(apic_base() & APIC_BASE_X2APIC != 0, Ordering::Relaxed);
+    let mode = if cpuid::has_x2apic() && !cfg!(feature = "xapic") {
+        ApicMode::X2Apic
+    } else {
+        ApicMode::XApic
+    };
+    // a locked APIC stays in the mode the firmware left it in
+    let _ = enter_mode(mode);
+}
+
+/// Set IA32_APIC_BASE for `mode`. Leaving x2APIC mode has to go through
+/// the disabled state, which resets the APIC. Firmware or a hypervisor can
+/// lock the APIC in x2APIC mode, the write then raises a #GP.
+fn enter_mode(mode: ApicMode) -> Result<(), Fault> {
+    let original = recovery::rdmsr(IA32_APIC_BASE)?;
+    let base = original & !(APIC_BASE_ENABLE | APIC_BASE_X2APIC);
+    if mode == ApicMode::XApic && self::mode() == ApicMode::X2Apic {
+        recovery::wrmsr(IA32_APIC_BASE, base)?;
+    }
+    let value = match mode {
+        ApicMode::XApic => base | APIC_BASE_ENABLE,
+        ApicMode::X2Apic => base | APIC_BASE_ENABLE | APIC_BASE_X2APIC,
+    };
+    if let Err(fault) = recovery::wrmsr(IA32_APIC_BASE, value) {
+        // from the disabled state the original mode can be entered again
+        let _ = recovery::wrmsr(IA32_APIC_BASE, original);
+        return Err(fault);
+    }
+    X2APIC_MODE.store(mode == ApicMode::X2Apic, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Switch the register access mode at runtime and set the APIC up again
+pub fn set_mode(mode: ApicMode) -> Result<(), &'static str> {
+    if mode == ApicMode::X2Apic && !cpuid::has_x2apic() {
+        return Err("x2APIC is not supported");
+    }
+    without_interrupts(|| {
+        let entered = enter_mode(mode);
+        // the APIC was reset if the switch got halfway, set it up either way
+        Lapic::new().configure(TIMER_INITIAL.load(Ordering::Relaxed));
+        entered
+    })
+    .map_err(|_| match mode {
+        ApicMode::XApic => "switching to xAPIC refused",
+        ApicMode::X2Apic => "switching to x2APIC refused",
+    })
+}
+
+pub struct Lapic {
+    mode: ApicMode,
+}
 
 /// Calibrated LAPIC timer frequency in Hz
 static LAPIC_TIMER_FREQ_HZ: spin::Mutex<Option<u64>> = spin::Mutex::new(None);
 
 impl Lapic {
     pub fn new() -> Self {
-        let base: u64;
-        unsafe {
-            base = xapic_base();
+        Self { mode: mode() }
+    }
+
+    fn read(&self, offset: u16) -> u32 {
+        match self.mode {
+            ApicMode::XApic => {
+                let base = XAPIC_BASE.load(Ordering::Relaxed);
+                unsafe { core::ptr::read_volatile((base + offset as u64) as *const u32) }
+            }
+            ApicMode::X2Apic => unsafe { Msr::new(x2apic_msr(offset)).read() as u32 },
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u32) {
+        match self.mode {
+            ApicMode::XApic => {
+                let base = XAPIC_BASE.load(Ordering::Relaxed);
+                unsafe { core::ptr::write_volatile((base + offset as u64) as *mut u32, value) }
+            }
+            ApicMode::X2Apic => unsafe { Msr::new(x2apic_msr(offset)).write(value as u64) },
         }
-        let lapic = LocalApicBuilder::new()
-            .timer_vector(TIMER_VECTOR as usize)
-            .error_vector(ERROR_VECTOR as usize)
-            .spurious_vector(SPURIOUS_VECTOR as usize)
-            .set_xapic_base(base)
-            .build()
-            .unwrap();
-        Self(lapic)
     }
 
     pub fn enable(&mut self) {
@@ -50,62 +244,67 @@ impl Lapic {
             .calibrate_with_tsc()
             .or_else(|| self.calibrate_with_pit())
             .unwrap_or(FALLBACK_TIMER_INITIAL);
+        TIMER_INITIAL.store(initial_count, Ordering::Relaxed);
+        self.configure(initial_count);
+    }
 
-        unsafe {
-            self.0.enable();
-            self.0.set_timer_divide(APIC_TIMER_DIVIDE);
-            self.0.set_timer_mode(TimerMode::Periodic);
-            self.0.set_timer_initial(initial_count);
-            self.0.enable_timer();
-        }
+    /// Route the timer, error and spurious interrupts, mask the LINT pins
+    /// and start the periodic timer. A mode switch resets all of it.
+    fn configure(&mut self, initial_count: u32) {
+        self.write(REG_SVR, SVR_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+        self.write(REG_LVT_ERROR, ERROR_VECTOR as u32);
+        self.write(REG_LVT_LINT0, LVT_MASKED);
+        self.write(REG_LVT_LINT1, LVT_MASKED);
+        self.write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+        self.write(REG_LVT_TIMER, LVT_TIMER_PERIODIC | TIMER_VECTOR as u32);
+        self.write(REG_TIMER_INITIAL, initial_count);
+    }
+
+    /// Software enable the APIC with a masked one-shot timer, which counts
+    /// down from the initial count without raising an interrupt
+    fn prepare_calibration(&mut self) {
+        self.write(REG_SVR, SVR_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+        self.write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+        self.write(REG_LVT_TIMER, LVT_MASKED | TIMER_VECTOR as u32);
     }
 
     /// Calibrate the LAPIC timer using TSC as reference.
     fn calibrate_with_tsc(&mut self) -> Option<u32> {
         let tsc_freq = cpuid::tsc_frequency()?;
+        self.prepare_calibration();
 
-        unsafe {
-            self.0.enable();
-            self.0.set_timer_divide(APIC_TIMER_DIVIDE);
-            self.0.set_timer_mode(TimerMode::OneShot);
-            self.0.disable_timer();
-
-            let start_count: u32 = 0xFFFF_FFFF;
-            self.0.set_timer_initial(start_count);
+        let start_count: u32 = 0xFFFF_FFFF;
 
-            // Measure for ~10ms worth of TSC ticks
-            let calibration_tsc_ticks = tsc_freq / 100;
-            let tsc_start = _rdtsc();
-            self.0.enable_timer();
+        // Measure for ~10ms worth of TSC ticks
+        let calibration_tsc_ticks = tsc_freq / 100;
+        let tsc_start = unsafe { _rdtsc() };
+        self.write(REG_TIMER_INITIAL, start_count);
 
-            while _rdtsc() - tsc_start < calibration_tsc_ticks {}
+        while unsafe { _rdtsc() } - tsc_start < calibration_tsc_ticks {}
 
-            self.0.disable_timer();
-            let end_count = self.0.timer_current();
-            let elapsed_lapic_ticks = start_count - end_count;
+        let end_count = self.read(REG_TIMER_CURRENT);
+        self.write(REG_TIMER_INITIAL, 0);
+        let elapsed_lapic_ticks = start_count - end_count;
 
-            let lapic_freq = (elapsed_lapic_ticks as u64 * tsc_freq) / calibration_tsc_ticks;
-            *LAPIC_TIMER_FREQ_HZ.lock() = Some(lapic_freq);
+        let lapic_freq = (elapsed_lapic_ticks as u64 * tsc_freq) / calibration_tsc_ticks;
+        *LAPIC_TIMER_FREQ_HZ.lock() = Some(lapic_freq);
 
-            Some((lapic_freq / TARGET_TIMER_HZ) as u32)
-        }
+        Some((lapic_freq / TARGET_TIMER_HZ) as u32)
     }
 
     /// Calibrate the LAPIC timer using PIT channel 2 as reference.
     fn calibrate_with_pit(&mut self) -> Option<u32> {
-        unsafe {
-            self.0.enable();
-            self.0.set_timer_divide(APIC_TIMER_DIVIDE);
-            self.0.set_timer_mode(TimerMode::OneShot);
-            self.0.disable_timer();
+        self.prepare_calibration();
 
-            // Set up PIT channel 2 for ~10ms (11932 ticks at 1.193182 MHz)
-            let pit_ticks: u16 = (PIT_FREQUENCY / 100) as u16; // ~10ms
+        // Set up PIT channel 2 for ~10ms (11932 ticks at 1.193182 MHz)
+        let pit_ticks: u16 = (PIT_FREQUENCY / 100) as u16; // ~10ms
 
-            let mut control_port = Port::<u8>::new(PIT_CONTROL);
-            let mut command_port = Port::<u8>::new(PIT_COMMAND);
-            let mut channel2_port = Port::<u8>::new(PIT_CHANNEL2_DATA);
+        let mut control_port = Port::<u8>::new(PIT_CONTROL);
+        let mut command_port = Port::<u8>::new(PIT_COMMAND);
+        let mut channel2_port = Port::<u8>::new(PIT_CHANNEL2_DATA);
 
+        let start_count: u32 = 0xFFFF_FFFF;
+        unsafe {
             // Disable speaker, enable PIT channel 2 gate
             let control = control_port.read();
             control_port.write((control & 0xFC) | 0x01);
@@ -118,37 +317,96 @@ impl Lapic {
             channel2_port.write((pit_ticks & 0xFF) as u8);
             channel2_port.write((pit_ticks >> 8) as u8);
 
-            // Start LAPIC timer with max count
-            let start_count: u32 = 0xFFFF_FFFF;
-            self.0.set_timer_initial(start_count);
-
             // Reset PIT gate to start countdown
             let control = control_port.read();
             control_port.write(control & 0xFE); // Gate low
             control_port.write(control | 0x01); // Gate high - starts countdown
+        }
 
-            self.0.enable_timer();
+        // Start LAPIC timer with max count
+        self.write(REG_TIMER_INITIAL, start_count);
 
-            // Wait for PIT channel 2 output to go high (bit 5 of port 0x61)
-            while (control_port.read() & 0x20) == 0 {}
+        // Wait for PIT channel 2 output to go high (bit 5 of port 0x61)
+        while (unsafe { control_port.read() } & 0x20) == 0 {}
 
-            self.0.disable_timer();
-            let end_count = self.0.timer_current();
-            let elapsed_lapic_ticks = start_count - end_count;
+        let end_count = self.read(REG_TIMER_CURRENT);
+        self.write(REG_TIMER_INITIAL, 0);
+        let elapsed_lapic_ticks = start_count - end_count;
 
-            // Calculate LAPIC frequency: elapsed_ticks / (pit_ticks / PIT_FREQUENCY)
-            let lapic_freq = (elapsed_lapic_ticks as u64 * PIT_FREQUENCY) / pit_ticks as u64;
-            *LAPIC_TIMER_FREQ_HZ.lock() = Some(lapic_freq);
+        // Calculate LAPIC frequency: elapsed_ticks / (pit_ticks / PIT_FREQUENCY)
+        let lapic_freq = (elapsed_lapic_ticks as u64 * PIT_FREQUENCY) / pit_ticks as u64;
+        *LAPIC_TIMER_FREQ_HZ.lock() = Some(lapic_freq);
 
-            Some((lapic_freq / TARGET_TIMER_HZ) as u32)
-        }
+        Some((lapic_freq / TARGET_TIMER_HZ) as u32)
     }
 
     pub fn eoi(&mut self) {
-        unsafe {
-            self.0.end_of_interrupt();
+        self.write(REG_EOI, 0);
+    }
+
+    /// Error Status Register bits. The ESR latches errors when written, so
+    /// it is written before the read.
+    pub fn error_flags(&mut self) -> u8 {
+        self.write(REG_ESR, 0);
+        self.read(REG_ESR) as u8
+    }
+}
+
+/// Names of the error bits set in `esr`, "none" without any
+pub fn error_names(esr: u8) -> String {
+    if esr == 0 {
+        return String::from("none");
+    }
+    let names: Vec<&str> = (0..8)
+        .filter(|bit| esr & (1 << bit) != 0)
+        .map(|bit| ERROR_NAMES[bit])
+        .collect();
+    names.join(", ")
+}
+
+/// IA32_APIC_BASE: base address, enable, x2APIC and BSP bits
+pub fn apic_base() -> u64 {
+    recovery::rdmsr(IA32_APIC_BASE).unwrap_or(0)
+}
+
+/// Physical address of the xAPIC register page
+pub fn base_address() -> u64 {
+    apic_base() & APIC_BASE_ADDRESS
+}
+
+/// Read the register at xAPIC offset `offset`, None if it does not exist
+/// in the current mode, like the APR in x2APIC mode
+pub fn read_register(offset: u16) -> Option<u32> {
+    match mode() {
+        ApicMode::XApic if XAPIC_BASE.load(Ordering::Relaxed) == 0 => None,
+        ApicMode::XApic => Some(Lapic::new().read(offset)),
+        ApicMode::X2Apic => recovery::rdmsr(x2apic_msr(offset))
+            .ok()
+            .map(|value| value as u32),
+    }
+}
+
+/// The ICR with the destination in the upper half: one 64-bit MSR in
+/// x2APIC mode, two registers in xAPIC mode
+pub fn read_icr() -> Option<u64> {
+    match mode() {
+        ApicMode::XApic => {
+            let low = read_register(REG_ICR)?;
+            let high = read_register(REG_ICR_HIGH)?;
+            Some((high as u64) << 32 | low as u64)
         }
+        ApicMode::X2Apic => recovery::rdmsr(x2apic_msr(REG_ICR)).ok(),
+    }
+}
+
+/// The 256-bit ISR, TMR or IRR at `offset`, as eight 32-bit registers
+/// 16 bytes apart
+pub fn read_bitmap(offset: u16) -> Option<[u32; 8]> {
+    let mut bitmap = [0; 8];
+    for (i, word) in bitmap.iter_mut().enumerate() {
+        *word = read_register(offset + i as u16 * 0x10)?;
     }
+    Some(bitmap)
 }
 
 /// Returns the calibrated LAPIC timer frequency in Hz, if available.
@@ -167,20 +425,11 @@ pub struct LapicTimerRegs {
 
 /// Read the current LAPIC timer register values
 pub fn read_lapic_timer_regs() -> LapicTimerRegs {
-    use x86_64::registers::model_specific::Msr;
-
-    // x2APIC MSR addresses for timer registers
-    const MSR_LVT_TIMER: u32 = 0x832;
-    const MSR_TICR: u32 = 0x838;
-    const MSR_TCCR: u32 = 0x839;
-    const MSR_TDCR: u32 = 0x83E;
-
-    unsafe {
-        LapicTimerRegs {
-            lvt_timer: Msr::new(MSR_LVT_TIMER).read() as u32,
-            initial_count: Msr::new(MSR_TICR).read() as u32,
-            current_count: Msr::new(MSR_TCCR).read() as u32,
-            divide_config: Msr::new(MSR_TDCR).read() as u32,
-        }
+    let lapic = Lapic::new();
+    LapicTimerRegs {
+        lvt_timer: lapic.read(REG_LVT_TIMER),
+        initial_count: lapic.read(REG_TIMER_INITIAL),
+        current_count: lapic.read(REG_TIMER_CURRENT),
+        divide_config: lapic.read(REG_TIMER_DIVIDE),
     }
 }
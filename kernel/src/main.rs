@@ -1,29 +1,48 @@
 #![no_std] // don't link the Rust standard library
 #![no_main] // disable all Rust-level entry points
 #![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
 
 extern crate alloc;
 
 use app::App;
 use bootloader_api::config::{BootloaderConfig, Mapping};
 use bootloader_api::{BootInfo, entry_point};
+use core::alloc::Layout;
 use core::fmt::Write;
+use cpuid::CpuFeatures;
 
+mod allocator;
+mod apic;
 mod app;
+mod backtrace;
+mod boot;
 mod cpuid;
+mod crash;
+mod descriptors;
 mod fpu;
+mod gdt;
+mod hex;
 mod input;
 mod interrupts;
 mod ioapic;
+mod irq;
 mod lapic;
+mod mca;
 mod memory;
 #[cfg(feature = "msr")]
 mod msr;
+mod paging;
 mod pane;
+mod pkru;
 mod qemu;
 mod ratatui_backend;
+mod recovery;
 mod serial;
+mod symbols;
+mod system;
 mod timer;
+mod vuln;
 mod xsave;
 
 static BOOTLOADER_CONFIG: BootloaderConfig = {
@@ -37,18 +56,39 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let mut port = serial::port();
     writeln!(port, "boot info: {boot_info:#?}").unwrap();
 
+    let boot_info: &'static BootInfo = boot_info;
+    gdt::init();
     let mappings = memory::init(boot_info);
     interrupts::init(&mappings);
+    let protection = pkru::init(&CpuFeatures::new());
 
     writeln!(port, "init done").unwrap();
 
-    let mut app = App::new();
+    let mut app = App::new(boot_info, &mappings, protection);
     app.run();
 }
 
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    match memory::heap_usage() {
+        Some((size, used, free)) => panic!(
+            "out of memory: {} bytes aligned to {} requested, heap {} bytes, {} used, {} free",
+            layout.size(),
+            layout.align(),
+            size,
+            used,
+            free
+        ),
+        None => panic!(
+            "out of memory: {} bytes aligned to {} requested",
+            layout.size(),
+            layout.align()
+        ),
+    }
+}
+
 #[panic_handler]
 #[cfg(not(test))]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    let _ = writeln!(serial::port(), "PANIC: {info}");
-    qemu::exit(qemu::QemuExitCode::Failed);
+    crash::panic(info)
 }
@@ -0,0 +1,342 @@
+//! Machine check architecture: bank registers, MCi_STATUS decoding and the
+//! state captured by the #MC handler
+
+use core::fmt;
+
+use raw_cpuid::CpuId;
+use spin::Mutex;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+use crate::recovery;
+
+pub const MSR_MCG_CAP: u32 = 0x179;
+pub const MSR_MCG_STATUS: u32 = 0x17A;
+
+/// First bank register, banks are four MSRs each: CTL, STATUS, ADDR, MISC
+pub const MSR_MC0_CTL: u32 = 0x400;
+/// First CMCI control register, one per bank
+#[cfg(feature = "msr")]
+pub const MSR_MC0_CTL2: u32 = 0x280;
+
+/// Banks with MSRs below the VMX capability MSRs, MC31_MISC is 0x47F
+const MAX_BANKS: usize = 32;
+
+// MCG_CAP
+const MCG_CAP_COUNT: u64 = 0xFF;
+const MCG_CAP_CMCI_P: u64 = 1 << 10;
+
+// MCG_STATUS
+const MCG_STATUS_RIPV: u64 = 1 << 0;
+const MCG_STATUS_MCIP: u64 = 1 << 2;
+
+// MCi_STATUS
+const STATUS_VAL: u64 = 1 << 63;
+const STATUS_UC: u64 = 1 << 61;
+const STATUS_MISCV: u64 = 1 << 59;
+const STATUS_ADDRV: u64 = 1 << 58;
+const STATUS_PCC: u64 = 1 << 57;
+
+/// Flags of MCi_STATUS, high bit first
+const STATUS_FLAGS: [(&str, u8); 9] = [
+    ("VAL", 63),
+    ("OVER", 62),
+    ("UC", 61),
+    ("EN", 60),
+    ("MISCV", 59),
+    ("ADDRV", 58),
+    ("PCC", 57),
+    ("S", 56),
+    ("AR", 55),
+];
+
+/// Registers of one bank
+#[cfg(feature = "msr")]
+#[derive(Clone, Copy)]
+pub struct BankMsrs {
+    pub ctl: u32,
+    pub status: u32,
+    pub addr: u32,
+    pub misc: u32,
+    /// Present if the processor supports CMCI
+    pub ctl2: Option<u32>,
+}
+
+/// MCi_CTL of `bank`, followed by its STATUS, ADDR and MISC
+fn bank_base(bank: u32) -> u32 {
+    MSR_MC0_CTL + bank * 4
+}
+
+#[cfg(feature = "msr")]
+pub fn bank_msrs(bank: u32, cmci: bool) -> BankMsrs {
+    let ctl = bank_base(bank);
+    BankMsrs {
+        ctl,
+        status: ctl + 1,
+        addr: ctl + 2,
+        misc: ctl + 3,
+        ctl2: cmci.then_some(MSR_MC0_CTL2 + bank),
+    }
+}
+
+/// Number of banks and whether CMCI is supported, from MCG_CAP. The count
+/// is capped at `MAX_BANKS`, the pane and the #MC handler use the same.
+pub fn capabilities(mcg_cap: u64) -> (u32, bool) {
+    (
+        ((mcg_cap & MCG_CAP_COUNT) as u32).min(MAX_BANKS as u32),
+        mcg_cap & MCG_CAP_CMCI_P != 0,
+    )
+}
+
+/// Enable #MC delivery. Without CR4.MCE a machine check shuts the processor
+/// down. Only done with the bank MSRs present, the handler reads them.
+pub fn init() {
+    let has_mca = CpuId::new()
+        .get_feature_info()
+        .is_some_and(|fi| fi.has_mce() && fi.has_mca());
+    if has_mca {
+        unsafe { Cr4::update(|cr4| cr4.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION)) };
+    }
+}
+
+/// MCi_STATUS flags set in `status`, e.g. `VAL UC EN ADDRV`
+pub struct StatusFlags(pub u64);
+
+impl fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (name, bit) in STATUS_FLAGS {
+            if self.0 & (1 << bit) != 0 {
+                if !first {
+                    f.write_str(" ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        if first {
+            f.write_str("-")?;
+        }
+        Ok(())
+    }
+}
+
+/// Architectural MCA error code from MCi_STATUS[15:0]
+pub struct ErrorCode(pub u16);
+
+impl ErrorCode {
+    fn transaction(tt: u16) -> &'static str {
+        match tt {
+            0 => "instr",
+            1 => "data",
+            _ => "generic",
+        }
+    }
+
+    fn level(ll: u16) -> &'static str {
+        match ll {
+            0 => "L0",
+            1 => "L1",
+            2 => "L2",
+            _ => "generic",
+        }
+    }
+
+    fn request(rrrr: u16) -> &'static str {
+        match rrrr {
+            0 => "error",
+            1 => "read",
+            2 => "write",
+            3 => "data read",
+            4 => "data write",
+            5 => "instr fetch",
+            6 => "prefetch",
+            7 => "eviction",
+            8 => "snoop",
+            _ => "unknown",
+        }
+    }
+
+    fn memory_transaction(mmm: u16) -> &'static str {
+        match mmm {
+            0 => "generic",
+            1 => "read",
+            2 => "write",
+            3 => "address/command",
+            4 => "scrubbing",
+            _ => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.0;
+        // bit 12 only filters corrected error reporting
+        let compound = code & !(1 << 12);
+        let (tt, ll, rrrr) = ((code >> 2) & 0b11, code & 0b11, (code >> 4) & 0xF);
+
+        match code {
+            0x0000 => f.write_str("no error"),
+            0x0001 => f.write_str("unclassified"),
+            0x0002 => f.write_str("microcode ROM parity"),
+            0x0003 => f.write_str("external"),
+            0x0004 => f.write_str("FRC"),
+            0x0005 => f.write_str("internal parity"),
+            0x0006 => f.write_str("SMM handler code access violation"),
+            0x0400 => f.write_str("internal timer"),
+            0x0E0B => f.write_str("I/O"),
+            0x0401..=0x07FF => f.write_str("internal unclassified"),
+            _ if compound & 0xEFFC == 0x000C => {
+                write!(f, "{} generic cache hierarchy", Self::level(ll))
+            }
+            _ if compound & 0xEFF0 == 0x0010 => {
+                write!(f, "{} {} TLB", Self::level(ll), Self::transaction(tt))
+            }
+            _ if compound & 0xEF80 == 0x0080 => {
+                let channel = code & 0xF;
+                write!(
+                    f,
+                    "memory controller {}",
+                    Self::memory_transaction((code >> 4) & 0b111)
+                )?;
+                if channel != 0xF {
+                    write!(f, ", channel {}", channel)?;
+                }
+                Ok(())
+            }
+            _ if compound & 0xEF00 == 0x0100 => write!(
+                f,
+                "{} {} {} cache",
+                Self::level(ll),
+                Self::transaction(tt),
+                Self::request(rrrr)
+            ),
+            _ if compound & 0xE800 == 0x0800 => write!(
+                f,
+                "{} bus/interconnect {}{}",
+                Self::level(ll),
+                Self::request(rrrr),
+                if code & (1 << 8) != 0 {
+                    ", timeout"
+                } else {
+                    ""
+                }
+            ),
+            _ => f.write_str("model specific"),
+        }
+    }
+}
+
+/// A bank with a valid error, as captured by the #MC handler
+#[derive(Clone, Copy)]
+pub struct BankState {
+    pub bank: u8,
+    pub status: u64,
+    pub addr: Option<u64>,
+    pub misc: Option<u64>,
+}
+
+impl fmt::Display for BankState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "MC{} STATUS=0x{:016x} [{}] {}",
+            self.bank,
+            self.status,
+            StatusFlags(self.status),
+            ErrorCode(self.status as u16)
+        )?;
+        if let Some(addr) = self.addr {
+            write!(f, " ADDR=0x{:x}", addr)?;
+        }
+        if let Some(misc) = self.misc {
+            write!(f, " MISC=0x{:x}", misc)?;
+        }
+        Ok(())
+    }
+}
+
+/// Machine check state captured when #MC was raised
+#[derive(Clone)]
+pub struct MachineCheck {
+    pub mcg_status: u64,
+    pub banks: heapless::Vec<BankState, MAX_BANKS>,
+}
+
+impl MachineCheck {
+    /// Execution can resume if the interrupted IP is valid and no bank
+    /// reports an uncorrected error or corrupted processor context
+    pub fn is_recoverable(&self) -> bool {
+        self.mcg_status & MCG_STATUS_RIPV != 0
+            && self
+                .banks
+                .iter()
+                .all(|b| b.status & (STATUS_UC | STATUS_PCC) == 0)
+    }
+
+    /// Write the captured state as terminal lines, for the serial console
+    pub fn write_report(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "MCG_STATUS=0x{:016x}\r\n", self.mcg_status)?;
+        if self.banks.is_empty() {
+            write!(out, "no bank reports a valid error\r\n")?;
+        }
+        for bank in &self.banks {
+            write!(out, "{}\r\n", bank)?;
+        }
+        Ok(())
+    }
+}
+
+/// Last machine check the handler recovered from
+static LAST_MACHINE_CHECK: Mutex<Option<MachineCheck>> = Mutex::new(None);
+
+#[cfg(feature = "msr")]
+pub fn last_machine_check() -> Option<MachineCheck> {
+    LAST_MACHINE_CHECK.lock().clone()
+}
+
+/// Read MCG_STATUS and every bank with a valid error. Only touches MSRs
+/// MCG_CAP reports and skips any read that faults anyway, does not allocate.
+pub fn capture() -> MachineCheck {
+    let (count, _) = capabilities(recovery::rdmsr(MSR_MCG_CAP).unwrap_or(0));
+    let mut banks = heapless::Vec::new();
+
+    for bank in 0..count {
+        let base = bank_base(bank);
+        let status = recovery::rdmsr(base + 1).unwrap_or(0);
+        if status & STATUS_VAL == 0 {
+            continue;
+        }
+        let addr = (status & STATUS_ADDRV != 0)
+            .then(|| recovery::rdmsr(base + 2).ok())
+            .flatten();
+        let misc = (status & STATUS_MISCV != 0)
+            .then(|| recovery::rdmsr(base + 3).ok())
+            .flatten();
+        let _ = banks.push(BankState {
+            bank: bank as u8,
+            status,
+            addr,
+            misc,
+        });
+    }
+
+    MachineCheck {
+        mcg_status: recovery::rdmsr(MSR_MCG_STATUS).unwrap_or(0),
+        banks,
+    }
+}
+
+/// Clear the logged errors and MCG_STATUS.MCIP so the next #MC is not a
+/// shutdown, then keep the state for display
+pub fn acknowledge(mc: MachineCheck) {
+    for bank in &mc.banks {
+        let _ = recovery::wrmsr(bank_base(bank.bank as u32) + 1, 0);
+    }
+    let _ = recovery::wrmsr(MSR_MCG_STATUS, mc.mcg_status & !MCG_STATUS_MCIP);
+
+    // the pane may hold the lock when #MC arrives, drop the record then
+    if let Some(mut last) = LAST_MACHINE_CHECK.try_lock() {
+        *last = Some(mc);
+    }
+}
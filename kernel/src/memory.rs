@@ -1,20 +1,101 @@
+mod frames;
+mod heap;
+
+pub use frames::BitmapFrameAllocator;
+pub use heap::HeapStats;
+
+use heap::KernelHeap;
+
 use crate::ioapic;
+use crate::lapic;
+use alloc::vec::Vec;
 use bootloader_api::BootInfo;
-use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
-use linked_list_allocator::LockedHeap;
+use spin::{Mutex, MutexGuard, Once};
 use x86_64::registers::control::Cr3;
-use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::mapper::{FlagUpdateError, MapToError, TranslateResult};
+use x86_64::structures::paging::page_table::PageTableEntry;
 use x86_64::structures::paging::{
-    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+    Size4KiB, Translate,
 };
 use x86_64::{PhysAddr, VirtAddr};
 
 pub const HEAP_START: usize = 0x_1234_abcd_0000;
-// pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+/// Heap mapped at boot, it grows on demand up to HEAP_MAX_SIZE
 pub const HEAP_SIZE: usize = 512 * 1024; // 512 KiB
+/// Virtual space reserved for the heap after HEAP_START
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+
+/// Protection key bits 62:59 of a leaf page table entry
+const PROTECTION_KEY_SHIFT: u64 = 59;
+const PROTECTION_KEY_MASK: u64 = 0xF << PROTECTION_KEY_SHIFT;
+
+/// PAT bit of a 4 KiB page entry, where huge pages have PS
+const PAT_4K: u64 = 1 << 7;
+/// PAT bit of a 2 MiB or 1 GiB page entry
+const PAT_HUGE: u64 = 1 << 12;
+
+/// Names of the page table levels, from the root down
+pub const LEVEL_NAMES: [&str; 4] = ["PML4", "PDPT", "PD", "PT"];
+
+/// An entry visited by a page walk
+pub struct WalkStep {
+    /// 0 for the PML4 down to 3 for the PT
+    pub level: usize,
+    pub index: usize,
+    /// Physical address of the table holding the entry
+    pub table: PhysAddr,
+    pub entry: u64,
+}
+
+/// Page a page walk ended in
+pub struct Leaf {
+    pub phys: PhysAddr,
+    pub size: u64,
+    /// PAT entry selected by the PAT, PCD and PWT bits
+    pub pat_index: u8,
+}
+
+/// Bytes mapped by one entry at `level`, 0 being the PML4
+pub fn page_size(level: usize) -> u64 {
+    1 << (12 + 9 * (3 - level))
+}
+
+/// Entry value including the address bits
+pub fn raw_entry(entry: &PageTableEntry) -> u64 {
+    entry.addr().as_u64() | entry.flags().bits()
+}
+
+/// PAT index of a leaf entry: PAT is bit 7 in a 4 KiB entry and bit 12 in a
+/// huge page entry
+pub fn pat_index(entry: u64, huge: bool) -> u8 {
+    let pat = if huge { PAT_HUGE } else { PAT_4K };
+    let flags = PageTableFlags::from_bits_retain(entry);
+    flags.contains(PageTableFlags::WRITE_THROUGH) as u8
+        | (flags.contains(PageTableFlags::NO_CACHE) as u8) << 1
+        | ((entry & pat != 0) as u8) << 2
+}
 
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: KernelHeap = KernelHeap::empty();
+
+static PAGE_TABLES: Once<Mutex<PageTableManager>> = Once::new();
+
+static FRAMES: Once<Mutex<BitmapFrameAllocator>> = Once::new();
+
+/// Returns the page table manager, available after `init`
+pub fn page_tables() -> MutexGuard<'static, PageTableManager> {
+    PAGE_TABLES
+        .get()
+        .expect("page tables not initialized")
+        .lock()
+}
+
+/// Returns the physical frame allocator, available after `init`. Take it
+/// after `page_tables` when both are needed.
+pub fn frames() -> MutexGuard<'static, BitmapFrameAllocator> {
+    FRAMES.get().expect("frames not initialized").lock()
+}
 
 pub struct UninitPageTableManager;
 
@@ -26,6 +107,127 @@ impl PageTableManager {
     pub fn mapper(&mut self) -> &mut OffsetPageTable<'static> {
         &mut self.mapper
     }
+
+    /// Tag a 4 KiB page with a protection key, or clear the tag with `None`.
+    ///
+    /// PKRU only governs user-mode addresses, so the page is made user
+    /// accessible while tagged. U/S is also set on the upper levels of the
+    /// walk; this is harmless since the leaf entry still decides.
+    pub fn set_protection_key(
+        &mut self,
+        page: Page<Size4KiB>,
+        key: Option<u8>,
+    ) -> Result<(), FlagUpdateError> {
+        let TranslateResult::Mapped { flags, .. } = self.mapper.translate(page.start_address())
+        else {
+            return Err(FlagUpdateError::PageNotMapped);
+        };
+
+        let user = PageTableFlags::USER_ACCESSIBLE;
+        let mut bits = flags.bits() & !PROTECTION_KEY_MASK & !user.bits();
+        if let Some(key) = key {
+            bits |= ((key as u64) << PROTECTION_KEY_SHIFT) & PROTECTION_KEY_MASK | user.bits();
+            self.set_parent_flags(page, user)?;
+        }
+        let flags = PageTableFlags::from_bits_retain(bits);
+
+        unsafe { self.mapper.update_flags(page, flags)?.flush() };
+        Ok(())
+    }
+
+    /// Start of the bootloader's mapping of all physical memory
+    pub fn phys_offset(&self) -> VirtAddr {
+        self.mapper.phys_offset()
+    }
+
+    /// Physical address of the PML4
+    pub fn root(&self) -> PhysAddr {
+        Cr3::read().0.start_address()
+    }
+
+    /// Page table at physical address `table`
+    pub fn table(&self, table: PhysAddr) -> &PageTable {
+        unsafe { &*(self.phys_offset() + table.as_u64()).as_ptr() }
+    }
+
+    /// Walk the page tables for `addr`, returning every entry visited and
+    /// the page it maps to. The leaf is `None` if the address is not mapped.
+    pub fn walk(&self, addr: VirtAddr) -> (Vec<WalkStep>, Option<Leaf>) {
+        let indices = [
+            addr.p4_index(),
+            addr.p3_index(),
+            addr.p2_index(),
+            addr.p1_index(),
+        ];
+
+        let mut steps = Vec::new();
+        let mut table = self.root();
+        for (level, index) in indices.into_iter().enumerate() {
+            let entry = &self.table(table)[index];
+            let flags = entry.flags();
+            steps.push(WalkStep {
+                level,
+                index: usize::from(index),
+                table,
+                entry: raw_entry(entry),
+            });
+            if !flags.contains(PageTableFlags::PRESENT) {
+                return (steps, None);
+            }
+
+            let huge = level > 0 && flags.contains(PageTableFlags::HUGE_PAGE);
+            if level == 3 || huge {
+                let size = page_size(level);
+                let base = entry.addr().as_u64() & !(size - 1);
+                let leaf = Leaf {
+                    phys: PhysAddr::new(base | (addr.as_u64() & (size - 1))),
+                    size,
+                    pat_index: pat_index(raw_entry(entry), huge),
+                };
+                return (steps, Some(leaf));
+            }
+            table = entry.addr();
+        }
+        (steps, None)
+    }
+
+    /// Walk the page tables for `addr` and return the physical address it maps
+    /// to with the PAT index selected by the PAT, PCD and PWT bits of the leaf
+    /// entry. `None` if the address is not mapped.
+    #[cfg(feature = "msr")]
+    pub fn translate_with_pat(&self, addr: VirtAddr) -> Option<(PhysAddr, u8)> {
+        let (_, leaf) = self.walk(addr);
+        leaf.map(|leaf| (leaf.phys, leaf.pat_index))
+    }
+
+    /// Add `extra` to the PML4, PDPT and PD entries mapping `page`
+    fn set_parent_flags(
+        &mut self,
+        page: Page<Size4KiB>,
+        extra: PageTableFlags,
+    ) -> Result<(), FlagUpdateError> {
+        let phys_offset = self.mapper.phys_offset();
+        let p4 = &self.mapper.level_4_table()[page.p4_index()];
+        let p4_flags = p4.flags();
+        let p3_table: &PageTable = unsafe { &*(phys_offset + p4.addr().as_u64()).as_ptr() };
+        let p3 = &p3_table[page.p3_index()];
+        let p3_flags = p3.flags();
+        let p2_table: &PageTable = unsafe { &*(phys_offset + p3.addr().as_u64()).as_ptr() };
+        let p2_flags = p2_table[page.p2_index()].flags();
+
+        unsafe {
+            self.mapper
+                .set_flags_p4_entry(page, p4_flags | extra)?
+                .ignore();
+            self.mapper
+                .set_flags_p3_entry(page, p3_flags | extra)?
+                .ignore();
+            self.mapper
+                .set_flags_p2_entry(page, p2_flags | extra)?
+                .ignore();
+        }
+        Ok(())
+    }
 }
 
 impl UninitPageTableManager {
@@ -54,42 +256,75 @@ impl UninitPageTableManager {
 
 fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
-    frame_alloc: &mut impl FrameAllocator<Size4KiB>,
+    frame_alloc: &mut BitmapFrameAllocator,
 ) -> Result<(), MapToError<Size4KiB>> {
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + (HEAP_SIZE as u64) - 1u64;
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
-    };
-
-    for page in page_range {
-        let frame = frame_alloc
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        unsafe { mapper.map_to(page, frame, flags, frame_alloc)?.flush() };
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    if map_heap(mapper, frame_alloc, heap_start, HEAP_SIZE) < HEAP_SIZE {
+        return Err(MapToError::FrameAllocationFailed);
     }
 
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
+        ALLOCATOR.init(HEAP_START as *mut u8, HEAP_SIZE);
     }
 
     Ok(())
 }
 
+/// Map `len` bytes of fresh frames at `start`, returning how many bytes were
+/// mapped before frames or page tables ran out
+fn map_heap(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_alloc: &mut BitmapFrameAllocator,
+    start: VirtAddr,
+    len: usize,
+) -> usize {
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::containing_address(start + (len as u64 - 1));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    let mut mapped = 0;
+    for page in Page::range_inclusive(first, last) {
+        let Some(frame) = frame_alloc.allocate_frame() else {
+            break;
+        };
+        match unsafe { mapper.map_to(page, frame, flags, frame_alloc) } {
+            Ok(flush) => flush.flush(),
+            Err(_) => {
+                unsafe { frame_alloc.deallocate_frame(frame) };
+                break;
+            }
+        }
+        mapped += page.size() as usize;
+    }
+    mapped
+}
+
+/// Heap usage and allocator counters
+pub fn heap_stats() -> HeapStats {
+    ALLOCATOR.stats()
+}
+
+/// Heap size, used and free bytes, None if the heap is locked
+pub fn heap_usage() -> Option<(usize, usize, usize)> {
+    ALLOCATOR.usage()
+}
+
 pub struct Mappings {
     ioapic_base: VirtAddr,
+    lapic_base: VirtAddr,
 }
 
 impl Mappings {
     pub fn ioapic_base(&self) -> VirtAddr {
         self.ioapic_base
     }
+
+    pub fn lapic_base(&self) -> VirtAddr {
+        self.lapic_base
+    }
 }
 
-pub fn init(boot_info: &'static mut BootInfo) -> Mappings {
+pub fn init(boot_info: &'static BootInfo) -> Mappings {
     let phys_mem_offset = boot_info
         .physical_memory_offset
         .into_option()
@@ -100,50 +335,19 @@ pub fn init(boot_info: &'static mut BootInfo) -> Mappings {
     let mut frame_alloc;
     unsafe {
         manager = UninitPageTableManager::new().init(phys_mem_offset);
-        frame_alloc = BootInfoFrameAllocator::init(&boot_info.memory_regions);
+        frame_alloc = BitmapFrameAllocator::init(&boot_info.memory_regions, phys_mem_offset);
     }
     let mapper = manager.mapper();
     init_heap(mapper, &mut frame_alloc).expect("heap initialization failed");
 
     let ioapic_base = ioapic::map_ioapic(mapper, &mut frame_alloc).expect("ioapic mapping failed");
+    let lapic_base = lapic::map_lapic(mapper, &mut frame_alloc).expect("lapic mapping failed");
 
-    Mappings { ioapic_base }
-}
+    PAGE_TABLES.call_once(|| Mutex::new(manager));
+    FRAMES.call_once(|| Mutex::new(frame_alloc));
 
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-struct BootInfoFrameAllocator {
-    regions: &'static MemoryRegions,
-    next: usize,
-}
-
-impl BootInfoFrameAllocator {
-    /// Create a FrameAllocator from the passed memory map.
-    ///
-    /// This function is unsafe because the caller must guarantee that the passed
-    /// memory map is valid. The main requirement is that all frames that are marked
-    /// as `USABLE` in it are really unused.
-    unsafe fn init(regions: &'static MemoryRegions) -> Self {
-        Self { regions, next: 0 }
-    }
-
-    /// Returns an iterator over the usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        // get usable regions from memory map
-        let regions = self.regions.iter();
-        let usable_regions = regions.filter(|r| r.kind == MemoryRegionKind::Usable);
-        // map each region to its address range
-        let addr_ranges = usable_regions.map(|r| r.start..r.end);
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-        // create `PhysFrame` types from the start addresses
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
-}
-
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+    Mappings {
+        ioapic_base,
+        lapic_base,
     }
 }
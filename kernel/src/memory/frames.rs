@@ -0,0 +1,200 @@
+//! Physical frame allocator backed by a bitmap of the usable memory
+//!
+//! The bitmap holds one bit per frame up to the end of the last usable
+//! region, set while the frame is free. It lives in the first usable region
+//! large enough for it and is accessed through the physical memory mapping,
+//! so it is available before the heap.
+
+use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+/// Usable regions tracked, after merging adjacent ones. Frames of any
+/// further region are left out of the allocator and counted as dropped.
+const MAX_REGIONS: usize = 64;
+
+/// Frame counts of a usable region of the memory map
+#[derive(Clone, Copy)]
+pub struct RegionStats {
+    pub start: PhysAddr,
+    pub end: PhysAddr,
+    pub frames: usize,
+    pub free: usize,
+}
+
+impl RegionStats {
+    fn contains(&self, addr: PhysAddr) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    regions: heapless::Vec<RegionStats, MAX_REGIONS>,
+    /// Usable frames in regions beyond `MAX_REGIONS`
+    dropped: usize,
+    /// Word the last allocation was found in, where the next search starts
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Build the allocator from the usable regions of the memory map.
+    ///
+    /// This function is unsafe because the caller must guarantee that the
+    /// memory map is valid, that frames marked `Usable` in it are really
+    /// unused and that all physical memory is mapped at `phys_offset`.
+    pub unsafe fn init(memory_regions: &'static MemoryRegions, phys_offset: VirtAddr) -> Self {
+        let mut regions = heapless::Vec::<RegionStats, MAX_REGIONS>::new();
+        let mut dropped = 0;
+        for region in memory_regions
+            .iter()
+            .filter(|r| r.kind == MemoryRegionKind::Usable)
+        {
+            let start = region.start.next_multiple_of(FRAME_SIZE);
+            let end = region.end / FRAME_SIZE * FRAME_SIZE;
+            if start >= end {
+                continue;
+            }
+            let frames = ((end - start) / FRAME_SIZE) as usize;
+            let (start, end) = (PhysAddr::new(start), PhysAddr::new(end));
+            // the map is not sorted, a neighbour can come before or after
+            let neighbour = regions
+                .iter_mut()
+                .find(|r| r.end == start || r.start == end);
+            if let Some(r) = neighbour {
+                r.start = r.start.min(start);
+                r.end = r.end.max(end);
+                r.frames += frames;
+                r.free += frames;
+                continue;
+            }
+            let stats = RegionStats {
+                start,
+                end,
+                frames,
+                free: frames,
+            };
+            if regions.push(stats).is_err() {
+                dropped += frames;
+            }
+        }
+        regions.sort_unstable_by_key(|r| r.start);
+
+        let max_frame = regions.last().map_or(0, |r| r.end.as_u64() / FRAME_SIZE);
+        let words = (max_frame as usize).div_ceil(BITS_PER_WORD);
+        let bitmap_frames = (words as u64 * 8).div_ceil(FRAME_SIZE);
+        let home = regions
+            .iter()
+            .position(|r| r.frames as u64 >= bitmap_frames)
+            .expect("no usable region can hold the frame bitmap");
+        let bitmap_start = regions[home].start;
+
+        let bitmap = unsafe {
+            let ptr: *mut u64 = (phys_offset + bitmap_start.as_u64()).as_mut_ptr();
+            core::slice::from_raw_parts_mut(ptr, words)
+        };
+        bitmap.fill(0);
+
+        let mut allocator = Self {
+            bitmap,
+            regions,
+            dropped,
+            next: 0,
+        };
+        for i in 0..allocator.regions.len() {
+            let region = allocator.regions[i];
+            let first = region.start.as_u64() / FRAME_SIZE;
+            for frame in first..first + region.frames as u64 {
+                allocator.set_free(frame as usize, true);
+            }
+        }
+
+        // the bitmap's own frames are in use from the start
+        let first = bitmap_start.as_u64() / FRAME_SIZE;
+        for frame in first..first + bitmap_frames {
+            allocator.set_free(frame as usize, false);
+        }
+        allocator.regions[home].free -= bitmap_frames as usize;
+        allocator
+    }
+
+    fn is_free(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) != 0
+    }
+
+    fn set_free(&mut self, frame: usize, free: bool) {
+        let word = &mut self.bitmap[frame / BITS_PER_WORD];
+        let bit = 1 << (frame % BITS_PER_WORD);
+        if free {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    fn region_mut(&mut self, addr: PhysAddr) -> Option<&mut RegionStats> {
+        self.regions.iter_mut().find(|r| r.contains(addr))
+    }
+
+    /// Frame counts of every usable region, in address order
+    pub fn regions(&self) -> &[RegionStats] {
+        &self.regions
+    }
+
+    /// Usable frames the allocator does not manage because the memory map
+    /// has more than `MAX_REGIONS` separate usable regions
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Free and total frames over all regions
+    pub fn totals(&self) -> (usize, usize) {
+        self.regions.iter().fold((0, 0), |(free, frames), r| {
+            (free + r.free, frames + r.frames)
+        })
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let words = self.bitmap.len();
+        let word = (0..words)
+            .map(|n| (self.next + n) % words)
+            .find(|&w| self.bitmap[w] != 0)?;
+        self.next = word;
+
+        let frame = word * BITS_PER_WORD + self.bitmap[word].trailing_zeros() as usize;
+        self.set_free(frame, false);
+        let addr = PhysAddr::new(frame as u64 * FRAME_SIZE);
+        if let Some(region) = self.region_mut(addr) {
+            region.free -= 1;
+        }
+        Some(PhysFrame::containing_address(addr))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    /// Return a frame to the allocator. Frames outside the usable regions,
+    /// like MMIO, are not tracked and ignored.
+    ///
+    /// Panics if the frame is already free.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let addr = frame.start_address();
+        if !self.regions.iter().any(|r| r.contains(addr)) {
+            return;
+        }
+
+        let index = (addr.as_u64() / FRAME_SIZE) as usize;
+        assert!(
+            !self.is_free(index),
+            "double free of frame 0x{:x}",
+            addr.as_u64()
+        );
+        self.set_free(index, true);
+        if let Some(region) = self.region_mut(addr) {
+            region.free += 1;
+        }
+    }
+}
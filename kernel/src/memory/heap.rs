@@ -0,0 +1,177 @@
+//! Kernel heap that grows by mapping more frames when it runs low
+//!
+//! Growing needs the page tables and the frame allocator. Their locks are
+//! only tried, as panes allocate while holding the page tables, so the heap
+//! also grows ahead of demand once free space falls below `LOW_WATER`.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use linked_list_allocator::Heap;
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use super::{FRAMES, HEAP_MAX_SIZE, PAGE_TABLES, map_heap};
+
+/// Granularity the heap grows by
+const GROW_STEP: usize = 256 * 1024;
+/// Free space below which an allocation grows the heap in advance
+const LOW_WATER: usize = 64 * 1024;
+
+/// Heap usage and allocator counters
+#[derive(Clone, Copy, Default)]
+pub struct HeapStats {
+    pub bottom: usize,
+    pub size: usize,
+    pub used: usize,
+    pub free: usize,
+    /// Largest allocation that would currently succeed without growing
+    pub largest_free: usize,
+    pub high_water: usize,
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub failures: u64,
+    pub growths: u64,
+}
+
+struct HeapState {
+    heap: Heap,
+    high_water: usize,
+    allocations: u64,
+    deallocations: u64,
+    failures: u64,
+    growths: u64,
+}
+
+impl HeapState {
+    /// Map at least `bytes` more after the top of the heap, false if the
+    /// page tables or frames are busy or exhausted
+    fn grow(&mut self, bytes: usize) -> bool {
+        let by = bytes.next_multiple_of(GROW_STEP);
+        if self.heap.size() + by > HEAP_MAX_SIZE {
+            return false;
+        }
+        let (Some(tables), Some(frames)) = (PAGE_TABLES.get(), FRAMES.get()) else {
+            return false;
+        };
+        let (Some(mut tables), Some(mut frames)) = (tables.try_lock(), frames.try_lock()) else {
+            return false;
+        };
+
+        let top = VirtAddr::from_ptr(self.heap.top());
+        let mapped = map_heap(tables.mapper(), &mut frames, top, by);
+        if mapped > 0 {
+            unsafe { self.heap.extend(mapped) };
+            self.growths += 1;
+        }
+        mapped == by
+    }
+}
+
+pub struct KernelHeap {
+    state: Mutex<HeapState>,
+}
+
+impl KernelHeap {
+    pub const fn empty() -> Self {
+        Self {
+            state: Mutex::new(HeapState {
+                heap: Heap::empty(),
+                high_water: 0,
+                allocations: 0,
+                deallocations: 0,
+                failures: 0,
+                growths: 0,
+            }),
+        }
+    }
+
+    /// Hand the mapped range `[bottom, bottom + size)` to the heap
+    ///
+    /// This function is unsafe because the range must be mapped, writable and
+    /// unused, and must be followed by unmapped space the heap can grow into.
+    pub unsafe fn init(&self, bottom: *mut u8, size: usize) {
+        unsafe { self.state.lock().heap.init(bottom, size) };
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let mut state = self.state.lock();
+        let largest_free = largest_allocation(&mut state.heap);
+        let heap = &state.heap;
+        HeapStats {
+            bottom: heap.bottom() as usize,
+            size: heap.size(),
+            used: heap.used(),
+            free: heap.free(),
+            largest_free,
+            high_water: state.high_water,
+            allocations: state.allocations,
+            deallocations: state.deallocations,
+            failures: state.failures,
+            growths: state.growths,
+        }
+    }
+
+    /// Size, used and free bytes without probing, for the OOM handler
+    pub fn usage(&self) -> Option<(usize, usize, usize)> {
+        let state = self.state.try_lock()?;
+        Some((state.heap.size(), state.heap.used(), state.heap.free()))
+    }
+}
+
+/// Binary search for the largest byte-aligned allocation that fits, the
+/// hole list itself is private to the heap
+fn largest_allocation(heap: &mut Heap) -> usize {
+    let fits = |heap: &mut Heap, size: usize| {
+        let Ok(layout) = Layout::from_size_align(size, 1) else {
+            return false;
+        };
+        match heap.allocate_first_fit(layout) {
+            Ok(ptr) => {
+                unsafe { heap.deallocate(ptr, layout) };
+                true
+            }
+            Err(()) => false,
+        }
+    };
+
+    let (mut low, mut high) = (0, heap.free());
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if fits(heap, mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut state = self.state.lock();
+        let mut result = state.heap.allocate_first_fit(layout);
+        if result.is_err() && state.grow(layout.size() + layout.align()) {
+            result = state.heap.allocate_first_fit(layout);
+        }
+
+        let Ok(ptr) = result else {
+            state.failures += 1;
+            return ptr::null_mut();
+        };
+        state.allocations += 1;
+        state.high_water = state.high_water.max(state.heap.used());
+        if state.heap.free() < LOW_WATER {
+            state.grow(GROW_STEP);
+        }
+        ptr.as_ptr()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut state = self.state.lock();
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe { state.heap.deallocate(ptr, layout) };
+            state.deallocations += 1;
+        }
+    }
+}
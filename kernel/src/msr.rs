@@ -1,22 +1,89 @@
 //! Model Specific Register (MSR) reading and display
 
+use alloc::borrow::Cow;
+use alloc::collections::BTreeSet;
 use alloc::format;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 
 use ratatui::buffer::Buffer;
 use ratatui::layout::Rect;
-use ratatui::style::Style;
-use ratatui::text::Line;
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
 use ratatui::widgets::{Paragraph, Widget};
+use x86_64::VirtAddr;
 
 use crate::cpuid::CpuFeatures;
-use crate::pane::{ScrollHints, Scrollable, Searchable, highlight_line};
-
-/// MSR entry with name, address, and value
+use crate::mca::{self, MSR_MCG_CAP, MSR_MCG_STATUS};
+use crate::memory;
+use crate::pane::{
+    PromptResult, Promptable, ScrollDirection, ScrollHints, Scrollable, Searchable, highlight_line,
+    parse_number,
+};
+use crate::recovery::{self, Fault};
+
+mod db;
+mod decode;
+mod hyperv;
+mod mtrr;
+mod scan;
+mod vmx;
+
+use db::{CategoryDef, Source};
+pub use mtrr::MemoryTypeMap;
+use scan::MsrScan;
+
+/// MSRs probed per main loop iteration while a scan is running
+const SCAN_BATCH: usize = 256;
+
+/// Name of the category holding MSRs read from the prompt
+const WATCHED_CATEGORY: &str = "Watched";
+
+/// MSR entry with name, address, and value (None if the read raised #GP)
 pub struct MsrEntry {
-    pub name: &'static str,
+    pub name: Cow<'static, str>,
     pub address: u32,
     pub value: Option<u64>,
+    /// Value before the last refresh, to highlight changed bits
+    pub previous: Option<u64>,
+    /// Increase per second, for monotonic counters
+    pub rate: Option<u64>,
+}
+
+impl MsrEntry {
+    /// Entry holding the current value of the MSR at `address`
+    fn read(name: impl Into<Cow<'static, str>>, address: u32) -> Self {
+        let value = try_read_msr(address).ok();
+        Self {
+            name: name.into(),
+            address,
+            value,
+            previous: value,
+            rate: None,
+        }
+    }
+
+    /// Re-read the MSR. Counters derive their rate from the increase over
+    /// the `seconds` since the previous refresh.
+    fn refresh(&mut self, seconds: usize) {
+        self.previous = self.value;
+        self.value = try_read_msr(self.address).ok();
+        self.rate = match (self.previous, self.value) {
+            (Some(previous), Some(value)) if seconds > 0 && is_counter(self.address) => {
+                Some(value.wrapping_sub(previous) / seconds as u64)
+            }
+            _ => None,
+        };
+    }
+
+    /// Bits that differ from the value before the last refresh
+    fn changed_bits(&self) -> u64 {
+        match (self.previous, self.value) {
+            (Some(previous), Some(value)) => previous ^ value,
+            _ => 0,
+        }
+    }
 }
 
 /// Category of MSRs
@@ -25,180 +92,139 @@ pub struct MsrCategory {
     pub entries: Vec<MsrEntry>,
 }
 
-/// Read an MSR (caller must ensure MSR exists)
-fn read_msr(address: u32) -> u64 {
-    unsafe { x86::msr::rdmsr(address) }
+/// Read an MSR, an unimplemented MSR yields the #GP it raised
+pub fn try_read_msr(address: u32) -> Result<u64, Fault> {
+    recovery::rdmsr(address)
 }
 
-/// Read a list of MSRs by address and name
-fn read_msrs(msrs: &[(&'static str, u32)]) -> Vec<MsrEntry> {
-    msrs.iter()
-        .map(|(name, addr)| MsrEntry {
-            name,
-            address: *addr,
-            value: Some(read_msr(*addr)),
-        })
-        .collect()
+/// Write an MSR, an unimplemented MSR or invalid value yields the #GP it raised
+#[cfg(feature = "msr-write")]
+pub fn try_write_msr(address: u32, value: u64) -> Result<(), Fault> {
+    recovery::wrmsr(address, value)
 }
 
-/// Conditionally read an MSR if feature is supported
-fn read_msr_if(name: &'static str, address: u32, supported: bool) -> Option<MsrEntry> {
-    if supported {
-        Some(MsrEntry {
-            name,
-            address,
-            value: Some(read_msr(address)),
-        })
-    } else {
-        None
-    }
+/// Read MSRs by address, named from the database
+fn read_known(addresses: &[u32]) -> Vec<MsrEntry> {
+    addresses
+        .iter()
+        .map(|&address| MsrEntry::read(msr_name(address), address))
+        .collect()
 }
 
-// MSR addresses from QEMU cpu.h
-// Long mode MSRs - architectural, always present in 64-bit mode
-const MSR_EFER: u32 = 0xC000_0080;
-const MSR_STAR: u32 = 0xC000_0081;
-const MSR_LSTAR: u32 = 0xC000_0082;
-const MSR_CSTAR: u32 = 0xC000_0083;
-const MSR_FMASK: u32 = 0xC000_0084;
-const MSR_FSBASE: u32 = 0xC000_0100;
-const MSR_GSBASE: u32 = 0xC000_0101;
-const MSR_KERNELGSBASE: u32 = 0xC000_0102;
-const MSR_TSC_AUX: u32 = 0xC000_0103;
-
-// System MSRs
-const MSR_IA32_APICBASE: u32 = 0x1B;
-
-// TSC MSRs
+// MSR addresses the readers below rely on, names and layouts are in msrs.def
 const MSR_IA32_TSC: u32 = 0x10;
-const MSR_TSC_ADJUST: u32 = 0x3B;
-const MSR_IA32_TSC_DEADLINE: u32 = 0x6E0;
+const MSR_IA32_MPERF: u32 = 0xE7;
+const MSR_IA32_APERF: u32 = 0xE8;
+
+/// Monotonic counters, shown with their rate per second
+const COUNTER_MSRS: &[u32] = &[
+    MSR_IA32_TSC,
+    MSR_IA32_MPERF,
+    MSR_IA32_APERF,
+    hyperv::HV_X64_MSR_TIME_REF_COUNT,
+];
+
+fn is_counter(address: u32) -> bool {
+    COUNTER_MSRS.contains(&address)
+}
 
-// SYSENTER MSRs - architectural
-const MSR_IA32_SYSENTER_CS: u32 = 0x174;
-const MSR_IA32_SYSENTER_ESP: u32 = 0x175;
-const MSR_IA32_SYSENTER_EIP: u32 = 0x176;
+/// Commands of the prompt, `wrmsr` only where it is compiled in
+#[cfg(not(feature = "msr-write"))]
+const PROMPT_USAGE: &str = "usage: <address> | scan [ranges] | mtrr | memtype <address> | mce";
+#[cfg(feature = "msr-write")]
+const PROMPT_USAGE: &str =
+    "usage: <address> | scan [ranges] | mtrr | memtype <address> | mce | wrmsr <address> <value>";
 
 // Machine Check MSRs
-const MSR_MCG_CAP: u32 = 0x179;
-const MSR_MCG_STATUS: u32 = 0x17A;
+const MSR_MCG_CTL: u32 = 0x17B;
 
 // MTRR MSRs
 const MSR_MTRRCAP: u32 = 0xFE;
 const MSR_MTRR_DEF_TYPE: u32 = 0x2FF;
 const MSR_MTRR_PHYSBASE0: u32 = 0x200;
-const MSR_MTRR_PHYSMASK0: u32 = 0x201;
-const MSR_MTRR_PHYSBASE1: u32 = 0x202;
-const MSR_MTRR_PHYSMASK1: u32 = 0x203;
-const MSR_MTRR_FIX64K_00000: u32 = 0x250;
-const MSR_MTRR_FIX16K_80000: u32 = 0x258;
-const MSR_MTRR_FIX16K_A0000: u32 = 0x259;
-const MSR_MTRR_FIX4K_C0000: u32 = 0x268;
 
 // PAT MSR
 const MSR_PAT: u32 = 0x277;
 
+/// MTRRCAP, DEF_TYPE, every variable range MTRRCAP.VCNT reports and the
+/// fixed-range MTRRs if MTRRCAP.FIX is set
+fn read_mtrrs() -> Vec<MsrEntry> {
+    let mut entries = read_known(&[MSR_MTRRCAP, MSR_MTRR_DEF_TYPE]);
+    let cap = entries[0].value.unwrap_or(0);
+
+    for index in 0..(cap & 0xFF) as u32 {
+        let (base, mask) = mtrr::variable_range_msrs(index);
+        entries.extend(read_known(&[base, mask]));
+    }
+
+    // MTRRCAP.FIX
+    if cap & (1 << 8) != 0 {
+        entries.extend(read_known(&mtrr::FIXED_MTRRS.map(|(msr, ..)| msr)));
+    }
+    entries
+}
+
+/// MCG registers and the CTL, STATUS, ADDR, MISC (and CTL2 with CMCI) of
+/// every bank MCG_CAP.Count reports
+fn read_mc_banks() -> Vec<MsrEntry> {
+    let mut entries = read_known(&[MSR_MCG_CAP, MSR_MCG_STATUS]);
+    let cap = entries[0].value.unwrap_or(0);
+    // MCG_CAP.MCG_CTL_P
+    if cap & (1 << 8) != 0 {
+        entries.extend(read_known(&[MSR_MCG_CTL]));
+    }
+
+    let (count, cmci) = mca::capabilities(cap);
+    for bank in 0..count {
+        let msrs = mca::bank_msrs(bank, cmci);
+        entries.extend(read_known(&[msrs.ctl, msrs.status, msrs.addr, msrs.misc]));
+        if let Some(ctl2) = msrs.ctl2 {
+            entries.extend(read_known(&[ctl2]));
+        }
+    }
+    entries
+}
+
+/// Parse an MSR address typed at the prompt
+pub fn parse_address(input: &str) -> Result<u32, String> {
+    parse_number(input)
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or_else(|| format!("invalid MSR address: {}", input))
+}
+
+/// Name of an MSR, from the database or made up from its address
+pub fn msr_name(address: u32) -> Cow<'static, str> {
+    db::name(address).unwrap_or_else(|| Cow::Owned(format!("MSR_{:08X}", address)))
+}
+
+/// Read the MSRs of a category
+fn read_category(category: &CategoryDef, cpufeatures: &CpuFeatures) -> Vec<MsrEntry> {
+    match category.source {
+        Some(Source::McBanks) => read_mc_banks(),
+        Some(Source::Vmx) => vmx::read_vmx_msrs(),
+        Some(Source::Mtrrs) => read_mtrrs(),
+        None => category
+            .msrs
+            .iter()
+            .filter(|msr| db::met(msr.requires, cpufeatures))
+            .flat_map(|msr| msr.instances())
+            .map(|(name, address)| MsrEntry::read(name, address))
+            .collect(),
+    }
+}
+
 /// Build all MSR categories with current values
-/// Only reads MSRs that are confirmed present via CPUID
+/// Only lists MSRs that CPUID reports as present. Reads recover from #GP, so
+/// a feature advertised without a backing MSR shows up as unreadable.
 pub fn read_all_msrs(cpufeatures: &CpuFeatures) -> Vec<MsrCategory> {
-    let mut categories = Vec::new();
-
-    // Long mode MSRs - architectural, always present in x86-64
-    let mut long_mode_entries = read_msrs(&[
-        ("IA32_EFER", MSR_EFER),
-        ("IA32_STAR", MSR_STAR),
-        ("IA32_LSTAR", MSR_LSTAR),
-        ("IA32_CSTAR", MSR_CSTAR),
-        ("IA32_FMASK", MSR_FMASK),
-        ("IA32_FS_BASE", MSR_FSBASE),
-        ("IA32_GS_BASE", MSR_GSBASE),
-        ("IA32_KERNEL_GS_BASE", MSR_KERNELGSBASE),
-    ]);
-    // TSC_AUX requires RDTSCP support
-    if let Some(entry) = read_msr_if("IA32_TSC_AUX", MSR_TSC_AUX, cpufeatures.has_rdtscp()) {
-        long_mode_entries.push(entry);
-    }
-    categories.push(MsrCategory {
-        name: "Long Mode / SYSCALL",
-        entries: long_mode_entries,
-    });
-
-    // Core system MSRs - APIC_BASE is always present with APIC
-    categories.push(MsrCategory {
-        name: "System",
-        entries: read_msrs(&[("IA32_APIC_BASE", MSR_IA32_APICBASE)]),
-    });
-
-    // Time-related MSRs
-    let mut tsc_entries = read_msrs(&[("IA32_TSC", MSR_IA32_TSC)]);
-    if let Some(entry) = read_msr_if(
-        "IA32_TSC_ADJUST",
-        MSR_TSC_ADJUST,
-        cpufeatures.has_tsc_adjust(),
-    ) {
-        tsc_entries.push(entry);
-    }
-    if let Some(entry) = read_msr_if(
-        "IA32_TSC_DEADLINE",
-        MSR_IA32_TSC_DEADLINE,
-        cpufeatures.has_tsc_deadline(),
-    ) {
-        tsc_entries.push(entry);
-    }
-    categories.push(MsrCategory {
-        name: "Time Stamp Counter",
-        entries: tsc_entries,
-    });
-
-    // SYSENTER MSRs - architectural, always present
-    categories.push(MsrCategory {
-        name: "SYSENTER",
-        entries: read_msrs(&[
-            ("IA32_SYSENTER_CS", MSR_IA32_SYSENTER_CS),
-            ("IA32_SYSENTER_ESP", MSR_IA32_SYSENTER_ESP),
-            ("IA32_SYSENTER_EIP", MSR_IA32_SYSENTER_EIP),
-        ]),
-    });
-
-    // Machine Check MSRs - only if MCE/MCA supported
-    if cpufeatures.has_mce() && cpufeatures.has_mca() {
-        categories.push(MsrCategory {
-            name: "Machine Check",
-            entries: read_msrs(&[
-                ("IA32_MCG_CAP", MSR_MCG_CAP),
-                ("IA32_MCG_STATUS", MSR_MCG_STATUS),
-            ]),
-        });
-    }
-
-    // MTRR MSRs - only if MTRR supported
-    if cpufeatures.has_mtrr() {
-        categories.push(MsrCategory {
-            name: "MTRR",
-            entries: read_msrs(&[
-                ("IA32_MTRRCAP", MSR_MTRRCAP),
-                ("IA32_MTRR_DEF_TYPE", MSR_MTRR_DEF_TYPE),
-                ("IA32_MTRR_PHYSBASE0", MSR_MTRR_PHYSBASE0),
-                ("IA32_MTRR_PHYSMASK0", MSR_MTRR_PHYSMASK0),
-                ("IA32_MTRR_PHYSBASE1", MSR_MTRR_PHYSBASE1),
-                ("IA32_MTRR_PHYSMASK1", MSR_MTRR_PHYSMASK1),
-                ("IA32_MTRR_FIX64K_00000", MSR_MTRR_FIX64K_00000),
-                ("IA32_MTRR_FIX16K_80000", MSR_MTRR_FIX16K_80000),
-                ("IA32_MTRR_FIX16K_A0000", MSR_MTRR_FIX16K_A0000),
-                ("IA32_MTRR_FIX4K_C0000", MSR_MTRR_FIX4K_C0000),
-            ]),
-        });
-    }
-
-    // PAT - only if PAT supported
-    if cpufeatures.has_pat() {
-        categories.push(MsrCategory {
-            name: "PAT",
-            entries: read_msrs(&[("IA32_PAT", MSR_PAT)]),
-        });
-    }
-
-    categories
+    db::CATEGORIES
+        .iter()
+        .filter(|category| db::met(category.requires, cpufeatures))
+        .map(|category| MsrCategory {
+            name: category.name,
+            entries: read_category(category, cpufeatures),
+        })
+        .collect()
 }
 
 /// Pane wrapper for MSR state with scroll and search support
@@ -206,6 +232,24 @@ pub struct MsrPane {
     categories: Vec<MsrCategory>,
     scroll: ScrollHints,
     search: search::SearchState,
+    /// Index of the selected entry, counted across all categories
+    selected: usize,
+    /// Addresses of entries showing their decoded bitfields
+    expanded: BTreeSet<u32>,
+    view: MsrView,
+    memory_types: MemoryTypeMap,
+    scan: Option<MsrScan>,
+    /// Write entered at the prompt, awaiting confirmation
+    #[cfg(feature = "msr-write")]
+    pending_write: Option<(u32, u64)>,
+}
+
+/// What the MSR pane shows
+#[derive(PartialEq, Clone, Copy)]
+enum MsrView {
+    Registers,
+    MemoryTypes,
+    Scan,
 }
 
 impl MsrPane {
@@ -214,6 +258,195 @@ impl MsrPane {
             categories: read_all_msrs(cpufeatures),
             scroll: ScrollHints::default(),
             search: search::SearchState::default(),
+            selected: 0,
+            expanded: BTreeSet::new(),
+            view: MsrView::Registers,
+            memory_types: MemoryTypeMap::new(cpufeatures),
+            scan: None,
+            #[cfg(feature = "msr-write")]
+            pending_write: None,
+        }
+    }
+
+    /// Pin an MSR to the watched category and select it
+    fn watch(&mut self, address: u32) {
+        if self.categories.first().map(|c| c.name) != Some(WATCHED_CATEGORY) {
+            self.categories.insert(
+                0,
+                MsrCategory {
+                    name: WATCHED_CATEGORY,
+                    entries: Vec::new(),
+                },
+            );
+        }
+
+        let watched = &mut self.categories[0].entries;
+        let index = match watched.iter().position(|e| e.address == address) {
+            Some(index) => index,
+            None => {
+                watched.push(MsrEntry::read(msr_name(address), address));
+                watched.len() - 1
+            }
+        };
+        // a write from the prompt shows up as changed bits
+        watched[index].refresh(0);
+
+        self.selected = index;
+        self.view = MsrView::Registers;
+    }
+
+    /// Read an MSR typed at the prompt and pin it
+    fn read_command(&mut self, address: &str) -> PromptResult {
+        let address = parse_address(address)?;
+        self.watch(address);
+
+        let name = db::name(address).unwrap_or(Cow::Borrowed("MSR"));
+        match try_read_msr(address) {
+            Ok(value) => Ok(format!("{} (0x{:08X}) = 0x{:016x}", name, address, value)),
+            Err(_) => Err(format!("{} (0x{:08X}): #GP", name, address)),
+        }
+    }
+
+    /// Stage a write typed at the prompt, it is applied once confirmed
+    #[cfg(feature = "msr-write")]
+    fn write_command(&mut self, args: &str) -> PromptResult {
+        let (address, value) = args
+            .trim()
+            .split_once(' ')
+            .ok_or("usage: wrmsr <address> <value>")?;
+        let address = parse_address(address)?;
+        let value = parse_number(value).ok_or_else(|| format!("invalid value: {}", value))?;
+
+        self.pending_write = Some((address, value));
+        Ok(String::new())
+    }
+
+    /// Apply the staged write if confirmed and report the value read back
+    #[cfg(feature = "msr-write")]
+    fn confirm_write(&mut self, address: u32, value: u64, answer: &str) -> PromptResult {
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            return Err("write cancelled".into());
+        }
+
+        let name = db::name(address).unwrap_or(Cow::Borrowed("MSR"));
+        let written = try_write_msr(address, value);
+        self.watch(address);
+        if written.is_err() {
+            return Err(format!("{} (0x{:08X}): write raised #GP", name, address));
+        }
+        match try_read_msr(address) {
+            Ok(readback) => Ok(format!(
+                "wrote {} (0x{:08X}), read back 0x{:016x}",
+                name, address, readback
+            )),
+            Err(_) => Err(format!("wrote {} (0x{:08X}), read back #GP", name, address)),
+        }
+    }
+
+    /// Effective memory type of a virtual address through the live page
+    /// tables, or of a physical address mapped with the given PAT index
+    fn memtype_command(&mut self, args: &str) -> PromptResult {
+        let mut args = args.split_whitespace();
+        let usage = "usage: memtype <virt> | memtype <phys> <PAT index>";
+        let address = args.next().ok_or(usage)?;
+        let address =
+            parse_number(address).ok_or_else(|| format!("invalid address: {}", address))?;
+
+        match args.next() {
+            Some(index) => {
+                let index = parse_number(index)
+                    .filter(|&i| i < 8)
+                    .ok_or_else(|| format!("invalid PAT index: {}", index))?;
+                Ok(format!(
+                    "phys 0x{:x}: {}",
+                    address,
+                    self.memory_types.effective_type(address, index as u8)
+                ))
+            }
+            None => {
+                let virt = VirtAddr::try_new(address)
+                    .map_err(|_| format!("non-canonical address: 0x{:x}", address))?;
+                let (phys, index) = memory::page_tables()
+                    .translate_with_pat(virt)
+                    .ok_or_else(|| format!("0x{:x} is not mapped", address))?;
+                Ok(format!(
+                    "0x{:x} -> phys 0x{:x}: {}",
+                    address,
+                    phys,
+                    self.memory_types.effective_type(phys.as_u64(), index)
+                ))
+            }
+        }
+    }
+
+    /// Cycle through the register list, the memory type map and, if a scan
+    /// was started, the scan results
+    pub fn toggle_view(&mut self) {
+        self.view = match self.view {
+            MsrView::Registers => MsrView::MemoryTypes,
+            MsrView::MemoryTypes if self.scan.is_some() => MsrView::Scan,
+            _ => MsrView::Registers,
+        };
+    }
+
+    /// Re-read every listed MSR, called once per `seconds` elapsed since the
+    /// last refresh
+    pub fn refresh(&mut self, seconds: usize) {
+        for category in &mut self.categories {
+            for entry in &mut category.entries {
+                entry.refresh(seconds);
+            }
+        }
+    }
+
+    /// Advance a running scan. Returns true when the scan just completed.
+    pub fn scan_step(&mut self) -> bool {
+        match &mut self.scan {
+            Some(scan) if !scan.is_done() => scan.step(SCAN_BATCH),
+            _ => false,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &MsrEntry> {
+        self.categories.iter().flat_map(|c| c.entries.iter())
+    }
+
+    /// Number of decoded lines shown below an entry
+    fn detail_lines(&self, entry: &MsrEntry) -> u16 {
+        match entry.value {
+            _ if !self.expanded.contains(&entry.address) => 0,
+            Some(value) => decode::decode(entry.address, value).len().max(1) as u16,
+            None => 1,
+        }
+    }
+
+    /// Line offset of every entry, in entry order
+    fn entry_lines(&self) -> Vec<u16> {
+        let mut lines = Vec::new();
+        let mut line: u16 = 0;
+
+        for category in &self.categories {
+            // Skip header line
+            line += 1;
+
+            for entry in &category.entries {
+                lines.push(line);
+                line += 1 + self.detail_lines(entry);
+            }
+            // Empty line between categories
+            line += 1;
+        }
+
+        lines
+    }
+
+    /// Show or hide the decoded bitfields of the selected entry
+    pub fn toggle_expanded(&mut self) {
+        let Some(address) = self.entries().nth(self.selected).map(|e| e.address) else {
+            return;
+        };
+        if !self.expanded.remove(&address) {
+            self.expanded.insert(address);
         }
     }
 
@@ -230,6 +463,36 @@ impl Scrollable for MsrPane {
     fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
         &mut self.scroll
     }
+
+    /// Scrolling moves the selection, the view follows it on render
+    fn scroll(&mut self, direction: ScrollDirection) {
+        if let (MsrView::Scan, Some(scan)) = (self.view, &mut self.scan) {
+            scan.scroll.scroll(direction);
+            return;
+        }
+        if self.view == MsrView::MemoryTypes {
+            self.memory_types.scroll.scroll(direction);
+            return;
+        }
+
+        let last = self.entries().count().saturating_sub(1);
+        let page = self.scroll.page_height as usize;
+        self.selected = match direction {
+            ScrollDirection::Up => self.selected.saturating_sub(1),
+            ScrollDirection::Down => (self.selected + 1).min(last),
+            ScrollDirection::Top => 0,
+            ScrollDirection::Bottom => last,
+            ScrollDirection::PageUp => self.selected.saturating_sub(page),
+            ScrollDirection::PageDown => (self.selected + page).min(last),
+        };
+    }
+
+    /// Select the entry at the given line offset
+    fn scroll_to(&mut self, offset: u16) {
+        if let Some(index) = self.entry_lines().iter().position(|&l| l == offset) {
+            self.selected = index;
+        }
+    }
 }
 
 impl Searchable for MsrPane {
@@ -238,27 +501,133 @@ impl Searchable for MsrPane {
     }
 
     fn search_items(&self) -> Vec<(&str, u16)> {
-        let mut items = Vec::new();
-        let mut line: u16 = 0;
+        self.entries()
+            .map(|entry| entry.name.as_ref())
+            .zip(self.entry_lines())
+            .collect()
+    }
+}
 
-        for category in &self.categories {
-            // Skip header line
-            line += 1;
+impl Promptable for MsrPane {
+    fn prompt_label(&self) -> String {
+        #[cfg(feature = "msr-write")]
+        if let Some((address, value)) = self.pending_write {
+            let current = match try_read_msr(address) {
+                Ok(current) => format!("0x{:x}", current),
+                Err(_) => "#GP".into(),
+            };
+            return format!(
+                "wrmsr 0x{:X}: {} -> 0x{:x}, confirm? [y/N] ",
+                address, current, value
+            );
+        }
+        "msr ".into()
+    }
 
-            for entry in &category.entries {
-                items.push((entry.name, line));
-                line += 1;
+    #[cfg(feature = "msr-write")]
+    fn prompt_pending(&self) -> bool {
+        self.pending_write.is_some()
+    }
+
+    #[cfg(feature = "msr-write")]
+    fn cancel_prompt(&mut self) {
+        self.pending_write = None;
+    }
+
+    fn submit_prompt(&mut self, input: &str) -> PromptResult {
+        #[cfg(feature = "msr-write")]
+        if let Some((address, value)) = self.pending_write.take() {
+            return self.confirm_write(address, value, input);
+        }
+
+        let (command, args) = input.trim().split_once(' ').unwrap_or((input.trim(), ""));
+        match command {
+            "scan" => {
+                let ranges = if args.trim().is_empty() {
+                    scan::DEFAULT_RANGES.to_vec()
+                } else {
+                    scan::parse_ranges(args)?
+                };
+                self.scan = Some(MsrScan::new(ranges));
+                self.view = MsrView::Scan;
+                Ok("scan started, Tab switches views".into())
             }
-            // Empty line between categories
-            line += 1;
+            "mtrr" => {
+                self.view = MsrView::MemoryTypes;
+                Ok(String::new())
+            }
+            "memtype" => self.memtype_command(args),
+            "mce" => Ok(match mca::last_machine_check() {
+                Some(mc) if mc.banks.is_empty() => {
+                    format!("#MC recovered, MCG_STATUS=0x{:x}", mc.mcg_status)
+                }
+                Some(mc) => {
+                    let banks: Vec<String> = mc.banks.iter().map(|b| format!("{}", b)).collect();
+                    format!("#MC recovered: {}", banks.join("; "))
+                }
+                None => "no machine check recorded".into(),
+            }),
+            #[cfg(feature = "msr-write")]
+            "wrmsr" => self.write_command(args),
+            "" => Err(PROMPT_USAGE.into()),
+            _ if args.is_empty() => self.read_command(command),
+            _ => Err(format!("unknown command: {}", command)),
         }
+    }
+}
+
+/// Value in hex, nibbles with bits in `changed` highlighted
+fn value_spans(value: u64, changed: u64) -> Vec<Span<'static>> {
+    let mut spans = vec![Span::raw("0x")];
+    let mut run = String::new();
+    let mut run_changed = false;
+
+    for nibble in (0..16).rev() {
+        let shift = nibble * 4;
+        let is_changed = (changed >> shift) & 0xF != 0;
+        if is_changed != run_changed && !run.is_empty() {
+            spans.push(changed_span(core::mem::take(&mut run), run_changed));
+        }
+        run_changed = is_changed;
+        run.push_str(&format!("{:x}", (value >> shift) & 0xF));
+    }
+    spans.push(changed_span(run, run_changed));
+    spans
+}
+
+fn changed_span(text: String, changed: bool) -> Span<'static> {
+    if changed {
+        Span::styled(text, Style::default().fg(Color::Yellow).bold())
+    } else {
+        Span::raw(text)
+    }
+}
 
-        items
+/// Counter increase per second with an SI prefix, e.g. `2.59 G/s`
+fn rate_str(rate: u64) -> String {
+    let rate = rate as f64;
+    if rate >= 1e9 {
+        format!("{:.2} G/s", rate / 1e9)
+    } else if rate >= 1e6 {
+        format!("{:.2} M/s", rate / 1e6)
+    } else if rate >= 1e3 {
+        format!("{:.2} k/s", rate / 1e3)
+    } else {
+        format!("{} /s", rate)
     }
 }
 
 impl Widget for &mut MsrPane {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        if let (MsrView::Scan, Some(scan)) = (self.view, &mut self.scan) {
+            scan.render(area, buf);
+            return;
+        }
+        if self.view == MsrView::MemoryTypes {
+            self.memory_types.render(area, buf);
+            return;
+        }
+
         let query = if self.search.last_query.is_empty() {
             None
         } else {
@@ -267,17 +636,63 @@ impl Widget for &mut MsrPane {
 
         let mut lines: Vec<Line> = Vec::new();
         let num_categories = self.categories.len();
+        let mut index = 0;
+        let mut selected_lines = (0, 0);
 
-        for (i, category) in self.categories.iter().enumerate() {
+        for (i, category) in self.categories.iter_mut().enumerate() {
             // Category header
             lines.push(Line::styled(category.name, Style::default().bold()));
 
-            for entry in &category.entries {
-                // Read fresh MSR value on each render
-                let value = read_msr(entry.address);
-                let value_str = format!("0x{:016x}", value);
-                let suffix = format!(" (0x{:08X}) = {}", entry.address, value_str);
-                lines.push(highlight_line(entry.name, &suffix, 24, query));
+            for entry in &mut category.entries {
+                let marker = if self.expanded.contains(&entry.address) {
+                    "-"
+                } else {
+                    "+"
+                };
+                let suffix = format!(" (0x{:08X}) = ", entry.address);
+                let mut line = highlight_line(&entry.name, &suffix, 24, query);
+                match entry.value {
+                    Some(value) => line.spans.extend(value_spans(value, entry.changed_bits())),
+                    None => line.push_span(format!("{:<18}", "#GP")),
+                }
+                line.push_span(format!(" {}", marker));
+                if let Some(rate) = entry.rate {
+                    line.push_span(Span::styled(
+                        format!(" {}", rate_str(rate)),
+                        Style::default().fg(Color::Green),
+                    ));
+                }
+
+                let first_line = lines.len() as u16;
+                if index == self.selected {
+                    lines.push(line.reversed());
+                } else {
+                    lines.push(line);
+                }
+
+                if self.expanded.contains(&entry.address) {
+                    let decoded = match entry.value {
+                        Some(value) => decode::decode(entry.address, value),
+                        None => vec![String::from("unreadable (#GP)")],
+                    };
+                    if decoded.is_empty() {
+                        lines.push(Line::styled(
+                            "  no bitfield decoding available",
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    for field in decoded {
+                        lines.push(Line::styled(
+                            format!("  {}", field),
+                            Style::default().fg(Color::Cyan),
+                        ));
+                    }
+                }
+
+                if index == self.selected {
+                    selected_lines = (first_line, lines.len() as u16 - 1);
+                }
+                index += 1;
             }
 
             // Empty line between categories (but not after the last one)
@@ -286,6 +701,16 @@ impl Widget for &mut MsrPane {
             }
         }
 
+        // Keep the selected entry, and its decoded lines if they fit, in view
+        let (first, last) = selected_lines;
+        let height = area.height.max(1);
+        if last >= self.scroll.y_offset + height {
+            self.scroll.y_offset = (last + 1).saturating_sub(height).min(first);
+        }
+        if first < self.scroll.y_offset {
+            self.scroll.y_offset = first;
+        }
+
         let n_lines = lines.len();
         let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
 
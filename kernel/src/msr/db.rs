@@ -0,0 +1,176 @@
+//! MSR database: names, bitfield layouts and listing conditions of the known
+//! MSRs, generated by build.rs from msrs.def
+
+use alloc::borrow::Cow;
+use alloc::format;
+
+use super::decode::{BitField, FieldKind};
+use super::hyperv;
+use crate::cpuid::CpuFeatures;
+
+/// Category of the MSR pane, listed if its requirements are met
+pub struct CategoryDef {
+    pub name: &'static str,
+    pub requires: &'static [Condition],
+    /// Reader deciding which MSRs exist, instead of listing `msrs`
+    pub source: Option<Source>,
+    pub msrs: &'static [MsrDef],
+}
+
+/// An MSR, or an array of `count` MSRs `stride` apart
+pub struct MsrDef {
+    /// Name, with `{n}` standing for the index of arrays
+    pub name: &'static str,
+    pub address: u32,
+    pub count: u32,
+    pub stride: u32,
+    pub requires: &'static [Condition],
+    pub fields: &'static [BitField],
+    /// Alternate layouts, used instead of `fields` if their bit matches
+    pub variants: &'static [Variant],
+}
+
+/// Layout of an MSR whose value has bit `bit` `set`
+pub struct Variant {
+    pub bit: u8,
+    pub set: bool,
+    pub fields: &'static [BitField],
+}
+
+impl MsrDef {
+    /// Index of `address` within the array
+    fn index_of(&self, address: u32) -> Option<u32> {
+        let offset = address.checked_sub(self.address)?;
+        (offset % self.stride == 0 && offset / self.stride < self.count)
+            .then_some(offset / self.stride)
+    }
+
+    /// Name of instance `index`
+    pub fn name(&self, index: u32) -> Cow<'static, str> {
+        if self.count == 1 {
+            Cow::Borrowed(self.name)
+        } else {
+            Cow::Owned(self.name.replace("{n}", &format!("{}", index)))
+        }
+    }
+
+    /// Fields of the layout `value` uses
+    fn fields(&self, value: u64) -> &'static [BitField] {
+        self.variants
+            .iter()
+            .find(|v| (value >> v.bit) & 1 == v.set as u64)
+            .map_or(self.fields, |v| v.fields)
+    }
+
+    /// Names and addresses of all instances
+    pub fn instances(&self) -> impl Iterator<Item = (Cow<'static, str>, u32)> + '_ {
+        (0..self.count).map(|n| (self.name(n), self.address + n * self.stride))
+    }
+}
+
+/// Requirement on a CPUID feature
+pub enum Condition {
+    Has(Feature),
+    Lacks(Feature),
+}
+
+impl Condition {
+    fn met(&self, cpufeatures: &CpuFeatures) -> bool {
+        match self {
+            Condition::Has(feature) => feature.present(cpufeatures),
+            Condition::Lacks(feature) => !feature.present(cpufeatures),
+        }
+    }
+}
+
+/// Features MSRs depend on, named in msrs.def in snake case
+pub enum Feature {
+    Rdtscp,
+    TscAdjust,
+    TscDeadline,
+    Aperfmperf,
+    Mce,
+    Mca,
+    Vmx,
+    Hyperv,
+    HvHypercall,
+    HvVpIndex,
+    HvTimeRefCount,
+    HvReferenceTsc,
+    HvSynic,
+    HvSyntheticTimers,
+    HvCrash,
+    Amd,
+    Svm,
+    TscRateMsr,
+    PerfctrCore,
+    Mtrr,
+    Pat,
+}
+
+impl Feature {
+    fn present(&self, cpufeatures: &CpuFeatures) -> bool {
+        use hyperv::*;
+
+        match self {
+            Feature::Rdtscp => cpufeatures.has_rdtscp(),
+            Feature::TscAdjust => cpufeatures.has_tsc_adjust(),
+            Feature::TscDeadline => cpufeatures.has_tsc_deadline(),
+            Feature::Aperfmperf => cpufeatures.has_aperfmperf(),
+            Feature::Mce => cpufeatures.has_mce(),
+            Feature::Mca => cpufeatures.has_mca(),
+            Feature::Vmx => cpufeatures.has_vmx(),
+            Feature::Hyperv => cpufeatures.is_hyperv(),
+            Feature::HvHypercall => granted(cpufeatures, HV_MSR_HYPERCALL_AVAILABLE),
+            Feature::HvVpIndex => granted(cpufeatures, HV_MSR_VP_INDEX_AVAILABLE),
+            Feature::HvTimeRefCount => granted(cpufeatures, HV_MSR_TIME_REF_COUNT_AVAILABLE),
+            Feature::HvReferenceTsc => granted(cpufeatures, HV_MSR_REFERENCE_TSC_AVAILABLE),
+            Feature::HvSynic => granted(cpufeatures, HV_MSR_SYNIC_AVAILABLE),
+            Feature::HvSyntheticTimers => granted(cpufeatures, HV_MSR_SYNTIMER_AVAILABLE),
+            Feature::HvCrash => has_crash_msrs(cpufeatures),
+            Feature::Amd => cpufeatures.vendor_info().amd,
+            Feature::Svm => cpufeatures.svm_info().is_some(),
+            Feature::TscRateMsr => cpufeatures.has_tsc_rate_msr(),
+            Feature::PerfctrCore => cpufeatures.has_perf_cntr_extensions(),
+            Feature::Mtrr => cpufeatures.has_mtrr(),
+            Feature::Pat => cpufeatures.has_pat(),
+        }
+    }
+}
+
+/// Readers for categories whose MSRs are enumerated by other MSRs
+pub enum Source {
+    /// MCG registers and the banks MCG_CAP reports
+    McBanks,
+    /// VMX capability MSRs the basic and control capabilities report
+    Vmx,
+    /// Variable ranges MTRRCAP reports and fixed ranges if supported
+    Mtrrs,
+}
+
+/// Whether all `conditions` hold
+pub fn met(conditions: &[Condition], cpufeatures: &CpuFeatures) -> bool {
+    conditions.iter().all(|c| c.met(cpufeatures))
+}
+
+include!(concat!(env!("OUT_DIR"), "/msrdb.rs"));
+
+/// Definition of the MSR at `address` and its index within the array
+fn find(address: u32) -> Option<(&'static MsrDef, u32)> {
+    CATEGORIES
+        .iter()
+        .flat_map(|c| c.msrs)
+        .chain(UNLISTED)
+        .find_map(|msr| Some((msr, msr.index_of(address)?)))
+}
+
+/// Name of a known MSR
+pub fn name(address: u32) -> Option<Cow<'static, str>> {
+    find(address).map(|(msr, index)| msr.name(index))
+}
+
+/// Bitfield layout of the MSR at `address` holding `value`, empty if
+/// unknown
+pub fn fields(address: u32, value: u64) -> &'static [BitField] {
+    find(address).map_or(&[], |(msr, _)| msr.fields(value))
+}
@@ -0,0 +1,142 @@
+//! Bitfield layouts of MSRs and their decoding into readable lines
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{db, vmx};
+use crate::mca::ErrorCode;
+
+/// How the value of a bitfield is shown
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Single bit, shown as 0/1
+    Flag,
+    /// Unsigned number, shown in decimal and hex
+    Number,
+    /// Address bits, shown in place (masked, not shifted)
+    Address,
+    /// PAT memory type encoding
+    PatType,
+    /// MTRR memory type encoding
+    MtrrType,
+    /// Segment selector: index, table indicator and RPL
+    Selector,
+    /// Architectural MCA error code
+    McaCode,
+}
+
+/// A bitfield within an MSR, spanning bits `lsb..=msb`
+pub struct BitField {
+    pub name: &'static str,
+    pub lsb: u8,
+    pub msb: u8,
+    pub kind: FieldKind,
+    pub desc: &'static str,
+}
+
+impl BitField {
+    pub const fn new(
+        name: &'static str,
+        lsb: u8,
+        msb: u8,
+        kind: FieldKind,
+        desc: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            lsb,
+            msb,
+            kind,
+            desc,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        let width = (self.msb - self.lsb + 1) as u32;
+        let bits = if width == 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        bits << self.lsb
+    }
+
+    /// Field value shifted down to bit 0
+    pub fn extract(&self, value: u64) -> u64 {
+        (value & self.mask()) >> self.lsb
+    }
+
+    fn bits_str(&self) -> String {
+        if self.lsb == self.msb {
+            format!("[{}]", self.lsb)
+        } else {
+            format!("[{}:{}]", self.msb, self.lsb)
+        }
+    }
+
+    fn value_str(&self, value: u64) -> String {
+        let field = self.extract(value);
+        match self.kind {
+            FieldKind::Flag => format!("{}", field),
+            FieldKind::Number => format!("{} (0x{:x})", field, field),
+            FieldKind::Address => format!("0x{:x}", value & self.mask()),
+            FieldKind::PatType => format!("{} ({})", field, pat_type_name(field)),
+            FieldKind::MtrrType => format!("{} ({})", field, mtrr_type_name(field)),
+            FieldKind::McaCode => format!("0x{:04x} ({})", field, ErrorCode(field as u16)),
+            FieldKind::Selector => format!(
+                "0x{:04x} (index {}, {}, RPL {})",
+                field,
+                field >> 3,
+                if field & 0b100 != 0 { "LDT" } else { "GDT" },
+                field & 0b11
+            ),
+        }
+    }
+}
+
+/// Name of a PAT memory type encoding
+pub fn pat_type_name(ty: u64) -> &'static str {
+    match ty {
+        0 => "UC",
+        1 => "WC",
+        4 => "WT",
+        5 => "WP",
+        6 => "WB",
+        7 => "UC-",
+        _ => "reserved",
+    }
+}
+
+/// Name of an MTRR memory type encoding
+pub fn mtrr_type_name(ty: u64) -> &'static str {
+    match ty {
+        0 => "UC",
+        1 => "WC",
+        4 => "WT",
+        5 => "WP",
+        6 => "WB",
+        _ => "reserved",
+    }
+}
+
+/// Decode `value` into one line per bitfield of the MSR at `address`, or one
+/// line per control for VMX capability MSRs
+pub fn decode(address: u32, value: u64) -> Vec<String> {
+    if let Some(lines) = vmx::decode_controls(address, value) {
+        return lines;
+    }
+
+    db::fields(address, value)
+        .iter()
+        .map(|field| {
+            format!(
+                "{:<8}{:<12}{:<30}{}",
+                field.bits_str(),
+                field.name,
+                field.value_str(value),
+                field.desc
+            )
+        })
+        .collect()
+}
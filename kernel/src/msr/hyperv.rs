@@ -0,0 +1,41 @@
+//! Hyper-V synthetic MSRs, listed when the hypervisor signature is
+//! "Microsoft Hv"
+
+use crate::cpuid::CpuFeatures;
+
+/// Feature identification leaf: partition privileges in EBX:EAX, features in
+/// EDX
+const HYPERV_FEATURES_LEAF: u32 = 0x4000_0003;
+
+pub const HV_X64_MSR_TIME_REF_COUNT: u32 = 0x4000_0020;
+
+// Partition privileges, EAX
+pub const HV_MSR_TIME_REF_COUNT_AVAILABLE: u32 = 1 << 1;
+pub const HV_MSR_SYNIC_AVAILABLE: u32 = 1 << 2;
+pub const HV_MSR_SYNTIMER_AVAILABLE: u32 = 1 << 3;
+pub const HV_MSR_HYPERCALL_AVAILABLE: u32 = 1 << 5;
+pub const HV_MSR_VP_INDEX_AVAILABLE: u32 = 1 << 6;
+pub const HV_MSR_REFERENCE_TSC_AVAILABLE: u32 = 1 << 9;
+
+// Features, EDX
+const HV_FEATURE_GUEST_CRASH_MSR_AVAILABLE: u32 = 1 << 10;
+
+/// Partition privileges and features of leaf 0x4000_0003, zero if the leaf
+/// is not reported
+fn features_leaf(cpufeatures: &CpuFeatures) -> [u32; 4] {
+    if cpufeatures.leaf(0x4000_0000, 0)[0] < HYPERV_FEATURES_LEAF {
+        return [0; 4];
+    }
+    cpufeatures.leaf(HYPERV_FEATURES_LEAF, 0)
+}
+
+/// Whether the partition has a privilege. Reads of MSRs without the
+/// privilege #GP.
+pub fn granted(cpufeatures: &CpuFeatures, privilege: u32) -> bool {
+    features_leaf(cpufeatures)[0] & privilege != 0
+}
+
+/// Whether the guest crash MSRs are available
+pub fn has_crash_msrs(cpufeatures: &CpuFeatures) -> bool {
+    features_leaf(cpufeatures)[3] & HV_FEATURE_GUEST_CRASH_MSR_AVAILABLE != 0
+}
@@ -0,0 +1,426 @@
+//! MTRR decoding into a physical memory type map, combined with PAT
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+
+use super::{MSR_MTRR_DEF_TYPE, MSR_MTRR_PHYSBASE0, MSR_MTRRCAP, MSR_PAT, try_read_msr};
+use crate::cpuid::CpuFeatures;
+use crate::pane::ScrollHints;
+
+/// Fixed-range MTRRs with the start and size of the ranges they cover.
+/// Each MSR holds eight type bytes, one per range.
+pub const FIXED_MTRRS: [(u32, u64, u64); 11] = [
+    (0x250, 0x00000, 0x10000),
+    (0x258, 0x80000, 0x4000),
+    (0x259, 0xA0000, 0x4000),
+    (0x268, 0xC0000, 0x1000),
+    (0x269, 0xC8000, 0x1000),
+    (0x26A, 0xD0000, 0x1000),
+    (0x26B, 0xD8000, 0x1000),
+    (0x26C, 0xE0000, 0x1000),
+    (0x26D, 0xE8000, 0x1000),
+    (0x26E, 0xF0000, 0x1000),
+    (0x26F, 0xF8000, 0x1000),
+];
+
+/// End of the range covered by fixed-range MTRRs
+const FIXED_END: u64 = 0x10_0000;
+
+/// Addresses of the PHYSBASE and PHYSMASK MSRs of variable range `index`
+pub fn variable_range_msrs(index: u32) -> (u32, u32) {
+    let base = MSR_MTRR_PHYSBASE0 + index * 2;
+    (base, base + 1)
+}
+
+/// Effective memory type of an access
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryType {
+    Uncacheable,
+    WriteCombining,
+    WriteThrough,
+    WriteProtected,
+    WriteBack,
+    /// PAT UC-, which WC from the MTRRs can override
+    UncachedMinus,
+    /// Reserved encoding, or overlapping variable ranges with a combination
+    /// the SDM leaves undefined
+    Undefined,
+}
+
+impl MemoryType {
+    /// Decode an MTRR type encoding, UC- only exists in PAT
+    pub fn from_mtrr(ty: u64) -> Self {
+        match ty {
+            7 => Self::Undefined,
+            ty => Self::from_pat(ty),
+        }
+    }
+
+    /// Decode a PAT type encoding
+    pub fn from_pat(ty: u64) -> Self {
+        match ty {
+            0 => Self::Uncacheable,
+            1 => Self::WriteCombining,
+            4 => Self::WriteThrough,
+            5 => Self::WriteProtected,
+            6 => Self::WriteBack,
+            7 => Self::UncachedMinus,
+            _ => Self::Undefined,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uncacheable => "UC",
+            Self::WriteCombining => "WC",
+            Self::WriteThrough => "WT",
+            Self::WriteProtected => "WP",
+            Self::WriteBack => "WB",
+            Self::UncachedMinus => "UC-",
+            Self::Undefined => "??",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Uncacheable | Self::UncachedMinus => Color::Red,
+            Self::WriteCombining => Color::Magenta,
+            Self::WriteThrough | Self::WriteProtected => Color::Yellow,
+            Self::WriteBack => Color::Green,
+            Self::Undefined => Color::DarkGray,
+        }
+    }
+
+    /// Combine the MTRR type of a physical address with the PAT type of the
+    /// mapping, per the SDM table "Effective Page-Level Memory Types for
+    /// Pentium III and More Recent Processor Families"
+    pub fn with_pat(self, pat: MemoryType) -> MemoryType {
+        use MemoryType::*;
+
+        match (self, pat) {
+            (Undefined, _) | (_, Undefined) => Undefined,
+            (_, Uncacheable) => Uncacheable,
+            (_, WriteCombining) => WriteCombining,
+            (WriteCombining | WriteProtected, UncachedMinus) => WriteCombining,
+            (_, UncachedMinus) => Uncacheable,
+            (Uncacheable, _) => Uncacheable,
+            (WriteCombining, WriteBack) => WriteCombining,
+            (WriteCombining, _) => Uncacheable,
+            (WriteThrough, WriteBack) => WriteThrough,
+            (WriteProtected, WriteBack) => WriteProtected,
+            (_, pat) => pat,
+        }
+    }
+}
+
+/// An enabled variable range
+#[derive(Clone, Copy)]
+pub struct VariableRange {
+    pub index: u32,
+    pub base: u64,
+    pub mask: u64,
+    pub mem_type: MemoryType,
+}
+
+impl VariableRange {
+    fn contains(&self, address: u64) -> bool {
+        address & self.mask == self.base & self.mask
+    }
+
+    /// Size of the range, assuming a contiguous mask
+    fn size(&self, address_mask: u64) -> u64 {
+        (!self.mask & address_mask).wrapping_add(1)
+    }
+}
+
+/// MTRR configuration read from the MSRs
+pub struct MtrrState {
+    pub enabled: bool,
+    pub fixed_enabled: bool,
+    pub default_type: MemoryType,
+    /// Type bytes of the fixed-range MTRRs, in `FIXED_MTRRS` order
+    pub fixed: Option<[u64; FIXED_MTRRS.len()]>,
+    pub variable_count: u32,
+    pub variable: Vec<VariableRange>,
+    /// Mask of the implemented physical address bits
+    pub address_mask: u64,
+}
+
+impl MtrrState {
+    /// Read all MTRRs. Returns `None` if MTRRs are not supported.
+    pub fn read(physical_address_bits: u8) -> Option<Self> {
+        let cap = try_read_msr(MSR_MTRRCAP).ok()?;
+        let def_type = try_read_msr(MSR_MTRR_DEF_TYPE).ok()?;
+        let address_mask = (1u64 << physical_address_bits) - 1;
+
+        let variable_count = (cap & 0xFF) as u32;
+        let variable = (0..variable_count)
+            .filter_map(|index| {
+                let (base_msr, mask_msr) = variable_range_msrs(index);
+                let base = try_read_msr(base_msr).ok()?;
+                let mask = try_read_msr(mask_msr).ok()?;
+                // PHYSMASK.V
+                (mask & (1 << 11) != 0).then(|| VariableRange {
+                    index,
+                    base: base & address_mask & !0xFFF,
+                    mask: mask & address_mask & !0xFFF,
+                    mem_type: MemoryType::from_mtrr(base & 0xFF),
+                })
+            })
+            .collect();
+
+        // MTRRCAP.FIX
+        let fixed = (cap & (1 << 8) != 0)
+            .then(|| FIXED_MTRRS.map(|(msr, ..)| try_read_msr(msr).unwrap_or(0)));
+
+        Some(Self {
+            enabled: def_type & (1 << 11) != 0,
+            fixed_enabled: def_type & (1 << 10) != 0,
+            default_type: MemoryType::from_mtrr(def_type & 0xFF),
+            fixed,
+            variable_count,
+            variable,
+            address_mask,
+        })
+    }
+
+    /// Memory type the MTRRs assign to a physical address
+    pub fn memory_type(&self, address: u64) -> MemoryType {
+        if !self.enabled {
+            return MemoryType::Uncacheable;
+        }
+
+        if let Some(ty) = self.fixed_type(address) {
+            return ty;
+        }
+
+        let mut matching = self
+            .variable
+            .iter()
+            .filter(|range| range.contains(address))
+            .map(|range| range.mem_type);
+        let Some(first) = matching.next() else {
+            return self.default_type;
+        };
+
+        // Overlaps: UC wins, WT wins over WB, anything else is undefined
+        matching.fold(first, |acc, ty| match (acc, ty) {
+            (MemoryType::Uncacheable, _) | (_, MemoryType::Uncacheable) => MemoryType::Uncacheable,
+            (a, b) if a == b => a,
+            (MemoryType::WriteThrough, MemoryType::WriteBack)
+            | (MemoryType::WriteBack, MemoryType::WriteThrough) => MemoryType::WriteThrough,
+            _ => MemoryType::Undefined,
+        })
+    }
+
+    fn fixed_type(&self, address: u64) -> Option<MemoryType> {
+        let fixed = self.fixed.as_ref().filter(|_| self.fixed_enabled)?;
+        if address >= FIXED_END {
+            return None;
+        }
+
+        FIXED_MTRRS
+            .iter()
+            .zip(fixed)
+            .find_map(|(&(_, start, size), value)| {
+                let offset = address.checked_sub(start)?;
+                let byte = offset / size;
+                (byte < 8).then(|| MemoryType::from_mtrr((value >> (byte * 8)) & 0xFF))
+            })
+    }
+
+    /// Physical address space split into regions of the same memory type
+    pub fn regions(&self) -> Vec<(u64, u64, MemoryType)> {
+        let end = self.address_mask + 1;
+        let mut bounds = vec![0, end];
+
+        if self.enabled {
+            if self.fixed.is_some() && self.fixed_enabled {
+                for &(_, start, size) in &FIXED_MTRRS {
+                    bounds.extend((0..=8).map(|i| start + i * size));
+                }
+            }
+            for range in &self.variable {
+                bounds.push(range.base);
+                bounds.push(range.base.saturating_add(range.size(self.address_mask)));
+            }
+        }
+
+        bounds.retain(|&b| b <= end);
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut regions: Vec<(u64, u64, MemoryType)> = Vec::new();
+        for window in bounds.windows(2) {
+            let ty = self.memory_type(window[0]);
+            match regions.last_mut() {
+                Some(last) if last.2 == ty && last.1 == window[0] => last.1 = window[1],
+                _ => regions.push((window[0], window[1], ty)),
+            }
+        }
+        regions
+    }
+}
+
+/// Type PAT entry `index` selects
+pub fn pat_entry(pat: u64, index: u8) -> MemoryType {
+    MemoryType::from_pat((pat >> (index as u32 * 8)) & 0x7)
+}
+
+/// Effective memory type of a physical address mapped with PAT entry `index`
+pub fn effective_type(
+    mtrr: Option<&MtrrState>,
+    pat: Option<u64>,
+    address: u64,
+    index: u8,
+) -> String {
+    let mtrr_type = mtrr.map_or(MemoryType::Uncacheable, |m| m.memory_type(address));
+    match pat {
+        Some(pat) => {
+            let pat_type = pat_entry(pat, index);
+            format!(
+                "MTRR {} + PA{} {} = {}",
+                mtrr_type.name(),
+                index,
+                pat_type.name(),
+                mtrr_type.with_pat(pat_type).name()
+            )
+        }
+        None => format!("MTRR {} (no PAT)", mtrr_type.name()),
+    }
+}
+
+/// Memory type map view of the MSR pane
+pub struct MemoryTypeMap {
+    physical_address_bits: u8,
+    has_mtrr: bool,
+    has_pat: bool,
+    pub scroll: ScrollHints,
+}
+
+impl MemoryTypeMap {
+    pub fn new(cpufeatures: &CpuFeatures) -> Self {
+        Self {
+            physical_address_bits: cpufeatures.physical_address_bits(),
+            has_mtrr: cpufeatures.has_mtrr(),
+            has_pat: cpufeatures.has_pat(),
+            scroll: ScrollHints::default(),
+        }
+    }
+
+    pub fn mtrr_state(&self) -> Option<MtrrState> {
+        if !self.has_mtrr {
+            return None;
+        }
+        MtrrState::read(self.physical_address_bits)
+    }
+
+    pub fn pat(&self) -> Option<u64> {
+        if !self.has_pat {
+            return None;
+        }
+        try_read_msr(MSR_PAT).ok()
+    }
+
+    /// Effective memory type of a physical address mapped with PAT entry
+    /// `index`, with the current MTRRs and PAT
+    pub fn effective_type(&self, address: u64, index: u8) -> String {
+        effective_type(self.mtrr_state().as_ref(), self.pat(), address, index)
+    }
+}
+
+impl Widget for &mut MemoryTypeMap {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = vec![Line::styled("Memory Types", Style::default().bold())];
+
+        let Some(state) = self.mtrr_state() else {
+            lines.push(Line::raw("MTRRs not supported"));
+            Paragraph::new(lines).render(area, buf);
+            return;
+        };
+        let pat = self.pat();
+
+        let on_off = |b: bool| if b { "enabled" } else { "disabled" };
+        lines.push(Line::raw(format!(
+            "{:<16}{}, fixed ranges {}, default {}",
+            "MTRRs:",
+            on_off(state.enabled),
+            if state.fixed.is_some() {
+                on_off(state.fixed_enabled)
+            } else {
+                "unsupported"
+            },
+            state.default_type.name()
+        )));
+        lines.push(Line::raw(format!(
+            "{:<16}{} of {} enabled, {}-bit physical addresses",
+            "Variable:",
+            state.variable.len(),
+            state.variable_count,
+            self.physical_address_bits
+        )));
+        for range in &state.variable {
+            lines.push(Line::raw(format!(
+                "  {:<5}base 0x{:013x} mask 0x{:013x} {}",
+                range.index,
+                range.base,
+                range.mask,
+                range.mem_type.name()
+            )));
+        }
+
+        if let Some(pat) = pat {
+            let entries: Vec<String> = (0..8)
+                .map(|i| format!("{}={}", i, pat_entry(pat, i).name()))
+                .collect();
+            lines.push(Line::raw(format!("{:<16}{}", "PAT:", entries.join(" "))));
+        }
+        lines.push(Line::raw(""));
+
+        // One column per PAT entry with the type a mapping using it gets
+        let mut header = format!("{:<28}{:<6}", "Physical range", "MTRR");
+        if pat.is_some() {
+            for i in 0..8 {
+                header.push_str(&format!("{:<5}", format!("PA{}", i)));
+            }
+        }
+        lines.push(Line::styled(header, Style::default().bold()));
+
+        for (start, end, ty) in state.regions() {
+            let mut spans = vec![
+                format!("{:013x}-{:013x} ", start, end - 1).into(),
+                Span::styled(format!("{:<6}", ty.name()), Style::default().fg(ty.color())),
+            ];
+            if let Some(pat) = pat {
+                for i in 0..8 {
+                    let effective = ty.with_pat(pat_entry(pat, i));
+                    spans.push(Span::styled(
+                        format!("{:<5}", effective.name()),
+                        Style::default().fg(effective.color()),
+                    ));
+                }
+            }
+            lines.push(Line::from(spans));
+        }
+
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            "Query with :memtype <virt> or :memtype <phys> <PAT index>",
+            Style::default().fg(Color::DarkGray),
+        ));
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
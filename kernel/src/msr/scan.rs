@@ -0,0 +1,158 @@
+//! Probing MSR address ranges for implemented registers
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Widget};
+
+use super::{db, parse_address, try_read_msr};
+use crate::pane::ScrollHints;
+
+/// Ranges probed by `scan` without arguments: architectural, AMD/long mode
+/// and the hypervisor range
+pub const DEFAULT_RANGES: [RangeInclusive<u32>; 3] = [
+    0x0000_0000..=0x0000_1FFF,
+    0xC000_0000..=0xC000_1FFF,
+    0x4000_0000..=0x4000_00FF,
+];
+
+/// Upper bound on probed addresses per scan
+const MAX_SCAN_SIZE: u64 = 1 << 20;
+
+/// Parse ranges like `0x0-0x1fff 0xc0000080`, separated by spaces or commas
+pub fn parse_ranges(input: &str) -> Result<Vec<RangeInclusive<u32>>, String> {
+    let mut ranges = Vec::new();
+
+    for item in input.split([' ', ',']).filter(|s| !s.is_empty()) {
+        let (start, end) = item.split_once('-').unwrap_or((item, item));
+        let (start, end) = (parse_address(start)?, parse_address(end)?);
+        if start > end {
+            return Err(format!("empty range: {}", item));
+        }
+        ranges.push(start..=end);
+    }
+
+    let total: u64 = ranges.iter().map(range_len).sum();
+    if total > MAX_SCAN_SIZE {
+        return Err(format!("scan too large: {} MSRs", total));
+    }
+    Ok(ranges)
+}
+
+fn range_len(range: &RangeInclusive<u32>) -> u64 {
+    (*range.end() - *range.start()) as u64 + 1
+}
+
+/// Scan in progress or finished, advanced in batches from the main loop
+pub struct MsrScan {
+    ranges: Vec<RangeInclusive<u32>>,
+    /// Index of the range being probed
+    range: usize,
+    /// Next address to probe within that range
+    next: u64,
+    probed: u64,
+    total: u64,
+    found: Vec<(u32, u64)>,
+    pub scroll: ScrollHints,
+}
+
+impl MsrScan {
+    pub fn new(ranges: Vec<RangeInclusive<u32>>) -> Self {
+        let total = ranges.iter().map(range_len).sum();
+        let next = ranges.first().map_or(0, |r| *r.start() as u64);
+        Self {
+            ranges,
+            range: 0,
+            next,
+            probed: 0,
+            total,
+            found: Vec::new(),
+            scroll: ScrollHints::default(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.range >= self.ranges.len()
+    }
+
+    /// Probe up to `budget` addresses. Returns true if the scan completed.
+    pub fn step(&mut self, budget: usize) -> bool {
+        for _ in 0..budget {
+            let Some(range) = self.ranges.get(self.range) else {
+                return true;
+            };
+
+            let address = self.next as u32;
+            if let Ok(value) = try_read_msr(address) {
+                self.found.push((address, value));
+            }
+            self.probed += 1;
+
+            if self.next >= *range.end() as u64 {
+                self.range += 1;
+                if let Some(next_range) = self.ranges.get(self.range) {
+                    self.next = *next_range.start() as u64;
+                }
+            } else {
+                self.next += 1;
+            }
+        }
+        self.is_done()
+    }
+}
+
+impl Widget for &mut MsrScan {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let ranges: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|r| format!("0x{:X}-0x{:X}", r.start(), r.end()))
+            .collect();
+        let mut lines = vec![
+            Line::styled("MSR Scan", Style::default().bold()),
+            Line::raw(format!("{:<10}{}", "Ranges:", ranges.join(" "))),
+        ];
+
+        let percent = (self.probed * 100).checked_div(self.total).unwrap_or(100);
+        let status = if self.is_done() { "done" } else { "scanning" };
+        lines.push(Line::raw(format!(
+            "{:<10}{}/{} ({}%) {}, {} readable",
+            "Progress:",
+            self.probed,
+            self.total,
+            percent,
+            status,
+            self.found.len()
+        )));
+        lines.push(Line::raw(""));
+
+        for (address, value) in &self.found {
+            let name = db::name(*address);
+            let line = format!(
+                "0x{:08X}  {:<26}0x{:016x}",
+                address,
+                name.as_deref().unwrap_or("-"),
+                value
+            );
+            let style = if name.is_some() {
+                Style::default()
+            } else {
+                Style::default().fg(Color::Yellow)
+            };
+            lines.push(Line::styled(line, style));
+        }
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
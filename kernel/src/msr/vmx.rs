@@ -0,0 +1,272 @@
+//! VMX capability MSRs and the allowed settings of the VM-execution controls
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{MsrEntry, read_known, try_read_msr};
+
+pub const MSR_IA32_FEATURE_CONTROL: u32 = 0x3A;
+pub const MSR_VMX_BASIC: u32 = 0x480;
+pub const MSR_VMX_PINBASED_CTLS: u32 = 0x481;
+pub const MSR_VMX_PROCBASED_CTLS: u32 = 0x482;
+pub const MSR_VMX_EXIT_CTLS: u32 = 0x483;
+pub const MSR_VMX_ENTRY_CTLS: u32 = 0x484;
+pub const MSR_VMX_MISC: u32 = 0x485;
+pub const MSR_VMX_CR0_FIXED0: u32 = 0x486;
+pub const MSR_VMX_CR0_FIXED1: u32 = 0x487;
+pub const MSR_VMX_CR4_FIXED0: u32 = 0x488;
+pub const MSR_VMX_CR4_FIXED1: u32 = 0x489;
+pub const MSR_VMX_VMCS_ENUM: u32 = 0x48A;
+pub const MSR_VMX_PROCBASED_CTLS2: u32 = 0x48B;
+pub const MSR_VMX_EPT_VPID_CAP: u32 = 0x48C;
+pub const MSR_VMX_TRUE_PINBASED_CTLS: u32 = 0x48D;
+pub const MSR_VMX_TRUE_PROCBASED_CTLS: u32 = 0x48E;
+pub const MSR_VMX_TRUE_EXIT_CTLS: u32 = 0x48F;
+pub const MSR_VMX_TRUE_ENTRY_CTLS: u32 = 0x490;
+pub const MSR_VMX_VMFUNC: u32 = 0x491;
+pub const MSR_VMX_PROCBASED_CTLS3: u32 = 0x492;
+pub const MSR_VMX_EXIT_CTLS2: u32 = 0x493;
+
+/// Whether a control with 32-bit allowed-0/allowed-1 halves may be set to 1
+fn allowed_1(value: u64, bit: u32) -> bool {
+    value & (1 << (bit + 32)) != 0
+}
+
+/// Read the VMX capability MSRs. The optional ones are only read if the
+/// capability MSRs already read say they exist.
+pub fn read_vmx_msrs() -> Vec<MsrEntry> {
+    let mut entries = read_known(&[
+        MSR_IA32_FEATURE_CONTROL,
+        MSR_VMX_BASIC,
+        MSR_VMX_PINBASED_CTLS,
+        MSR_VMX_PROCBASED_CTLS,
+        MSR_VMX_EXIT_CTLS,
+        MSR_VMX_ENTRY_CTLS,
+        MSR_VMX_MISC,
+        MSR_VMX_CR0_FIXED0,
+        MSR_VMX_CR0_FIXED1,
+        MSR_VMX_CR4_FIXED0,
+        MSR_VMX_CR4_FIXED1,
+        MSR_VMX_VMCS_ENUM,
+    ]);
+    let read = |address| try_read_msr(address).unwrap_or(0);
+    let basic = read(MSR_VMX_BASIC);
+    let procbased = read(MSR_VMX_PROCBASED_CTLS);
+    let exit = read(MSR_VMX_EXIT_CTLS);
+
+    let mut optional = Vec::new();
+    // IA32_VMX_BASIC[55]: TRUE controls report which default1 controls may be 0
+    if basic & (1 << 55) != 0 {
+        optional.extend([
+            MSR_VMX_TRUE_PINBASED_CTLS,
+            MSR_VMX_TRUE_PROCBASED_CTLS,
+            MSR_VMX_TRUE_EXIT_CTLS,
+            MSR_VMX_TRUE_ENTRY_CTLS,
+        ]);
+    }
+    // Activate secondary controls
+    if allowed_1(procbased, 31) {
+        optional.push(MSR_VMX_PROCBASED_CTLS2);
+        let procbased2 = read(MSR_VMX_PROCBASED_CTLS2);
+        // Enable EPT or enable VPID
+        if allowed_1(procbased2, 1) || allowed_1(procbased2, 5) {
+            optional.push(MSR_VMX_EPT_VPID_CAP);
+        }
+        // Enable VM functions
+        if allowed_1(procbased2, 13) {
+            optional.push(MSR_VMX_VMFUNC);
+        }
+    }
+    // Activate tertiary controls
+    if allowed_1(procbased, 17) {
+        optional.push(MSR_VMX_PROCBASED_CTLS3);
+    }
+    // Activate secondary exit controls
+    if allowed_1(exit, 31) {
+        optional.push(MSR_VMX_EXIT_CTLS2);
+    }
+
+    optional.sort_unstable();
+    entries.extend(read_known(&optional));
+    entries
+}
+
+const PINBASED_CONTROLS: &[(u8, &str)] = &[
+    (0, "External-interrupt exiting"),
+    (3, "NMI exiting"),
+    (5, "Virtual NMIs"),
+    (6, "Activate VMX-preemption timer"),
+    (7, "Process posted interrupts"),
+];
+
+const PROCBASED_CONTROLS: &[(u8, &str)] = &[
+    (2, "Interrupt-window exiting"),
+    (3, "Use TSC offsetting"),
+    (7, "HLT exiting"),
+    (9, "INVLPG exiting"),
+    (10, "MWAIT exiting"),
+    (11, "RDPMC exiting"),
+    (12, "RDTSC exiting"),
+    (15, "CR3-load exiting"),
+    (16, "CR3-store exiting"),
+    (17, "Activate tertiary controls"),
+    (19, "CR8-load exiting"),
+    (20, "CR8-store exiting"),
+    (21, "Use TPR shadow"),
+    (22, "NMI-window exiting"),
+    (23, "MOV-DR exiting"),
+    (24, "Unconditional I/O exiting"),
+    (25, "Use I/O bitmaps"),
+    (27, "Monitor trap flag"),
+    (28, "Use MSR bitmaps"),
+    (29, "MONITOR exiting"),
+    (30, "PAUSE exiting"),
+    (31, "Activate secondary controls"),
+];
+
+const PROCBASED2_CONTROLS: &[(u8, &str)] = &[
+    (0, "Virtualize APIC accesses"),
+    (1, "Enable EPT"),
+    (2, "Descriptor-table exiting"),
+    (3, "Enable RDTSCP"),
+    (4, "Virtualize x2APIC mode"),
+    (5, "Enable VPID"),
+    (6, "WBINVD exiting"),
+    (7, "Unrestricted guest"),
+    (8, "APIC-register virtualization"),
+    (9, "Virtual-interrupt delivery"),
+    (10, "PAUSE-loop exiting"),
+    (11, "RDRAND exiting"),
+    (12, "Enable INVPCID"),
+    (13, "Enable VM functions"),
+    (14, "VMCS shadowing"),
+    (15, "Enable ENCLS exiting"),
+    (16, "RDSEED exiting"),
+    (17, "Enable PML"),
+    (18, "EPT-violation #VE"),
+    (19, "Conceal VMX from PT"),
+    (20, "Enable XSAVES/XRSTORS"),
+    (21, "PASID translation"),
+    (22, "Mode-based execute control for EPT"),
+    (23, "Sub-page write permissions for EPT"),
+    (24, "PT uses guest physical addresses"),
+    (25, "Use TSC scaling"),
+    (26, "Enable user wait and pause"),
+    (27, "Enable PCONFIG"),
+    (28, "Enable ENCLV exiting"),
+    (30, "VMM bus-lock detection"),
+    (31, "Instruction timeout"),
+];
+
+const PROCBASED3_CONTROLS: &[(u8, &str)] = &[
+    (0, "LOADIWKEY exiting"),
+    (1, "Enable HLAT"),
+    (2, "EPT paging-write control"),
+    (3, "Guest-paging verification"),
+    (4, "IPI virtualization"),
+    (7, "Virtualize IA32_SPEC_CTRL"),
+];
+
+const EXIT_CONTROLS: &[(u8, &str)] = &[
+    (2, "Save debug controls"),
+    (9, "Host address-space size"),
+    (12, "Load IA32_PERF_GLOBAL_CTRL"),
+    (15, "Acknowledge interrupt on exit"),
+    (18, "Save IA32_PAT"),
+    (19, "Load IA32_PAT"),
+    (20, "Save IA32_EFER"),
+    (21, "Load IA32_EFER"),
+    (22, "Save VMX-preemption timer value"),
+    (23, "Clear IA32_BNDCFGS"),
+    (24, "Conceal VMX from PT"),
+    (25, "Clear IA32_RTIT_CTL"),
+    (26, "Clear IA32_LBR_CTL"),
+    (27, "Clear UINV"),
+    (28, "Load CET state"),
+    (29, "Load PKRS"),
+    (30, "Save IA32_PERF_GLOBAL_CTL"),
+    (31, "Activate secondary controls"),
+];
+
+const EXIT2_CONTROLS: &[(u8, &str)] = &[(3, "Prematurely busy shadow stack")];
+
+const ENTRY_CONTROLS: &[(u8, &str)] = &[
+    (2, "Load debug controls"),
+    (9, "IA-32e mode guest"),
+    (10, "Entry to SMM"),
+    (11, "Deactivate dual-monitor treatment"),
+    (13, "Load IA32_PERF_GLOBAL_CTRL"),
+    (14, "Load IA32_PAT"),
+    (15, "Load IA32_EFER"),
+    (16, "Load IA32_BNDCFGS"),
+    (17, "Conceal VMX from PT"),
+    (18, "Load IA32_RTIT_CTL"),
+    (19, "Load UINV"),
+    (20, "Load CET state"),
+    (21, "Load guest IA32_LBR_CTL"),
+    (22, "Load PKRS"),
+];
+
+const VM_FUNCTIONS: &[(u8, &str)] = &[(0, "EPTP switching")];
+
+/// How a capability MSR reports the allowed settings of its controls
+enum Layout {
+    /// Bits 31:0 are the allowed-0 settings, a 1 means the control must be 1.
+    /// Bits 63:32 are the allowed-1 settings, a 0 means it must be 0.
+    Allowed0And1,
+    /// All 64 bits are allowed-1 settings, every control may be 0
+    Allowed1,
+}
+
+fn controls(address: u32) -> Option<(&'static [(u8, &'static str)], Layout)> {
+    use Layout::*;
+
+    Some(match address {
+        MSR_VMX_PINBASED_CTLS | MSR_VMX_TRUE_PINBASED_CTLS => (PINBASED_CONTROLS, Allowed0And1),
+        MSR_VMX_PROCBASED_CTLS | MSR_VMX_TRUE_PROCBASED_CTLS => (PROCBASED_CONTROLS, Allowed0And1),
+        MSR_VMX_PROCBASED_CTLS2 => (PROCBASED2_CONTROLS, Allowed0And1),
+        MSR_VMX_PROCBASED_CTLS3 => (PROCBASED3_CONTROLS, Allowed1),
+        MSR_VMX_EXIT_CTLS | MSR_VMX_TRUE_EXIT_CTLS => (EXIT_CONTROLS, Allowed0And1),
+        MSR_VMX_EXIT_CTLS2 => (EXIT2_CONTROLS, Allowed1),
+        MSR_VMX_ENTRY_CTLS | MSR_VMX_TRUE_ENTRY_CTLS => (ENTRY_CONTROLS, Allowed0And1),
+        MSR_VMX_VMFUNC => (VM_FUNCTIONS, Allowed1),
+        _ => return None,
+    })
+}
+
+/// Decode the allowed settings of every control reported by a VMX capability
+/// MSR. `None` if the MSR does not report controls.
+pub fn decode_controls(address: u32, value: u64) -> Option<Vec<String>> {
+    let (names, layout) = controls(address)?;
+    let (must_be_1, may_be_1) = match layout {
+        Layout::Allowed0And1 => (value & 0xFFFF_FFFF, value >> 32),
+        Layout::Allowed1 => (0, value),
+    };
+
+    let mut lines: Vec<String> = names
+        .iter()
+        .map(|&(bit, name)| {
+            let setting = if must_be_1 & (1 << bit) != 0 {
+                "fixed 1"
+            } else if may_be_1 & (1 << bit) != 0 {
+                "0 or 1"
+            } else {
+                "fixed 0"
+            };
+            format!("{:<8}{:<42}{}", format!("[{}]", bit), name, setting)
+        })
+        .collect();
+
+    // reserved controls that must be 1, the "default1" class
+    let named = names.iter().fold(0u64, |mask, &(bit, _)| mask | 1 << bit);
+    if must_be_1 & !named != 0 {
+        lines.push(format!(
+            "{:<8}{:<42}0x{:x}",
+            "",
+            "Reserved controls fixed to 1",
+            must_be_1 & !named
+        ));
+    }
+    Some(lines)
+}
@@ -0,0 +1,361 @@
+//! Page table browser and virtual address translation through the live
+//! page tables
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Widget};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::cpuid::CpuidState;
+use crate::memory::{self, LEVEL_NAMES, PageTableManager, WalkStep};
+#[cfg(feature = "msr")]
+use crate::msr::MemoryTypeMap;
+use crate::pane::{
+    PromptResult, Promptable, ScrollDirection, ScrollHints, Scrollable, parse_number,
+};
+
+const ENTRIES_PER_TABLE: usize = 512;
+
+/// Address bits of an entry
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+const PRESENT: u64 = 1 << 0;
+/// PS in PDPT and PD entries, PAT in PT entries
+const PAGE_SIZE: u64 = 1 << 7;
+const PAT_HUGE: u64 = 1 << 12;
+const PROTECTION_KEY_SHIFT: u64 = 59;
+
+/// Flags with the same meaning at every level
+const COMMON_FLAGS: &[(&str, u8)] = &[
+    ("P", 0),
+    ("RW", 1),
+    ("US", 2),
+    ("PWT", 3),
+    ("PCD", 4),
+    ("A", 5),
+];
+
+fn is_leaf(entry: u64, level: usize) -> bool {
+    level == 3 || (level > 0 && entry & PAGE_SIZE != 0)
+}
+
+/// Physical address an entry at `level` points to. Bit 12 of a huge page
+/// entry is its PAT bit, not part of the address.
+fn entry_address(entry: u64, level: usize) -> u64 {
+    if is_leaf(entry, level) {
+        entry & ADDRESS_MASK & !(memory::page_size(level) - 1)
+    } else {
+        entry & ADDRESS_MASK
+    }
+}
+
+/// Decoded flags of an entry at `level`
+fn flags_str(entry: u64, level: usize) -> String {
+    let mut flags: Vec<String> = COMMON_FLAGS
+        .iter()
+        .filter(|(_, bit)| entry & (1 << bit) != 0)
+        .map(|(name, _)| String::from(*name))
+        .collect();
+    let leaf = is_leaf(entry, level);
+    let mut push_if = |set: bool, name: &str| {
+        if set {
+            flags.push(String::from(name));
+        }
+    };
+    push_if(leaf && entry & (1 << 6) != 0, "D");
+    push_if(level > 0 && level < 3 && entry & PAGE_SIZE != 0, "PS");
+    push_if(leaf && entry & (1 << 8) != 0, "G");
+    let pat = if level == 3 { PAGE_SIZE } else { PAT_HUGE };
+    push_if(leaf && entry & pat != 0, "PAT");
+    push_if(entry & (1 << 63) != 0, "NX");
+
+    let key = (entry >> PROTECTION_KEY_SHIFT) & 0xF;
+    if leaf && key != 0 {
+        flags.push(format!("PK{}", key));
+    }
+    flags.join(" ")
+}
+
+/// What an entry maps: the next table or the size of the page
+fn target_str(entry: u64, level: usize) -> &'static str {
+    if !is_leaf(entry, level) {
+        return LEVEL_NAMES[level + 1];
+    }
+    match level {
+        1 => "1G page",
+        2 => "2M page",
+        _ => "4K page",
+    }
+}
+
+fn step_line(step: &WalkStep) -> Line<'static> {
+    Line::raw(format!(
+        "  {:<10}{:<16}0x{:016x}  {}",
+        format!("{}[{}]", LEVEL_NAMES[step.level], step.index),
+        format!("@0x{:x}", step.table.as_u64()),
+        step.entry,
+        flags_str(step.entry, step.level)
+    ))
+}
+
+/// A table on the path from the PML4 to the table shown
+struct OpenTable {
+    table: PhysAddr,
+    /// Virtual address of the first byte entry 0 maps
+    base: u64,
+    /// Index in the present entries of the selected one
+    selected: usize,
+}
+
+pub struct PagingPane {
+    path: Vec<OpenTable>,
+    /// Address of the last translation, walked again on every render
+    translation: Option<VirtAddr>,
+    /// Present entries of the table shown, as of the last render
+    present: usize,
+    scroll: ScrollHints,
+    #[cfg(feature = "msr")]
+    memory_types: MemoryTypeMap,
+}
+
+impl PagingPane {
+    pub fn new(cpuid_state: &CpuidState) -> Self {
+        #[cfg(not(feature = "msr"))]
+        let _ = cpuid_state;
+        Self {
+            path: Vec::new(),
+            translation: None,
+            present: 0,
+            scroll: ScrollHints::default(),
+            #[cfg(feature = "msr")]
+            memory_types: MemoryTypeMap::new(cpuid_state.cpu_features()),
+        }
+    }
+
+    /// Present entries of the table shown with their index
+    fn present_entries(tables: &PageTableManager, table: PhysAddr) -> Vec<(usize, u64)> {
+        tables
+            .table(table)
+            .iter()
+            .map(memory::raw_entry)
+            .enumerate()
+            .filter(|(_, entry)| entry & PRESENT != 0)
+            .collect()
+    }
+
+    /// Open the table the selected entry points to
+    pub fn descend(&mut self) {
+        let tables = memory::page_tables();
+        let Some(open) = self.path.last() else {
+            return;
+        };
+        let level = self.path.len() - 1;
+        let entries = Self::present_entries(&tables, open.table);
+        let Some(&(index, entry)) = entries.get(open.selected) else {
+            return;
+        };
+        if is_leaf(entry, level) {
+            return;
+        }
+
+        let base = open.base + index as u64 * memory::page_size(level);
+        self.path.push(OpenTable {
+            table: PhysAddr::new(entry & ADDRESS_MASK),
+            base,
+            selected: 0,
+        });
+        self.scroll.y_offset = 0;
+    }
+
+    /// Go back to the parent table
+    pub fn ascend(&mut self) {
+        if self.path.len() > 1 {
+            self.path.pop();
+            self.scroll.y_offset = 0;
+        }
+    }
+
+    /// Every level walked for the translated address and where it ended
+    fn translation_lines(&self, tables: &PageTableManager, addr: VirtAddr) -> Vec<Line<'static>> {
+        let mut lines = vec![Line::styled(
+            format!("Translation of 0x{:x}", addr.as_u64()),
+            Style::default().bold(),
+        )];
+        let (steps, leaf) = tables.walk(addr);
+        lines.extend(steps.iter().map(step_line));
+
+        let Some(leaf) = leaf else {
+            let last = steps.last().map_or("PML4", |s| LEVEL_NAMES[s.level]);
+            lines.push(Line::styled(
+                format!("  not mapped, {} entry not present", last),
+                Style::default().fg(Color::Red),
+            ));
+            return lines;
+        };
+
+        #[cfg(feature = "msr")]
+        let memory_type = self
+            .memory_types
+            .effective_type(leaf.phys.as_u64(), leaf.pat_index);
+        #[cfg(not(feature = "msr"))]
+        let memory_type = format!("PAT index {}", leaf.pat_index);
+        lines.push(Line::styled(
+            format!(
+                "  -> phys 0x{:x} in a {} KiB page",
+                leaf.phys.as_u64(),
+                leaf.size / 1024
+            ),
+            Style::default().fg(Color::Green),
+        ));
+        lines.push(Line::styled(
+            format!("  {}", memory_type),
+            Style::default().fg(Color::Green),
+        ));
+        lines
+    }
+}
+
+impl Scrollable for PagingPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+
+    /// Scrolling moves the selection, the view follows it on render
+    fn scroll(&mut self, direction: ScrollDirection) {
+        let last = self.present.saturating_sub(1);
+        let page = self.scroll.page_height as usize;
+        let Some(open) = self.path.last_mut() else {
+            return;
+        };
+        open.selected = match direction {
+            ScrollDirection::Up => open.selected.saturating_sub(1),
+            ScrollDirection::Down => (open.selected + 1).min(last),
+            ScrollDirection::Top => 0,
+            ScrollDirection::Bottom => last,
+            ScrollDirection::PageUp => open.selected.saturating_sub(page),
+            ScrollDirection::PageDown => (open.selected + page).min(last),
+        };
+    }
+}
+
+impl Promptable for PagingPane {
+    fn prompt_label(&self) -> String {
+        String::from("translate: ")
+    }
+
+    /// Translate a virtual address, an empty input hides the translation
+    fn submit_prompt(&mut self, input: &str) -> PromptResult {
+        let input = input.trim();
+        if input.is_empty() {
+            self.translation = None;
+            return Ok(String::from("translation cleared"));
+        }
+
+        let address = parse_number(input).ok_or_else(|| format!("invalid address: {}", input))?;
+        let addr = VirtAddr::try_new(address)
+            .map_err(|_| format!("non-canonical address: 0x{:x}", address))?;
+        self.translation = Some(addr);
+        self.scroll.y_offset = 0;
+
+        match memory::page_tables().walk(addr).1 {
+            Some(leaf) => Ok(format!(
+                "0x{:x} -> phys 0x{:x}",
+                address,
+                leaf.phys.as_u64()
+            )),
+            None => Err(format!("0x{:x} is not mapped", address)),
+        }
+    }
+}
+
+impl Widget for &mut PagingPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let tables = memory::page_tables();
+        if self.path.is_empty() {
+            self.path.push(OpenTable {
+                table: tables.root(),
+                base: 0,
+                selected: 0,
+            });
+        }
+
+        let mut lines = Vec::new();
+        if let Some(addr) = self.translation {
+            lines.extend(self.translation_lines(&tables, addr));
+            lines.push(Line::raw(""));
+        }
+
+        let level = self.path.len() - 1;
+        let trail: Vec<String> = self
+            .path
+            .iter()
+            .enumerate()
+            .map(|(level, open)| format!("{}@0x{:x}", LEVEL_NAMES[level], open.table.as_u64()))
+            .collect();
+        lines.push(Line::styled(trail.join(" > "), Style::default().bold()));
+        lines.push(Line::styled(
+            "Enter: open table, Backspace: parent, ':': translate",
+            Style::default().fg(Color::DarkGray),
+        ));
+        lines.push(Line::styled(
+            format!(
+                "{:<5}{:<19}{:<15}{:<9}{}",
+                "Idx", "Virtual", "Physical", "Maps", "Flags"
+            ),
+            Style::default().bold(),
+        ));
+
+        let open = self.path.last_mut().expect("path holds the PML4");
+        let entries = PagingPane::present_entries(&tables, open.table);
+        open.selected = open.selected.min(entries.len().saturating_sub(1));
+        let mut selected_line = 0;
+        for (i, &(index, entry)) in entries.iter().enumerate() {
+            let virt = VirtAddr::new_truncate(open.base + index as u64 * memory::page_size(level));
+            let line = Line::raw(format!(
+                "{:<5}{:<19}{:<15}{:<9}{}",
+                index,
+                format!("0x{:x}", virt.as_u64()),
+                format!("0x{:x}", entry_address(entry, level)),
+                target_str(entry, level),
+                flags_str(entry, level)
+            ));
+            if i == open.selected {
+                selected_line = lines.len() as u16;
+                lines.push(line.reversed());
+            } else {
+                lines.push(line);
+            }
+        }
+        lines.push(Line::styled(
+            format!(
+                "{} of {} entries not present",
+                ENTRIES_PER_TABLE - entries.len(),
+                ENTRIES_PER_TABLE
+            ),
+            Style::default().fg(Color::DarkGray),
+        ));
+        self.present = entries.len();
+
+        // Keep the selected entry in view
+        let height = area.height.max(1);
+        if selected_line >= self.scroll.y_offset + height {
+            self.scroll.y_offset = selected_line + 1 - height;
+        }
+        if selected_line < self.scroll.y_offset {
+            self.scroll.y_offset = selected_line;
+        }
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
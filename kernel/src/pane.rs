@@ -1,7 +1,7 @@
 //! Pane traits for scrolling and searching
 
 use alloc::format;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 
@@ -130,6 +130,89 @@ pub trait Searchable: Scrollable {
     }
 }
 
+/// Outcome of a prompt command: a status message or an error message
+pub type PromptResult = Result<String, String>;
+
+/// Trait for panes that accept commands typed into the `:` prompt
+pub trait Promptable {
+    /// Text shown in front of the prompt input
+    fn prompt_label(&self) -> String;
+
+    /// Execute the entered command
+    fn submit_prompt(&mut self, input: &str) -> PromptResult;
+
+    /// Whether the last command asks a follow-up question, which keeps the
+    /// prompt open for the answer
+    fn prompt_pending(&self) -> bool {
+        false
+    }
+
+    /// The prompt was left with ESC
+    fn cancel_prompt(&mut self) {}
+}
+
+/// Parse a number typed into a prompt: hex with `0x` prefix or decimal,
+/// underscores are ignored
+pub fn parse_number(input: &str) -> Option<u64> {
+    let digits: String = input.trim().chars().filter(|c| *c != '_').collect();
+    match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => digits.parse().ok(),
+    }
+}
+
+/// Byte count in the largest binary unit it reaches, with one decimal
+pub fn size_str(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let exp = ((63 - bytes.max(1).leading_zeros()) / 10).min(UNITS.len() as u32 - 1);
+    let unit = 1u64 << (exp * 10);
+    let tenths = (bytes % unit) * 10 / unit;
+    if tenths == 0 {
+        format!("{} {}", bytes / unit, UNITS[exp as usize])
+    } else {
+        format!("{}.{} {}", bytes / unit, tenths, UNITS[exp as usize])
+    }
+}
+
+/// Every flag in `bits`, `per_line` to a row padded to `width`, set ones in
+/// green
+pub fn flag_lines(
+    value: u64,
+    bits: &[(&str, u8)],
+    per_line: usize,
+    width: usize,
+) -> Vec<Line<'static>> {
+    bits.chunks(per_line)
+        .map(|chunk| {
+            let spans: Vec<Span> = chunk
+                .iter()
+                .map(|(name, bit)| {
+                    let set = value & (1 << bit) != 0;
+                    let color = if set { Color::Green } else { Color::DarkGray };
+                    Span::styled(
+                        format!("  {:<w$}", name, w = width - 2),
+                        Style::default().fg(color),
+                    )
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Bold heading of a group of fields
+pub fn section(title: &str) -> Line<'static> {
+    Line::styled(String::from(title), Style::default().bold())
+}
+
+/// Indented field name padded to `width`, then the value
+pub fn field_line(name: &str, value: String, width: usize) -> Line<'static> {
+    Line::raw(format!("  {:<w$}{}", name, value, w = width))
+}
+
 /// Create a line with optional search highlighting.
 /// `name` is the searchable text, `suffix` is appended after, `name_width` pads the name.
 pub fn highlight_line(
@@ -0,0 +1,352 @@
+//! Protection keys for user pages (PKU): PKRU readout, editing and self-test
+
+use alloc::alloc::{Layout, alloc_zeroed, dealloc};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+use x86_64::registers::xcontrol::{XCr0, XCr0Flags};
+use x86_64::structures::idt::{ExceptionVector, PageFaultErrorCode};
+use x86_64::structures::paging::Page;
+
+use crate::cpuid::{self, CpuFeatures};
+use crate::memory;
+use crate::pane::{PromptResult, Promptable, ScrollHints, Scrollable, parse_number};
+use crate::recovery::{self, Fault};
+
+/// Number of protection keys, each with an AD and WD bit in PKRU
+const NUM_KEYS: u8 = 16;
+
+/// Key the self-test tags its page with
+const TEST_KEY: u8 = 1;
+
+const PAGE_SIZE: usize = 4096;
+
+#[inline(always)]
+pub fn rdpkru() -> u32 {
+    let value: u32;
+    unsafe {
+        asm!(
+            "rdpkru",
+            in("ecx") 0,
+            out("eax") value,
+            out("edx") _,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    value
+}
+
+#[inline(always)]
+pub fn wrpkru(value: u32) {
+    unsafe {
+        asm!(
+            "wrpkru",
+            in("eax") value,
+            in("ecx") 0,
+            in("edx") 0,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Enable protection keys in CR4 and PKRU state management in XCR0.
+/// Returns false if PKU is not supported.
+pub fn enable(features: &CpuFeatures) -> bool {
+    if !features.has_pku() {
+        return false;
+    }
+
+    unsafe {
+        let mut cr4 = Cr4::read();
+        cr4.insert(Cr4Flags::PROTECTION_KEY_USER);
+        if features.xcr0_supports_pkru() {
+            cr4.insert(Cr4Flags::OSXSAVE);
+        }
+        Cr4::write(cr4);
+
+        if features.xcr0_supports_pkru() {
+            let mut xcr0 = XCr0::read();
+            xcr0.insert(XCr0Flags::MPK);
+            XCr0::write(xcr0);
+        }
+    }
+    true
+}
+
+/// Access disable and write disable bits of `key`
+fn key_bits(pkru: u32, key: u8) -> (bool, bool) {
+    let shift = key as u32 * 2;
+    ((pkru >> shift) & 1 == 1, (pkru >> (shift + 1)) & 1 == 1)
+}
+
+/// Replace the AD and WD bits of `key`
+fn with_key_bits(pkru: u32, key: u8, ad: bool, wd: bool) -> u32 {
+    let shift = key as u32 * 2;
+    let cleared = pkru & !(0b11 << shift);
+    cleared | ((ad as u32) << shift) | ((wd as u32) << (shift + 1))
+}
+
+fn access_str(ad: bool, wd: bool) -> &'static str {
+    match (ad, wd) {
+        (true, _) => "no access",
+        (false, true) => "read-only",
+        (false, false) => "read/write",
+    }
+}
+
+/// One PKRU setting of the self-test with the observed accesses
+struct SelfTestStep {
+    ad: bool,
+    wd: bool,
+    read: Result<(), Fault>,
+    write: Result<(), Fault>,
+    expect_read_fault: bool,
+    expect_write_fault: bool,
+}
+
+impl SelfTestStep {
+    fn passed(&self) -> bool {
+        outcome_matches(&self.read, self.expect_read_fault)
+            && outcome_matches(&self.write, self.expect_write_fault)
+    }
+}
+
+/// A fault matches if it is a #PF with the PK bit set in its error code
+fn outcome_matches(outcome: &Result<(), Fault>, expect_fault: bool) -> bool {
+    match outcome {
+        Ok(()) => !expect_fault,
+        Err(fault) => {
+            expect_fault
+                && fault.vector == ExceptionVector::Page as u8
+                && fault.error_code & PageFaultErrorCode::PROTECTION_KEY.bits() != 0
+        }
+    }
+}
+
+fn outcome_str(outcome: &Result<(), Fault>) -> String {
+    match outcome {
+        Ok(()) => "ok".into(),
+        Err(fault) if fault.vector == ExceptionVector::Page as u8 => {
+            let pk = fault.error_code & PageFaultErrorCode::PROTECTION_KEY.bits() != 0;
+            format!("#PF{}", if pk { " (PK)" } else { "" })
+        }
+        Err(fault) => format!("vector {}", fault.vector),
+    }
+}
+
+struct SelfTest {
+    page: VirtAddr,
+    write_protect: bool,
+    steps: Vec<SelfTestStep>,
+}
+
+/// Tag a heap page with `TEST_KEY` and verify that reads and writes fault
+/// according to the key's AD/WD bits. PKRU is restored afterwards.
+fn run_self_test() -> Result<SelfTest, &'static str> {
+    let layout = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap();
+    let ptr = unsafe { alloc_zeroed(layout) };
+    if ptr.is_null() {
+        return Err("heap page allocation failed");
+    }
+    let page_addr = VirtAddr::from_ptr(ptr);
+    let page = Page::containing_address(page_addr);
+
+    if memory::page_tables()
+        .set_protection_key(page, Some(TEST_KEY))
+        .is_err()
+    {
+        unsafe { dealloc(ptr, layout) };
+        return Err("tagging the heap page failed");
+    }
+
+    // supervisor writes only honor WD if CR0.WP is set
+    let write_protect = Cr0::read().contains(Cr0Flags::WRITE_PROTECT);
+    let saved = rdpkru();
+
+    let steps = without_interrupts(|| {
+        [(false, false), (false, true), (true, false)]
+            .into_iter()
+            .map(|(ad, wd)| {
+                wrpkru(with_key_bits(saved, TEST_KEY, ad, wd));
+                let read = recovery::read_u8(ptr).map(|_| ());
+                let write = recovery::write_u8(ptr, 0xA5);
+                wrpkru(saved);
+
+                SelfTestStep {
+                    ad,
+                    wd,
+                    read,
+                    write,
+                    expect_read_fault: ad,
+                    expect_write_fault: ad || (wd && write_protect),
+                }
+            })
+            .collect()
+    });
+
+    let untagged = memory::page_tables().set_protection_key(page, None);
+    if untagged.is_ok() {
+        unsafe { dealloc(ptr, layout) };
+    }
+
+    Ok(SelfTest {
+        page: page_addr,
+        write_protect,
+        steps,
+    })
+}
+
+/// Outcome of `init`, shown by the PKRU pane
+pub struct Protection {
+    supported: bool,
+    self_test: Option<Result<SelfTest, &'static str>>,
+}
+
+/// Enable protection keys and run the self-test, which tags a heap page
+/// with a key. Needs the heap and the exception handlers.
+pub fn init(features: &CpuFeatures) -> Protection {
+    let supported = enable(features);
+    Protection {
+        supported,
+        self_test: supported.then(run_self_test),
+    }
+}
+
+pub struct PkruPane {
+    supported: bool,
+    self_test: Option<Result<SelfTest, &'static str>>,
+    scroll: ScrollHints,
+}
+
+impl PkruPane {
+    pub fn new(protection: Protection) -> Self {
+        Self {
+            supported: protection.supported,
+            self_test: protection.self_test,
+            scroll: ScrollHints::default(),
+        }
+    }
+}
+
+impl Scrollable for PkruPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Promptable for PkruPane {
+    fn prompt_label(&self) -> String {
+        "wrpkru ".into()
+    }
+
+    fn submit_prompt(&mut self, input: &str) -> PromptResult {
+        if !self.supported {
+            return Err("PKU not supported".into());
+        }
+        let value = parse_number(input)
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("invalid PKRU value: {}", input))?;
+
+        wrpkru(value);
+        Ok(format!("PKRU = 0x{:08x}", rdpkru()))
+    }
+}
+
+impl Widget for &mut PkruPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let yes_no = |b: bool| if b { "Yes" } else { "No" };
+        let mut lines = vec![Line::styled("Protection Keys", Style::default().bold())];
+
+        lines.push(Line::raw(format!(
+            "{:<18}{}",
+            "PKU supported:",
+            yes_no(self.supported)
+        )));
+        if !self.supported {
+            let paragraph = Paragraph::new(lines);
+            paragraph.render(area, buf);
+            return;
+        }
+
+        let cr4 = Cr4::read();
+        let pke = cr4.contains(Cr4Flags::PROTECTION_KEY_USER);
+        lines.push(Line::raw(format!("{:<18}{}", "CR4.PKE:", pke as u8)));
+        let ospke = cpuid::has_ospke();
+        lines.push(Line::raw(format!("{:<18}{}", "OSPKE:", yes_no(ospke))));
+
+        let pkru = rdpkru();
+        lines.push(Line::raw(format!("{:<18}0x{:08x}", "PKRU:", pkru)));
+        lines.push(Line::raw(""));
+
+        lines.push(Line::styled(
+            format!("{:<5}{:<4}{:<4}{}", "Key", "AD", "WD", "Access"),
+            Style::default().bold(),
+        ));
+        for key in 0..NUM_KEYS {
+            let (ad, wd) = key_bits(pkru, key);
+            lines.push(Line::raw(format!(
+                "{:<5}{:<4}{:<4}{}",
+                key,
+                ad as u8,
+                wd as u8,
+                access_str(ad, wd)
+            )));
+        }
+        lines.push(Line::raw(""));
+
+        match &self.self_test {
+            Some(Ok(test)) => {
+                lines.push(Line::styled(
+                    format!("Self-Test (key {} on page {:#x})", TEST_KEY, test.page),
+                    Style::default().bold(),
+                ));
+                lines.push(Line::raw(format!(
+                    "{:<18}{}",
+                    "CR0.WP:", test.write_protect as u8
+                )));
+                lines.push(Line::raw(format!(
+                    "{:<4}{:<4}{:<12}{:<12}{}",
+                    "AD", "WD", "Read", "Write", "Result"
+                )));
+                for step in &test.steps {
+                    let (verdict, color) = if step.passed() {
+                        ("PASS", Color::Green)
+                    } else {
+                        ("FAIL", Color::Red)
+                    };
+                    lines.push(Line::from(vec![
+                        Span::raw(format!(
+                            "{:<4}{:<4}{:<12}{:<12}",
+                            step.ad as u8,
+                            step.wd as u8,
+                            outcome_str(&step.read),
+                            outcome_str(&step.write)
+                        )),
+                        Span::styled(verdict, Style::default().fg(color)),
+                    ]));
+                }
+            }
+            Some(Err(err)) => {
+                lines.push(Line::raw(format!("Self-test failed: {}", err)));
+            }
+            None => {}
+        }
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
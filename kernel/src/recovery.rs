@@ -0,0 +1,216 @@
+//! Recovery from faults raised by instructions that are expected to fault
+//!
+//! A guarded access stores the address of its instruction and of its resume
+//! point before running the instruction. If an exception handler finds a
+//! resume point armed and the fault was raised by that instruction, it
+//! records the fault and continues execution there instead of treating it as
+//! fatal. Faults elsewhere, like in an interrupt handler that ran while the
+//! access was armed, are not redirected.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use x86_64::VirtAddr;
+use x86_64::structures::idt::InterruptStackFrame;
+
+/// Address execution resumes at if the guarded instruction faults, 0 if unarmed
+static RESUME_RIP: AtomicU64 = AtomicU64::new(0);
+/// Address of the guarded instruction
+static GUARDED_RIP: AtomicU64 = AtomicU64::new(0);
+/// Vector of the last recovered fault, NO_FAULT if none
+static FAULT_VECTOR: AtomicU16 = AtomicU16::new(NO_FAULT);
+static FAULT_ERROR_CODE: AtomicU64 = AtomicU64::new(0);
+
+const NO_FAULT: u16 = u16::MAX;
+
+/// Exception raised by a guarded instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub vector: u8,
+    pub error_code: u64,
+}
+
+/// Called by exception handlers. If the fault was raised by a guarded
+/// instruction in flight, the fault is recorded, the frame is redirected to
+/// the resume point and true is returned.
+pub fn recover(frame: &mut InterruptStackFrame, vector: u8, error_code: u64) -> bool {
+    let rip = RESUME_RIP.load(Ordering::Acquire);
+    let guarded = GUARDED_RIP.load(Ordering::Relaxed);
+    if rip == 0 || frame.instruction_pointer.as_u64() != guarded {
+        return false;
+    }
+    RESUME_RIP.store(0, Ordering::Relaxed);
+
+    FAULT_ERROR_CODE.store(error_code, Ordering::Relaxed);
+    FAULT_VECTOR.store(vector as u16, Ordering::Release);
+    unsafe {
+        frame
+            .as_mut()
+            .update(|f| f.instruction_pointer = VirtAddr::new(rip));
+    }
+    true
+}
+
+fn arm() {
+    FAULT_VECTOR.store(NO_FAULT, Ordering::Relaxed);
+}
+
+fn take_fault() -> Result<(), Fault> {
+    match FAULT_VECTOR.swap(NO_FAULT, Ordering::Acquire) {
+        NO_FAULT => Ok(()),
+        vector => Err(Fault {
+            vector: vector as u8,
+            error_code: FAULT_ERROR_CODE.load(Ordering::Relaxed),
+        }),
+    }
+}
+
+/// Read a byte, returning the fault instead of crashing if the access faults
+pub fn read_u8(ptr: *const u8) -> Result<u8, Fault> {
+    let value: u8;
+    arm();
+    unsafe {
+        asm!(
+            "lea {tmp}, [rip + 3f]",
+            "mov qword ptr [{guarded}], {tmp}",
+            "lea {tmp}, [rip + 2f]",
+            "mov qword ptr [{resume}], {tmp}",
+            "3:",
+            "mov {value}, byte ptr [{ptr}]",
+            "2:",
+            "mov qword ptr [{resume}], 0",
+            resume = in(reg) RESUME_RIP.as_ptr(),
+            guarded = in(reg) GUARDED_RIP.as_ptr(),
+            ptr = in(reg) ptr,
+            tmp = out(reg) _,
+            value = out(reg_byte) value,
+            options(nostack, preserves_flags),
+        );
+    }
+    take_fault().map(|_| value)
+}
+
+/// Guarded load of a wider integer, MMIO registers like the IOAPIC window
+/// only accept accesses of their own size
+macro_rules! guarded_read {
+    ($(#[$doc:meta])* $name:ident, $ty:ty, $load:literal) => {
+        $(#[$doc])*
+        pub fn $name(ptr: *const $ty) -> Result<$ty, Fault> {
+            let value: u64;
+            arm();
+            unsafe {
+                asm!(
+                    "xor {value:e}, {value:e}",
+                    "lea {tmp}, [rip + 3f]",
+                    "mov qword ptr [{guarded}], {tmp}",
+                    "lea {tmp}, [rip + 2f]",
+                    "mov qword ptr [{resume}], {tmp}",
+                    "3:",
+                    $load,
+                    "2:",
+                    "mov qword ptr [{resume}], 0",
+                    resume = in(reg) RESUME_RIP.as_ptr(),
+                    guarded = in(reg) GUARDED_RIP.as_ptr(),
+                    ptr = in(reg) ptr,
+                    tmp = out(reg) _,
+                    value = out(reg) value,
+                    options(nostack),
+                );
+            }
+            take_fault().map(|_| value as $ty)
+        }
+    };
+}
+
+guarded_read!(
+    /// Read a word, returning the fault instead of crashing if the access faults
+    read_u16,
+    u16,
+    "mov {value:x}, word ptr [{ptr}]"
+);
+guarded_read!(
+    /// Read a dword, returning the fault instead of crashing if the access faults
+    read_u32,
+    u32,
+    "mov {value:e}, dword ptr [{ptr}]"
+);
+guarded_read!(
+    /// Read a qword, returning the fault instead of crashing if the access faults
+    read_u64,
+    u64,
+    "mov {value}, qword ptr [{ptr}]"
+);
+
+/// Write a byte, returning the fault instead of crashing if the access faults
+pub fn write_u8(ptr: *mut u8, value: u8) -> Result<(), Fault> {
+    arm();
+    unsafe {
+        asm!(
+            "lea {tmp}, [rip + 3f]",
+            "mov qword ptr [{guarded}], {tmp}",
+            "lea {tmp}, [rip + 2f]",
+            "mov qword ptr [{resume}], {tmp}",
+            "3:",
+            "mov byte ptr [{ptr}], {value}",
+            "2:",
+            "mov qword ptr [{resume}], 0",
+            resume = in(reg) RESUME_RIP.as_ptr(),
+            guarded = in(reg) GUARDED_RIP.as_ptr(),
+            ptr = in(reg) ptr,
+            value = in(reg_byte) value,
+            tmp = out(reg) _,
+            options(nostack, preserves_flags),
+        );
+    }
+    take_fault()
+}
+
+/// Read an MSR, returning the #GP instead of crashing if it is not implemented
+pub fn rdmsr(address: u32) -> Result<u64, Fault> {
+    let (low, high): (u32, u32);
+    arm();
+    unsafe {
+        asm!(
+            "lea {tmp}, [rip + 3f]",
+            "mov qword ptr [{guarded}], {tmp}",
+            "lea {tmp}, [rip + 2f]",
+            "mov qword ptr [{resume}], {tmp}",
+            "3:",
+            "rdmsr",
+            "2:",
+            "mov qword ptr [{resume}], 0",
+            resume = in(reg) RESUME_RIP.as_ptr(),
+            guarded = in(reg) GUARDED_RIP.as_ptr(),
+            tmp = out(reg) _,
+            in("ecx") address,
+            out("eax") low,
+            out("edx") high,
+            options(nostack, preserves_flags),
+        );
+    }
+    take_fault().map(|_| ((high as u64) << 32) | low as u64)
+}
+
+/// Write an MSR, returning the #GP instead of crashing if the write is refused
+pub fn wrmsr(address: u32, value: u64) -> Result<(), Fault> {
+    arm();
+    unsafe {
+        asm!(
+            "lea {tmp}, [rip + 3f]",
+            "mov qword ptr [{guarded}], {tmp}",
+            "lea {tmp}, [rip + 2f]",
+            "mov qword ptr [{resume}], {tmp}",
+            "3:",
+            "wrmsr",
+            "2:",
+            "mov qword ptr [{resume}], 0",
+            resume = in(reg) RESUME_RIP.as_ptr(),
+            guarded = in(reg) GUARDED_RIP.as_ptr(),
+            tmp = out(reg) _,
+            in("ecx") address,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags),
+        );
+    }
+    take_fault()
+}
@@ -0,0 +1,36 @@
+//! Function symbols of the kernel itself
+//!
+//! The section below is reserved at link time and filled by the image build
+//! script from the linked kernel's symbol table, see `image/build.rs`.
+//! Addresses in it are link time addresses, the offset the bootloader
+//! loaded the kernel at is recovered from where the table ended up.
+
+use symtab::SymbolTable;
+
+/// Bytes reserved for the table, the image build fails if it does not fit
+const CAPACITY: usize = 256 * 1024;
+
+#[repr(C, align(8))]
+struct Reserved([u8; CAPACITY]);
+
+/// All zero as compiled, the image build script overwrites the contents in
+/// the linked kernel. Mutable so the compiler cannot assume the initializer
+/// is what a read returns, it is only ever read through a raw pointer.
+#[used]
+#[unsafe(link_section = ".ksymtab")]
+static mut TABLE: Reserved = Reserved([0; CAPACITY]);
+
+/// The embedded table, None in a kernel that was not built into an image
+fn table() -> Option<SymbolTable<'static>> {
+    // nothing writes the table at runtime
+    let data = unsafe { core::slice::from_raw_parts((&raw const TABLE).cast::<u8>(), CAPACITY) };
+    SymbolTable::parse(data)
+}
+
+/// Function containing the runtime address `address`, with the offset into
+/// it
+pub fn lookup(address: u64) -> Option<(&'static str, u64)> {
+    let table = table()?;
+    let load_offset = (&raw const TABLE as u64).wrapping_sub(table.base());
+    table.lookup(address.wrapping_sub(load_offset))
+}
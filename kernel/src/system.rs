@@ -0,0 +1,245 @@
+//! Control registers, XCR0, RFLAGS and EFER, decoded and read on every render
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Widget};
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4, Cr4Flags, Cr8};
+use x86_64::registers::model_specific::Efer;
+use x86_64::registers::rflags;
+use x86_64::registers::xcontrol::XCr0;
+
+use crate::pane::{self, ScrollHints, Scrollable};
+
+const FLAGS_PER_LINE: usize = 6;
+const FLAG_WIDTH: usize = 13;
+const FIELD_WIDTH: usize = 18;
+
+const CR0_BITS: &[(&str, u8)] = &[
+    ("PE", 0),
+    ("MP", 1),
+    ("EM", 2),
+    ("TS", 3),
+    ("ET", 4),
+    ("NE", 5),
+    ("WP", 16),
+    ("AM", 18),
+    ("NW", 29),
+    ("CD", 30),
+    ("PG", 31),
+];
+
+const CR4_BITS: &[(&str, u8)] = &[
+    ("VME", 0),
+    ("PVI", 1),
+    ("TSD", 2),
+    ("DE", 3),
+    ("PSE", 4),
+    ("PAE", 5),
+    ("MCE", 6),
+    ("PGE", 7),
+    ("PCE", 8),
+    ("OSFXSR", 9),
+    ("OSXMMEXCPT", 10),
+    ("UMIP", 11),
+    ("LA57", 12),
+    ("VMXE", 13),
+    ("SMXE", 14),
+    ("FSGSBASE", 16),
+    ("PCIDE", 17),
+    ("OSXSAVE", 18),
+    ("KL", 19),
+    ("SMEP", 20),
+    ("SMAP", 21),
+    ("PKE", 22),
+    ("CET", 23),
+    ("PKS", 24),
+    ("UINTR", 25),
+    ("LASS", 27),
+    ("LAM_SUP", 28),
+    ("FRED", 32),
+];
+
+const XCR0_BITS: &[(&str, u8)] = &[
+    ("X87", 0),
+    ("SSE", 1),
+    ("AVX", 2),
+    ("BNDREGS", 3),
+    ("BNDCSR", 4),
+    ("OPMASK", 5),
+    ("ZMM_HI256", 6),
+    ("HI16_ZMM", 7),
+    ("PKRU", 9),
+    ("TILECFG", 17),
+    ("TILEDATA", 18),
+    ("APX", 19),
+];
+
+const RFLAGS_BITS: &[(&str, u8)] = &[
+    ("CF", 0),
+    ("PF", 2),
+    ("AF", 4),
+    ("ZF", 6),
+    ("SF", 7),
+    ("TF", 8),
+    ("IF", 9),
+    ("DF", 10),
+    ("OF", 11),
+    ("NT", 14),
+    ("RF", 16),
+    ("VM", 17),
+    ("AC", 18),
+    ("VIF", 19),
+    ("VIP", 20),
+    ("ID", 21),
+];
+
+const EFER_BITS: &[(&str, u8)] = &[
+    ("SCE", 0),
+    ("LME", 8),
+    ("LMA", 10),
+    ("NXE", 11),
+    ("SVME", 12),
+    ("LMSLE", 13),
+    ("FFXSR", 14),
+    ("TCE", 15),
+    ("MCOMMIT", 17),
+    ("INTWB", 18),
+    ("UAIE", 20),
+    ("AIBRSE", 21),
+];
+
+/// Register value followed by every flag, set ones in green
+fn flag_lines(title: &str, value: u64, bits: &[(&str, u8)]) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::styled(
+        format!("{:<8}0x{:016x}", title, value),
+        Style::default().bold(),
+    )];
+    lines.extend(pane::flag_lines(value, bits, FLAGS_PER_LINE, FLAG_WIDTH));
+    lines
+}
+
+pub struct SystemPane {
+    scroll: ScrollHints,
+}
+
+impl SystemPane {
+    pub fn new() -> Self {
+        Self {
+            scroll: ScrollHints::default(),
+        }
+    }
+}
+
+impl Scrollable for SystemPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut SystemPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = Vec::new();
+
+        lines.extend(flag_lines("CR0", Cr0::read_raw(), CR0_BITS));
+        lines.push(Line::raw(""));
+
+        lines.push(Line::styled(
+            format!("{:<8}0x{:016x}", "CR2", Cr2::read_raw()),
+            Style::default().bold(),
+        ));
+        lines.push(pane::field_line(
+            "Last #PF address:",
+            format!("0x{:x}", Cr2::read_raw()),
+            FIELD_WIDTH,
+        ));
+        lines.push(Line::raw(""));
+
+        let cr4 = Cr4::read_raw();
+        let (frame, low) = Cr3::read_raw();
+        let base = frame.start_address().as_u64();
+        lines.push(Line::styled(
+            format!("{:<8}0x{:016x}", "CR3", base | low as u64),
+            Style::default().bold(),
+        ));
+        let level = if cr4 & Cr4Flags::L5_PAGING.bits() != 0 {
+            "PML5"
+        } else {
+            "PML4"
+        };
+        lines.push(pane::field_line(
+            &format!("{} base:", level),
+            format!("0x{:x}", base),
+            FIELD_WIDTH,
+        ));
+        // with CR4.PCIDE, bits 11:0 hold the PCID instead of PWT/PCD
+        if cr4 & Cr4Flags::PCID.bits() != 0 {
+            lines.push(pane::field_line(
+                "PCID:",
+                format!("{}", low & 0xFFF),
+                FIELD_WIDTH,
+            ));
+        } else {
+            lines.push(pane::field_line(
+                "PWT:",
+                format!("{}", (low >> 3) & 1),
+                FIELD_WIDTH,
+            ));
+            lines.push(pane::field_line(
+                "PCD:",
+                format!("{}", (low >> 4) & 1),
+                FIELD_WIDTH,
+            ));
+        }
+        lines.push(Line::raw(""));
+
+        lines.extend(flag_lines("CR4", cr4, CR4_BITS));
+        lines.push(Line::raw(""));
+
+        let cr8 = Cr8::read_raw();
+        lines.push(Line::styled(
+            format!("{:<8}0x{:016x}", "CR8", cr8),
+            Style::default().bold(),
+        ));
+        // interrupts are held unless their priority class, vector[7:4], is above
+        lines.push(pane::field_line(
+            "TPR:",
+            format!("{}", cr8 & 0xF),
+            FIELD_WIDTH,
+        ));
+        lines.push(Line::raw(""));
+
+        // XGETBV raises #UD unless CR4.OSXSAVE is set
+        if cr4 & Cr4Flags::OSXSAVE.bits() != 0 {
+            lines.extend(flag_lines("XCR0", XCr0::read_raw(), XCR0_BITS));
+        } else {
+            lines.push(Line::styled(
+                format!("{:<8}{}", "XCR0", "not accessible, CR4.OSXSAVE is clear"),
+                Style::default().bold(),
+            ));
+        }
+        lines.push(Line::raw(""));
+
+        let rflags = rflags::read_raw();
+        lines.extend(flag_lines("RFLAGS", rflags, RFLAGS_BITS));
+        lines.push(pane::field_line(
+            "IOPL:",
+            format!("{}", (rflags >> 12) & 0b11),
+            FIELD_WIDTH,
+        ));
+        lines.push(Line::raw(""));
+
+        lines.extend(flag_lines("EFER", Efer::read_raw(), EFER_BITS));
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
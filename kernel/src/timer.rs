@@ -11,7 +11,10 @@ use ratatui::widgets::{Paragraph, Widget};
 
 use crate::cpuid;
 use crate::interrupts::tick_count;
-use crate::lapic::{TARGET_TIMER_HZ, lapic_timer_freq_hz, read_lapic_timer_regs};
+use crate::lapic::{
+    self, REG_LVT_TIMER, REG_TIMER_CURRENT, REG_TIMER_DIVIDE, REG_TIMER_INITIAL, TARGET_TIMER_HZ,
+    lapic_timer_freq_hz, read_lapic_timer_regs,
+};
 
 pub struct TimerState {
     tick_count: usize,
@@ -96,26 +99,38 @@ impl Widget for &TimerState {
             "LAPIC Timer Registers",
             Style::default().bold(),
         ));
+        let mode = lapic::mode();
+        lines.push(Line::raw(format!("{:<18}{}", "LAPIC Mode:", mode.name())));
         lines.push(Line::raw(format!(
             "{:<18}{:<14}{}",
-            "Register", "MSR", "Value"
+            "Register", "Location", "Value"
         )));
         let r = read_lapic_timer_regs();
         lines.push(Line::raw(format!(
             "{:<18}{:<14}0x{:08X}",
-            "LVT Timer", "0x832", r.lvt_timer
+            "LVT Timer",
+            mode.register_location(REG_LVT_TIMER),
+            r.lvt_timer
         )));
         lines.push(Line::raw(format!(
             "{:<18}{:<14}0x{:08X} ({})",
-            "Initial Count", "0x838", r.initial_count, r.initial_count
+            "Initial Count",
+            mode.register_location(REG_TIMER_INITIAL),
+            r.initial_count,
+            r.initial_count
         )));
         lines.push(Line::raw(format!(
             "{:<18}{:<14}0x{:08X} ({})",
-            "Current Count", "0x839", r.current_count, r.current_count
+            "Current Count",
+            mode.register_location(REG_TIMER_CURRENT),
+            r.current_count,
+            r.current_count
         )));
         lines.push(Line::raw(format!(
             "{:<18}{:<14}0x{:08X}",
-            "Divide Config", "0x83E", r.divide_config
+            "Divide Config",
+            mode.register_location(REG_TIMER_DIVIDE),
+            r.divide_config
         )));
         lines.push(Line::raw(""));
 
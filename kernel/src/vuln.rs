@@ -0,0 +1,772 @@
+//! Speculative execution vulnerabilities, modeled after Linux's
+//! `/sys/devices/system/cpu/vulnerabilities`
+//!
+//! Verdicts are derived from enumeration only: CPUID bits, AMD leaf
+//! 0x8000_0008 EBX and the IA32_ARCH_CAPABILITIES/IA32_SPEC_CTRL MSRs. Linux
+//! additionally consults per-model tables, so where the answer depends on the
+//! model alone the verdict is "Unknown" instead of a guess.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Widget};
+use raw_cpuid::{CpuIdReader, CpuIdReaderNative};
+use x86_64::structures::idt::ExceptionVector;
+
+use crate::cpuid::CpuidState;
+use crate::pane::{self, ScrollHints, Scrollable};
+use crate::recovery::{self, Fault};
+
+const MSR_IA32_SPEC_CTRL: u32 = 0x48;
+const MSR_IA32_ARCH_CAPABILITIES: u32 = 0x10A;
+const MSR_IA32_MCU_OPT_CTRL: u32 = 0x123;
+const MSR_EFER: u32 = 0xC000_0080;
+
+// CPUID.1
+const LEAF1_ECX_AVX: u32 = 1 << 28;
+const LEAF1_ECX_RDRAND: u32 = 1 << 30;
+const LEAF1_ECX_HYPERVISOR: u32 = 1 << 31;
+
+// CPUID.7.0
+const LEAF7_EBX_RTM: u32 = 1 << 11;
+const LEAF7_EDX_SRBDS_CTRL: u32 = 1 << 9;
+const LEAF7_EDX_MD_CLEAR: u32 = 1 << 10;
+const LEAF7_EDX_IBRS_IBPB: u32 = 1 << 26;
+const LEAF7_EDX_STIBP: u32 = 1 << 27;
+const LEAF7_EDX_L1D_FLUSH: u32 = 1 << 28;
+const LEAF7_EDX_ARCH_CAPABILITIES: u32 = 1 << 29;
+const LEAF7_EDX_SSBD: u32 = 1 << 31;
+
+// CPUID.7.2
+const LEAF7_2_EDX_BHI_CTRL: u32 = 1 << 4;
+
+// CPUID.8000_0008
+const AMD_EBX_IBPB: u32 = 1 << 12;
+const AMD_EBX_IBRS: u32 = 1 << 14;
+const AMD_EBX_STIBP: u32 = 1 << 15;
+const AMD_EBX_IBRS_ALWAYS_ON: u32 = 1 << 16;
+const AMD_EBX_SSBD: u32 = 1 << 24;
+const AMD_EBX_VIRT_SSBD: u32 = 1 << 25;
+const AMD_EBX_SSB_NO: u32 = 1 << 26;
+const AMD_EBX_BTC_NO: u32 = 1 << 29;
+const AMD_EBX_IBPB_RET: u32 = 1 << 30;
+
+// CPUID.8000_0021
+const AMD_21_EAX_AUTOIBRS: u32 = 1 << 8;
+const AMD_21_EAX_SBPB: u32 = 1 << 27;
+const AMD_21_EAX_IBPB_BRTYPE: u32 = 1 << 28;
+const AMD_21_EAX_SRSO_NO: u32 = 1 << 29;
+
+// IA32_ARCH_CAPABILITIES
+const ARCH_CAP_RDCL_NO: u64 = 1 << 0;
+const ARCH_CAP_IBRS_ALL: u64 = 1 << 1;
+const ARCH_CAP_RSBA: u64 = 1 << 2;
+const ARCH_CAP_SSB_NO: u64 = 1 << 4;
+const ARCH_CAP_MDS_NO: u64 = 1 << 5;
+const ARCH_CAP_TSX_CTRL: u64 = 1 << 7;
+const ARCH_CAP_TAA_NO: u64 = 1 << 8;
+const ARCH_CAP_SBDR_SSDP_NO: u64 = 1 << 13;
+const ARCH_CAP_FBSDP_NO: u64 = 1 << 14;
+const ARCH_CAP_PSDP_NO: u64 = 1 << 15;
+const ARCH_CAP_FB_CLEAR: u64 = 1 << 17;
+const ARCH_CAP_BHI_NO: u64 = 1 << 20;
+const ARCH_CAP_GDS_CTRL: u64 = 1 << 25;
+const ARCH_CAP_GDS_NO: u64 = 1 << 26;
+const ARCH_CAP_RFDS_NO: u64 = 1 << 27;
+const ARCH_CAP_RFDS_CLEAR: u64 = 1 << 28;
+
+// IA32_SPEC_CTRL
+const SPEC_CTRL_IBRS: u64 = 1 << 0;
+const SPEC_CTRL_SSBD: u64 = 1 << 2;
+const SPEC_CTRL_BHI_DIS_S: u64 = 1 << 10;
+
+// IA32_MCU_OPT_CTRL
+const MCU_OPT_CTRL_RNGDS_MITG_DIS: u64 = 1 << 0;
+const MCU_OPT_CTRL_GDS_MITG_DIS: u64 = 1 << 4;
+
+// EFER
+const EFER_AIBRSE: u64 = 1 << 21;
+
+const LEAF7_EBX_BITS: &[(&str, u8)] = &[("RTM", 11)];
+
+const LEAF7_EDX_BITS: &[(&str, u8)] = &[
+    ("SRBDS_CTRL", 9),
+    ("MD_CLEAR", 10),
+    ("RTM_ALWAYS_ABORT", 11),
+    ("TSX_FORCE_ABORT", 13),
+    ("IBRS_IBPB", 26),
+    ("STIBP", 27),
+    ("L1D_FLUSH", 28),
+    ("ARCH_CAPABILITIES", 29),
+    ("CORE_CAPABILITIES", 30),
+    ("SSBD", 31),
+];
+
+const LEAF7_2_EDX_BITS: &[(&str, u8)] = &[
+    ("PSFD", 0),
+    ("IPRED_CTRL", 1),
+    ("RRSBA_CTRL", 2),
+    ("DDPD_U", 3),
+    ("BHI_CTRL", 4),
+    ("MCDT_NO", 5),
+];
+
+const AMD_EBX_BITS: &[(&str, u8)] = &[
+    ("IBPB", 12),
+    ("IBRS", 14),
+    ("STIBP", 15),
+    ("IBRS_ALWAYS_ON", 16),
+    ("STIBP_ALWAYS_ON", 17),
+    ("IBRS_PREFERRED", 18),
+    ("IBRS_SAME_MODE", 19),
+    ("SSBD", 24),
+    ("VIRT_SSBD", 25),
+    ("SSB_NO", 26),
+    ("PSFD", 28),
+    ("BTC_NO", 29),
+    ("IBPB_RET", 30),
+];
+
+const AMD_21_EAX_BITS: &[(&str, u8)] = &[
+    ("AUTOIBRS", 8),
+    ("SBPB", 27),
+    ("IBPB_BRTYPE", 28),
+    ("SRSO_NO", 29),
+];
+
+const ARCH_CAP_BITS: &[(&str, u8)] = &[
+    ("RDCL_NO", 0),
+    ("IBRS_ALL", 1),
+    ("RSBA", 2),
+    ("SKIP_L1DFL_VMENTRY", 3),
+    ("SSB_NO", 4),
+    ("MDS_NO", 5),
+    ("PSCHANGE_MC_NO", 6),
+    ("TSX_CTRL", 7),
+    ("TAA_NO", 8),
+    ("MCU_CONTROL", 9),
+    ("MISC_PACKAGE_CTLS", 10),
+    ("ENERGY_FILTERING_CTL", 11),
+    ("DOITM", 12),
+    ("SBDR_SSDP_NO", 13),
+    ("FBSDP_NO", 14),
+    ("PSDP_NO", 15),
+    ("FB_CLEAR", 17),
+    ("FB_CLEAR_CTRL", 18),
+    ("RRSBA", 19),
+    ("BHI_NO", 20),
+    ("XAPIC_DISABLE", 21),
+    ("PBRSB_NO", 24),
+    ("GDS_CTRL", 25),
+    ("GDS_NO", 26),
+    ("RFDS_NO", 27),
+    ("RFDS_CLEAR", 28),
+];
+
+const SPEC_CTRL_BITS: &[(&str, u8)] = &[
+    ("IBRS", 0),
+    ("STIBP", 1),
+    ("SSBD", 2),
+    ("IPRED_DIS_U", 3),
+    ("IPRED_DIS_S", 4),
+    ("RRSBA_DIS_U", 5),
+    ("RRSBA_DIS_S", 6),
+    ("PSFD", 7),
+    ("DDPD_U", 8),
+    ("BHI_DIS_S", 10),
+];
+
+const MCU_OPT_CTRL_BITS: &[(&str, u8)] = &[
+    ("RNGDS_MITG_DIS", 0),
+    ("RTM_ALLOW", 1),
+    ("RTM_LOCKED", 2),
+    ("FB_CLEAR_DIS", 3),
+    ("GDS_MITG_DIS", 4),
+    ("GDS_MITG_LOCK", 5),
+];
+
+/// Enumerated flags per line in the enumeration section
+const FLAGS_PER_LINE: usize = 3;
+const FLAG_WIDTH: usize = 26;
+
+/// Raw registers of a leaf, the verdicts test bits raw-cpuid has no
+/// accessors for
+fn cpuid(leaf: u32, subleaf: u32) -> [u32; 4] {
+    let result = CpuIdReaderNative.cpuid2(leaf, subleaf);
+    [result.eax, result.ebx, result.ecx, result.edx]
+}
+
+fn fault_str(fault: &Fault) -> String {
+    if fault.vector == ExceptionVector::GeneralProtection as u8 {
+        "#GP".into()
+    } else {
+        format!("vector {}", fault.vector)
+    }
+}
+
+/// Everything the verdicts are based on, read fresh on every render so
+/// writes from the MSR pane show up
+struct Enumeration {
+    intel: bool,
+    amd: bool,
+    family: u32,
+    leaf1_ecx: u32,
+    leaf7_ebx: u32,
+    leaf7_edx: u32,
+    leaf7_2_edx: u32,
+    amd_ebx: u32,
+    amd_21_eax: u32,
+    /// Vendor signature from leaf 0x4000_0000, if the hypervisor bit is set
+    hypervisor: Option<String>,
+    /// None if CPUID does not enumerate the MSR
+    arch_capabilities: Option<Result<u64, Fault>>,
+    spec_ctrl: Option<Result<u64, Fault>>,
+    mcu_opt_ctrl: Option<Result<u64, Fault>>,
+    efer: Result<u64, Fault>,
+}
+
+impl Enumeration {
+    fn read(intel: bool, amd: bool) -> Self {
+        let max_leaf = cpuid(0, 0)[0];
+        let max_ext_leaf = cpuid(0x8000_0000, 0)[0];
+
+        let leaf1 = cpuid(1, 0);
+        let base_family = (leaf1[0] >> 8) & 0xF;
+        let family = if base_family == 0xF {
+            base_family + ((leaf1[0] >> 20) & 0xFF)
+        } else {
+            base_family
+        };
+
+        let (leaf7_ebx, leaf7_edx, leaf7_2_edx) = if max_leaf >= 7 {
+            let leaf7 = cpuid(7, 0);
+            let leaf7_2_edx = if leaf7[0] >= 2 { cpuid(7, 2)[3] } else { 0 };
+            (leaf7[1], leaf7[3], leaf7_2_edx)
+        } else {
+            (0, 0, 0)
+        };
+        let amd_ebx = if max_ext_leaf >= 0x8000_0008 {
+            cpuid(0x8000_0008, 0)[1]
+        } else {
+            0
+        };
+        let amd_21_eax = if max_ext_leaf >= 0x8000_0021 {
+            cpuid(0x8000_0021, 0)[0]
+        } else {
+            0
+        };
+
+        let hypervisor = (leaf1[2] & LEAF1_ECX_HYPERVISOR != 0).then(|| {
+            let [_, ebx, ecx, edx] = cpuid(0x4000_0000, 0);
+            let bytes: Vec<u8> = [ebx, ecx, edx]
+                .iter()
+                .flat_map(|r| r.to_le_bytes())
+                .filter(|b| b.is_ascii_graphic() || *b == b' ')
+                .collect();
+            String::from_utf8(bytes).unwrap_or_default().trim().into()
+        });
+
+        let has_spec_ctrl = leaf7_edx & (LEAF7_EDX_IBRS_IBPB | LEAF7_EDX_STIBP | LEAF7_EDX_SSBD)
+            != 0
+            || amd_ebx & (AMD_EBX_IBRS | AMD_EBX_STIBP | AMD_EBX_SSBD) != 0;
+        let arch_capabilities = (leaf7_edx & LEAF7_EDX_ARCH_CAPABILITIES != 0)
+            .then(|| recovery::rdmsr(MSR_IA32_ARCH_CAPABILITIES));
+        let spec_ctrl = has_spec_ctrl.then(|| recovery::rdmsr(MSR_IA32_SPEC_CTRL));
+
+        let gds_ctrl = matches!(arch_capabilities, Some(Ok(caps)) if caps & ARCH_CAP_GDS_CTRL != 0);
+        let mcu_opt_ctrl = (leaf7_edx & LEAF7_EDX_SRBDS_CTRL != 0 || gds_ctrl)
+            .then(|| recovery::rdmsr(MSR_IA32_MCU_OPT_CTRL));
+
+        Self {
+            intel,
+            amd,
+            family,
+            leaf1_ecx: leaf1[2],
+            leaf7_ebx,
+            leaf7_edx,
+            leaf7_2_edx,
+            amd_ebx,
+            amd_21_eax,
+            hypervisor,
+            arch_capabilities,
+            spec_ctrl,
+            mcu_opt_ctrl,
+            efer: recovery::rdmsr(MSR_EFER),
+        }
+    }
+
+    /// ARCH_CAPABILITIES bit, false if the MSR is missing or faulted
+    fn arch_cap(&self, bit: u64) -> bool {
+        matches!(self.arch_capabilities, Some(Ok(caps)) if caps & bit != 0)
+    }
+
+    fn has_arch_capabilities(&self) -> bool {
+        matches!(self.arch_capabilities, Some(Ok(_)))
+    }
+
+    fn spec_ctrl(&self, bit: u64) -> bool {
+        matches!(self.spec_ctrl, Some(Ok(value)) if value & bit != 0)
+    }
+
+    fn mcu_opt_ctrl(&self, bit: u64) -> bool {
+        matches!(self.mcu_opt_ctrl, Some(Ok(value)) if value & bit != 0)
+    }
+
+    fn has_ibrs(&self) -> bool {
+        self.leaf7_edx & LEAF7_EDX_IBRS_IBPB != 0 || self.amd_ebx & AMD_EBX_IBRS != 0
+    }
+
+    fn has_md_clear(&self) -> bool {
+        self.leaf7_edx & LEAF7_EDX_MD_CLEAR != 0
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Status {
+    NotAffected,
+    /// The hardware mitigation is active
+    Mitigated,
+    /// Affected, a mitigation is available but not in effect here
+    Available,
+    Vulnerable,
+    /// Depends on the processor model
+    Unknown,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::NotAffected => "Not affected",
+            Status::Mitigated => "Mitigated",
+            Status::Available => "Available",
+            Status::Vulnerable => "Vulnerable",
+            Status::Unknown => "Unknown",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Status::NotAffected | Status::Mitigated => Color::Green,
+            Status::Available => Color::Yellow,
+            Status::Vulnerable => Color::Red,
+            Status::Unknown => Color::DarkGray,
+        }
+    }
+}
+
+/// Status of one vulnerability with the evidence it was derived from
+type Verdict = (Status, &'static str);
+
+type Check = fn(&Enumeration) -> Verdict;
+
+fn meltdown(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_RDCL_NO) {
+        (Status::NotAffected, "RDCL_NO")
+    } else {
+        (Status::Vulnerable, "needs page table isolation")
+    }
+}
+
+fn spectre_v1(_e: &Enumeration) -> Verdict {
+    (Status::Available, "software barriers only")
+}
+
+fn spectre_v2(e: &Enumeration) -> Verdict {
+    if e.amd && e.amd_21_eax & AMD_21_EAX_AUTOIBRS != 0 {
+        if matches!(e.efer, Ok(efer) if efer & EFER_AIBRSE != 0) {
+            (Status::Mitigated, "Automatic IBRS")
+        } else {
+            (Status::Available, "Automatic IBRS, EFER.AIBRSE clear")
+        }
+    } else if e.amd && e.amd_ebx & AMD_EBX_IBRS_ALWAYS_ON != 0 {
+        (Status::Mitigated, "IBRS always on")
+    } else if e.arch_cap(ARCH_CAP_IBRS_ALL) {
+        if e.spec_ctrl(SPEC_CTRL_IBRS) {
+            (Status::Mitigated, "Enhanced IBRS")
+        } else {
+            (Status::Available, "Enhanced IBRS, SPEC_CTRL.IBRS clear")
+        }
+    } else if e.has_ibrs() {
+        if e.spec_ctrl(SPEC_CTRL_IBRS) {
+            (Status::Mitigated, "IBRS")
+        } else {
+            (Status::Available, "IBRS/IBPB, retpolines")
+        }
+    } else if e.amd_ebx & AMD_EBX_IBPB != 0 {
+        (Status::Available, "IBPB, retpolines")
+    } else {
+        (Status::Vulnerable, "no IBRS/IBPB, retpolines only")
+    }
+}
+
+fn spectre_bhi(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_BHI_NO) {
+        (Status::NotAffected, "BHI_NO")
+    } else if e.leaf7_2_edx & LEAF7_2_EDX_BHI_CTRL != 0 {
+        if e.spec_ctrl(SPEC_CTRL_BHI_DIS_S) {
+            (Status::Mitigated, "BHI_DIS_S")
+        } else {
+            (Status::Available, "BHI_DIS_S clear")
+        }
+    } else {
+        (Status::Available, "BHB clearing sequence")
+    }
+}
+
+fn spec_store_bypass(e: &Enumeration) -> Verdict {
+    if e.arch_cap(ARCH_CAP_SSB_NO) || e.amd_ebx & AMD_EBX_SSB_NO != 0 {
+        (Status::NotAffected, "SSB_NO")
+    } else if e.leaf7_edx & LEAF7_EDX_SSBD != 0 || e.amd_ebx & AMD_EBX_SSBD != 0 {
+        if e.spec_ctrl(SPEC_CTRL_SSBD) {
+            (Status::Mitigated, "SSBD")
+        } else {
+            (Status::Available, "SSBD clear")
+        }
+    } else if e.amd_ebx & AMD_EBX_VIRT_SSBD != 0 {
+        (Status::Available, "VIRT_SSBD")
+    } else {
+        (Status::Vulnerable, "no SSBD")
+    }
+}
+
+fn l1tf(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_RDCL_NO) {
+        (Status::NotAffected, "RDCL_NO")
+    } else if e.leaf7_edx & LEAF7_EDX_L1D_FLUSH != 0 {
+        (Status::Available, "PTE inversion, L1D flush")
+    } else {
+        (Status::Available, "PTE inversion")
+    }
+}
+
+fn mds(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_MDS_NO) {
+        (Status::NotAffected, "MDS_NO")
+    } else if e.has_md_clear() {
+        (Status::Available, "VERW buffer clearing")
+    } else {
+        (Status::Vulnerable, "no MD_CLEAR microcode")
+    }
+}
+
+fn tsx_async_abort(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_TAA_NO) {
+        (Status::NotAffected, "TAA_NO")
+    } else if e.leaf7_ebx & LEAF7_EBX_RTM == 0 {
+        (Status::NotAffected, "TSX disabled")
+    } else if e.arch_cap(ARCH_CAP_TSX_CTRL) {
+        (Status::Available, "disable TSX via TSX_CTRL")
+    } else if e.has_md_clear() {
+        (Status::Available, "VERW buffer clearing")
+    } else {
+        (Status::Vulnerable, "no MD_CLEAR microcode")
+    }
+}
+
+fn mmio_stale_data(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_SBDR_SSDP_NO)
+        && e.arch_cap(ARCH_CAP_FBSDP_NO)
+        && e.arch_cap(ARCH_CAP_PSDP_NO)
+    {
+        (Status::NotAffected, "SBDR_SSDP_NO FBSDP_NO PSDP_NO")
+    } else if e.arch_cap(ARCH_CAP_FB_CLEAR) {
+        (Status::Available, "VERW buffer clearing")
+    } else if !e.has_arch_capabilities() {
+        (Status::Unknown, "no ARCH_CAPABILITIES")
+    } else {
+        (Status::Vulnerable, "no FB_CLEAR microcode")
+    }
+}
+
+fn retbleed(e: &Enumeration) -> Verdict {
+    if e.amd {
+        if e.amd_ebx & AMD_EBX_BTC_NO != 0 {
+            (Status::NotAffected, "BTC_NO")
+        } else if e.family >= 0x19 {
+            (Status::NotAffected, "family 19h or later")
+        } else if e.amd_ebx & AMD_EBX_IBPB_RET != 0 {
+            (Status::Available, "IBPB on entry, untrained return")
+        } else {
+            (Status::Available, "untrained return thunk")
+        }
+    } else if !e.arch_cap(ARCH_CAP_RSBA) {
+        if e.arch_cap(ARCH_CAP_IBRS_ALL) {
+            (Status::NotAffected, "Enhanced IBRS, no RSBA")
+        } else {
+            (Status::Unknown, "model specific")
+        }
+    } else if e.arch_cap(ARCH_CAP_IBRS_ALL) {
+        if e.spec_ctrl(SPEC_CTRL_IBRS) {
+            (Status::Mitigated, "Enhanced IBRS")
+        } else {
+            (Status::Available, "Enhanced IBRS, SPEC_CTRL.IBRS clear")
+        }
+    } else if e.has_ibrs() {
+        (Status::Available, "IBRS")
+    } else {
+        (Status::Vulnerable, "RSBA, no IBRS")
+    }
+}
+
+fn spec_rstack_overflow(e: &Enumeration) -> Verdict {
+    if !e.amd {
+        (Status::NotAffected, "Intel")
+    } else if e.amd_21_eax & AMD_21_EAX_SRSO_NO != 0 {
+        (Status::NotAffected, "SRSO_NO")
+    } else if !(0x17..=0x19).contains(&e.family) {
+        (Status::NotAffected, "not Zen 1 to 4")
+    } else if e.amd_21_eax & (AMD_21_EAX_IBPB_BRTYPE | AMD_21_EAX_SBPB) != 0 {
+        (Status::Available, "IBPB_BRTYPE/SBPB, safe RET")
+    } else {
+        (Status::Available, "safe RET")
+    }
+}
+
+fn srbds(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.leaf1_ecx & LEAF1_ECX_RDRAND == 0 {
+        (Status::NotAffected, "no RDRAND")
+    } else if e.leaf7_edx & LEAF7_EDX_SRBDS_CTRL != 0 {
+        if e.mcu_opt_ctrl(MCU_OPT_CTRL_RNGDS_MITG_DIS) {
+            (Status::Vulnerable, "RNGDS_MITG_DIS set")
+        } else {
+            (Status::Mitigated, "microcode")
+        }
+    } else if e.arch_cap(ARCH_CAP_MDS_NO)
+        && (e.leaf7_ebx & LEAF7_EBX_RTM == 0 || e.arch_cap(ARCH_CAP_TAA_NO))
+    {
+        (Status::NotAffected, "MDS_NO, no TSX")
+    } else {
+        (Status::Unknown, "model specific, no SRBDS_CTRL")
+    }
+}
+
+fn gather_data_sampling(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_GDS_NO) {
+        (Status::NotAffected, "GDS_NO")
+    } else if e.leaf1_ecx & LEAF1_ECX_AVX == 0 {
+        (Status::NotAffected, "no AVX")
+    } else if e.arch_cap(ARCH_CAP_GDS_CTRL) {
+        if e.mcu_opt_ctrl(MCU_OPT_CTRL_GDS_MITG_DIS) {
+            (Status::Vulnerable, "GDS_MITG_DIS set")
+        } else {
+            (Status::Mitigated, "microcode")
+        }
+    } else {
+        (Status::Unknown, "model specific, no GDS_CTRL")
+    }
+}
+
+fn reg_file_data_sampling(e: &Enumeration) -> Verdict {
+    if e.amd {
+        (Status::NotAffected, "AMD")
+    } else if e.arch_cap(ARCH_CAP_RFDS_NO) {
+        (Status::NotAffected, "RFDS_NO")
+    } else if e.arch_cap(ARCH_CAP_RFDS_CLEAR) {
+        (Status::Available, "VERW register clearing")
+    } else {
+        (Status::Unknown, "Atom cores only")
+    }
+}
+
+/// Vulnerabilities in the order of Linux's sysfs directory listing
+const VULNERABILITIES: &[(&str, Check)] = &[
+    ("gather_data_sampling", gather_data_sampling),
+    ("l1tf", l1tf),
+    ("mds", mds),
+    ("meltdown", meltdown),
+    ("mmio_stale_data", mmio_stale_data),
+    ("reg_file_data_sampling", reg_file_data_sampling),
+    ("retbleed", retbleed),
+    ("spec_rstack_overflow", spec_rstack_overflow),
+    ("spec_store_bypass", spec_store_bypass),
+    ("spectre_bhi", spectre_bhi),
+    ("spectre_v1", spectre_v1),
+    ("spectre_v2", spectre_v2),
+    ("srbds", srbds),
+    ("tsx_async_abort", tsx_async_abort),
+];
+
+/// Every flag of a CPUID register with whether it is set
+fn cpuid_flag_lines(title: &str, value: u32, bits: &[(&str, u8)]) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::raw(format!("{} = 0x{:08x}", title, value))];
+    lines.extend(pane::flag_lines(
+        value as u64,
+        bits,
+        FLAGS_PER_LINE,
+        FLAG_WIDTH,
+    ));
+    lines
+}
+
+/// MSR value with its set flags, or why it could not be read
+fn msr_flag_lines(
+    title: &str,
+    value: &Option<Result<u64, Fault>>,
+    bits: &[(&str, u8)],
+) -> Vec<Line<'static>> {
+    let value = match value {
+        None => return vec![Line::raw(format!("{}: not enumerated", title))],
+        Some(Err(fault)) => {
+            return vec![Line::styled(
+                format!("{}: {} despite CPUID enumeration", title, fault_str(fault)),
+                Style::default().fg(Color::Red),
+            )];
+        }
+        Some(Ok(value)) => *value,
+    };
+
+    let mut lines = vec![Line::raw(format!("{} = 0x{:016x}", title, value))];
+    let set: Vec<&str> = bits
+        .iter()
+        .filter(|(_, bit)| value & (1 << bit) != 0)
+        .map(|(name, _)| *name)
+        .collect();
+    for chunk in set.chunks(FLAGS_PER_LINE) {
+        let line: String = chunk
+            .iter()
+            .map(|name| format!("  {:<w$}", name, w = FLAG_WIDTH - 2))
+            .collect();
+        lines.push(Line::styled(line, Style::default().fg(Color::Green)));
+    }
+    lines
+}
+
+pub struct VulnPane {
+    intel: bool,
+    amd: bool,
+    scroll: ScrollHints,
+}
+
+impl VulnPane {
+    pub fn new(cpuid_state: &CpuidState) -> Self {
+        let vendor = cpuid_state.vendor_info();
+        Self {
+            intel: vendor.intel,
+            amd: vendor.amd,
+            scroll: ScrollHints::default(),
+        }
+    }
+}
+
+impl Scrollable for VulnPane {
+    fn scroll_hints_mut(&mut self) -> &mut ScrollHints {
+        &mut self.scroll
+    }
+}
+
+impl Widget for &mut VulnPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let e = Enumeration::read(self.intel, self.amd);
+        let bold = Style::default().bold();
+        let mut lines = Vec::new();
+
+        if let Some(hypervisor) = &e.hypervisor {
+            let warn = Style::default().fg(Color::Yellow);
+            lines.push(Line::styled(format!("Hypervisor: {}", hypervisor), warn));
+            lines.push(Line::styled(
+                "The bits below are what the hypervisor chose to expose. It may",
+                warn,
+            ));
+            lines.push(Line::styled(
+                "hide mitigations, or report *_NO bits the host CPU does not have.",
+                warn,
+            ));
+            lines.push(Line::raw(""));
+        }
+
+        lines.push(Line::styled(
+            format!("{:<24}{:<14}{}", "Vulnerability", "Status", "Evidence"),
+            bold,
+        ));
+        for (name, verdict) in VULNERABILITIES {
+            let (status, evidence) = if e.intel || e.amd {
+                verdict(&e)
+            } else {
+                (Status::Unknown, "vendor not recognized")
+            };
+            lines.push(Line::from(vec![
+                Span::raw(format!("{:<24}", name)),
+                Span::styled(
+                    format!("{:<14}", status.label()),
+                    Style::default().fg(status.color()),
+                ),
+                Span::raw(evidence),
+            ]));
+        }
+        lines.push(Line::raw(""));
+
+        lines.push(Line::styled("Enumeration", bold));
+        lines.extend(cpuid_flag_lines(
+            "CPUID.7.0:EBX",
+            e.leaf7_ebx,
+            LEAF7_EBX_BITS,
+        ));
+        lines.extend(cpuid_flag_lines(
+            "CPUID.7.0:EDX",
+            e.leaf7_edx,
+            LEAF7_EDX_BITS,
+        ));
+        lines.extend(cpuid_flag_lines(
+            "CPUID.7.2:EDX",
+            e.leaf7_2_edx,
+            LEAF7_2_EDX_BITS,
+        ));
+        if e.amd {
+            lines.extend(cpuid_flag_lines(
+                "CPUID.8000_0008:EBX",
+                e.amd_ebx,
+                AMD_EBX_BITS,
+            ));
+            lines.extend(cpuid_flag_lines(
+                "CPUID.8000_0021:EAX",
+                e.amd_21_eax,
+                AMD_21_EAX_BITS,
+            ));
+        }
+        lines.extend(msr_flag_lines(
+            "IA32_ARCH_CAPABILITIES",
+            &e.arch_capabilities,
+            ARCH_CAP_BITS,
+        ));
+        lines.extend(msr_flag_lines(
+            "IA32_SPEC_CTRL",
+            &e.spec_ctrl,
+            SPEC_CTRL_BITS,
+        ));
+        if e.mcu_opt_ctrl.is_some() {
+            lines.extend(msr_flag_lines(
+                "IA32_MCU_OPT_CTRL",
+                &e.mcu_opt_ctrl,
+                MCU_OPT_CTRL_BITS,
+            ));
+        }
+
+        let n_lines = lines.len();
+        let paragraph = Paragraph::new(lines).scroll((self.scroll.y_offset, 0));
+        paragraph.render(area, buf);
+
+        self.scroll.update_from_render(n_lines, area.height);
+    }
+}
@@ -1,13 +1,63 @@
 use alloc::format;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::arch::asm;
 
 use ratatui::buffer::Buffer;
 use ratatui::layout::Rect;
 use ratatui::text::Line;
 use ratatui::widgets::{Paragraph, Widget};
+use x86_64::registers::control::{Cr4, Cr4Flags};
+use x86_64::registers::xcontrol::{XCr0, XCr0Flags};
 
 use crate::cpuid::CpuidState;
+use crate::pkru::rdpkru;
+
+/// XSAVE state component number of PKRU
+const PKRU_COMPONENT: u32 = 9;
+/// Offset of XSTATE_BV in the XSAVE header
+const XSTATE_BV_OFFSET: usize = 512;
+const XSAVE_AREA_SIZE: usize = 4096;
+
+#[repr(C, align(64))]
+struct XsaveArea([u8; XSAVE_AREA_SIZE]);
+
+/// Save the components in `mask` with XSAVE (standard format)
+fn xsave64(area: &mut XsaveArea, mask: u64) {
+    unsafe {
+        asm!(
+            "xsave64 [{}]",
+            in(reg) area as *mut XsaveArea,
+            in("eax") mask as u32,
+            in("edx") (mask >> 32) as u32,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// Read the PKRU copy written by XSAVE at `offset`. A component in its init
+/// state is not written, in which case PKRU is 0.
+fn xsave_pkru(offset: usize) -> Option<u32> {
+    let enabled = Cr4::read().contains(Cr4Flags::OSXSAVE) && XCr0::read().contains(XCr0Flags::MPK);
+    if !enabled || offset + 4 > XSAVE_AREA_SIZE {
+        return None;
+    }
+
+    let mut area = XsaveArea([0; XSAVE_AREA_SIZE]);
+    xsave64(&mut area, 1 << PKRU_COMPONENT);
+
+    let xstate_bv = u64::from_le_bytes(
+        area.0[XSTATE_BV_OFFSET..XSTATE_BV_OFFSET + 8]
+            .try_into()
+            .unwrap(),
+    );
+    if xstate_bv & (1 << PKRU_COMPONENT) == 0 {
+        return Some(0);
+    }
+    Some(u32::from_le_bytes(
+        area.0[offset..offset + 4].try_into().unwrap(),
+    ))
+}
 
 // XSAVE State merely references CPUID state
 pub struct XsaveState {
@@ -15,6 +65,8 @@ pub struct XsaveState {
     leaf_0xd_0: [u32; 4],
     leaf_0xd_1: [u32; 4],
     has_xsave: bool,
+    /// Offset of the PKRU component in the standard format, if supported
+    pkru_offset: Option<usize>,
 }
 
 impl XsaveState {
@@ -22,11 +74,16 @@ impl XsaveState {
         let leaf_0x1_0 = cpuid_state.leaf(0x1, 0);
         let leaf_0xd_0 = cpuid_state.leaf(0xd, 0);
         let leaf_0xd_1 = cpuid_state.leaf(0xd, 1);
+        let pkru_offset = cpuid_state
+            .cpu_features()
+            .xcr0_supports_pkru()
+            .then(|| cpuid_state.leaf(0xd, PKRU_COMPONENT)[1] as usize);
         Self {
             leaf_0x1_0,
             leaf_0xd_0,
             leaf_0xd_1,
             has_xsave: cpuid_state.has_xsave(),
+            pkru_offset,
         }
     }
 }
@@ -52,10 +109,25 @@ impl Widget for &XsaveState {
             (eax >> 1) & 1
         );
 
-        let lines = vec![line_1, line_2, line_3, line_4]
-            .into_iter()
-            .map(Line::raw)
-            .collect::<Vec<Line>>();
+        let mut lines = vec![line_1, line_2, line_3, line_4];
+
+        if let Some(offset) = self.pkru_offset {
+            let saved = match xsave_pkru(offset) {
+                Some(pkru) => format!("0x{:08x}", pkru),
+                None => "n/a".into(),
+            };
+            let live = if Cr4::read().contains(Cr4Flags::PROTECTION_KEY_USER) {
+                format!("0x{:08x}", rdpkru())
+            } else {
+                "n/a".into()
+            };
+            lines.push(format!(
+                "PKRU (component 9 @ 0x{:x}) -> XSAVE={} RDPKRU={}",
+                offset, saved, live
+            ));
+        }
+
+        let lines = lines.into_iter().map(Line::raw).collect::<Vec<Line>>();
         let paragraph = Paragraph::new(lines);
 
         paragraph.render(area, buf);
@@ -0,0 +1,842 @@
+//! MSR database: parsing and checking of the declarative MSR descriptions
+//! in `kernel/msrs.def`, and generation of the static tables the kernel
+//! includes
+//!
+//! The format is line based, `#` starts a comment:
+//!
+//! ```text
+//! category "Time Stamp Counter" requires=tsc
+//! msr IA32_TSC_ADJUST 0x3B requires=tsc_adjust
+//!     field 63:0 ADJUST number "Offset added to the TSC"
+//! msr IA32_MC{n}_STATUS 0x401 count=32 stride=4
+//!     field 63 VAL flag "Status valid"
+//! msr HV_X64_MSR_GUEST_OS_ID 0x4000_0000
+//!     field 63 OPEN flag "Open source OS"
+//!     when 63=1
+//!     field 15:0 BUILD number "Build number"
+//! unlisted
+//! msr IA32_XSS 0xDA0
+//! ```
+//!
+//! - `category "<name>" [requires=<features>] [source=<reader>]` starts a
+//!   category. Without a source, the MSRs below it are listed if their
+//!   requirements are met. With one, the named reader decides which exist.
+//! - `unlisted` starts the MSRs that are only named and decoded.
+//! - `msr <name> <address> [requires=<features>] [count=<n> stride=<s>]`
+//!   describes an MSR, or an array of `count` MSRs `stride` apart whose
+//!   name contains `{n}` for the index.
+//! - `field <msb>[:<lsb>] <name> <kind> ["<description>"]` describes a
+//!   bitfield of the last MSR. Kinds are `flag`, `number`, `address`,
+//!   `pat`, `mtrr`, `selector` and `mca`.
+//! - `when <bit>=<0|1>` starts an alternate layout of the last MSR, used
+//!   instead of its fields when the bit of the value has that state. The
+//!   fields below it belong to the layout. The first matching one applies.
+//!
+//! Features are lowercase identifiers, a leading `!` requires their absence.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+/// How a field value is shown, mirrors the kernel's `FieldKind`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Flag,
+    Number,
+    Address,
+    Pat,
+    Mtrr,
+    Selector,
+    Mca,
+}
+
+impl Kind {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "flag" => Kind::Flag,
+            "number" => Kind::Number,
+            "address" => Kind::Address,
+            "pat" => Kind::Pat,
+            "mtrr" => Kind::Mtrr,
+            "selector" => Kind::Selector,
+            "mca" => Kind::Mca,
+            _ => return None,
+        })
+    }
+
+    /// Variant name of the kernel's `FieldKind`
+    fn variant(self) -> &'static str {
+        match self {
+            Kind::Flag => "Flag",
+            Kind::Number => "Number",
+            Kind::Address => "Address",
+            Kind::Pat => "PatType",
+            Kind::Mtrr => "MtrrType",
+            Kind::Selector => "Selector",
+            Kind::Mca => "McaCode",
+        }
+    }
+}
+
+/// CPUID feature an MSR depends on, `present: false` requires its absence
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub feature: String,
+    pub present: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub lsb: u8,
+    pub msb: u8,
+    pub kind: Kind,
+    pub desc: String,
+    pub line: usize,
+}
+
+impl Field {
+    fn mask(&self) -> u64 {
+        let width = (self.msb - self.lsb + 1) as u32;
+        let bits = if width == 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        bits << self.lsb
+    }
+}
+
+/// Fields of an MSR whose layout depends on bit `bit` being `set`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variant {
+    pub bit: u8,
+    pub set: bool,
+    pub fields: Vec<Field>,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Msr {
+    /// Name, with `{n}` standing for the index if `count` > 1
+    pub name: String,
+    pub address: u32,
+    pub count: u32,
+    pub stride: u32,
+    pub requires: Vec<Condition>,
+    pub fields: Vec<Field>,
+    /// Alternate layouts, checked in order before `fields`
+    pub variants: Vec<Variant>,
+    pub line: usize,
+}
+
+impl Msr {
+    /// Addresses of all instances
+    pub fn addresses(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.count as u64).map(|n| self.address as u64 + n * self.stride as u64)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Category {
+    pub name: String,
+    pub requires: Vec<Condition>,
+    /// Reader in the kernel that lists the MSRs instead of their requirements
+    pub source: Option<String>,
+    pub msrs: Vec<Msr>,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Database {
+    pub categories: Vec<Category>,
+    /// MSRs that are named and decoded but not listed in a category
+    pub unlisted: Vec<Msr>,
+}
+
+/// Syntax error with its 1-based line number
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Split a line into words, keeping "quoted strings" together and dropping
+/// comments
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated string".into()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    let digits = s.replace('_', "");
+    let parsed = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number: {}", s))
+}
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+    parse_number(s).and_then(|n| u32::try_from(n).map_err(|_| format!("out of range: {}", s)))
+}
+
+fn parse_bit(s: &str) -> Result<u8, String> {
+    parse_number(s)
+        .ok()
+        .filter(|&bit| bit < 64)
+        .map(|bit| bit as u8)
+        .ok_or_else(|| format!("invalid bit: {}", s))
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_lowercase())
+        && s.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn parse_conditions(s: &str) -> Result<Vec<Condition>, String> {
+    s.split(',')
+        .map(|item| {
+            let (feature, present) = match item.strip_prefix('!') {
+                Some(feature) => (feature, false),
+                None => (item, true),
+            };
+            if !is_identifier(feature) {
+                return Err(format!("invalid feature: {}", item));
+            }
+            Ok(Condition {
+                feature: feature.into(),
+                present,
+            })
+        })
+        .collect()
+}
+
+/// `key=value` options after the positional arguments of a line
+fn parse_options<'a>(
+    tokens: &'a [String],
+    allowed: &[&str],
+) -> Result<HashMap<&'a str, &'a str>, String> {
+    let mut options = HashMap::new();
+    for token in tokens {
+        let (key, value) = token
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value: {}", token))?;
+        if !allowed.contains(&key) {
+            return Err(format!("unknown option: {}", key));
+        }
+        if options.insert(key, value).is_some() {
+            return Err(format!("duplicate option: {}", key));
+        }
+    }
+    Ok(options)
+}
+
+fn parse_category(args: &[String], line: usize) -> Result<Category, String> {
+    let name = args.first().ok_or("usage: category \"<name>\" [options]")?;
+    let options = parse_options(&args[1..], &["requires", "source"])?;
+    let source = options.get("source").map(|s| s.to_string());
+    if let Some(source) = &source
+        && !is_identifier(source)
+    {
+        return Err(format!("invalid source: {}", source));
+    }
+
+    Ok(Category {
+        name: name.clone(),
+        requires: options
+            .get("requires")
+            .map_or(Ok(Vec::new()), |s| parse_conditions(s))?,
+        source,
+        msrs: Vec::new(),
+        line,
+    })
+}
+
+fn parse_msr(args: &[String], line: usize) -> Result<Msr, String> {
+    let [name, address, options @ ..] = args else {
+        return Err("usage: msr <name> <address> [options]".into());
+    };
+    let options = parse_options(options, &["requires", "count", "stride"])?;
+    let number = |key| options.get(key).map_or(Ok(1), |s| parse_u32(s));
+
+    Ok(Msr {
+        name: name.clone(),
+        address: parse_u32(address)?,
+        count: number("count")?,
+        stride: number("stride")?,
+        requires: options
+            .get("requires")
+            .map_or(Ok(Vec::new()), |s| parse_conditions(s))?,
+        fields: Vec::new(),
+        variants: Vec::new(),
+        line,
+    })
+}
+
+fn parse_variant(args: &[String], line: usize) -> Result<Variant, String> {
+    let usage = "usage: when <bit>=<0|1>";
+    let [condition] = args else {
+        return Err(usage.into());
+    };
+    let (bit, state) = condition.split_once('=').ok_or(usage)?;
+    let set = match state {
+        "0" => false,
+        "1" => true,
+        _ => return Err(format!("invalid bit state: {}", state)),
+    };
+
+    Ok(Variant {
+        bit: parse_bit(bit)?,
+        set,
+        fields: Vec::new(),
+        line,
+    })
+}
+
+fn parse_field(args: &[String], line: usize) -> Result<Field, String> {
+    let (bits, name, kind, desc) = match args {
+        [bits, name, kind] => (bits, name, kind, ""),
+        [bits, name, kind, desc] => (bits, name, kind, desc.as_str()),
+        _ => return Err("usage: field <msb>[:<lsb>] <name> <kind> [\"<desc>\"]".into()),
+    };
+    let (msb, lsb) = match bits.split_once(':') {
+        Some((msb, lsb)) => (parse_bit(msb)?, parse_bit(lsb)?),
+        None => (parse_bit(bits)?, parse_bit(bits)?),
+    };
+
+    Ok(Field {
+        name: name.clone(),
+        lsb,
+        msb,
+        kind: Kind::parse(kind).ok_or_else(|| format!("unknown field kind: {}", kind))?,
+        desc: desc.into(),
+        line,
+    })
+}
+
+/// Parse the database. Only syntax is checked here, see `Database::check`.
+pub fn parse(source: &str) -> Result<Database, Error> {
+    let mut db = Database::default();
+    let mut in_unlisted = false;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| Error { line, message };
+
+        let tokens = tokenize(text).map_err(error)?;
+        let Some((keyword, args)) = tokens.split_first() else {
+            continue;
+        };
+
+        let msrs = if in_unlisted {
+            Some(&mut db.unlisted)
+        } else {
+            db.categories.last_mut().map(|c| &mut c.msrs)
+        };
+
+        match keyword.as_str() {
+            "category" => {
+                if in_unlisted {
+                    return Err(error("categories must precede `unlisted`".into()));
+                }
+                db.categories
+                    .push(parse_category(args, line).map_err(error)?);
+            }
+            "unlisted" => {
+                if !args.is_empty() {
+                    return Err(error("`unlisted` takes no arguments".into()));
+                }
+                in_unlisted = true;
+            }
+            "msr" => {
+                let msrs = msrs.ok_or_else(|| error("msr outside of a category".into()))?;
+                msrs.push(parse_msr(args, line).map_err(error)?);
+            }
+            "when" => {
+                let msr = msrs
+                    .and_then(|msrs| msrs.last_mut())
+                    .ok_or_else(|| error("when outside of an msr".into()))?;
+                msr.variants.push(parse_variant(args, line).map_err(error)?);
+            }
+            "field" => {
+                let msr = msrs
+                    .and_then(|msrs| msrs.last_mut())
+                    .ok_or_else(|| error("field outside of an msr".into()))?;
+                let field = parse_field(args, line).map_err(error)?;
+                match msr.variants.last_mut() {
+                    Some(variant) => variant.fields.push(field),
+                    None => msr.fields.push(field),
+                }
+            }
+            other => return Err(error(format!("unknown keyword: {}", other))),
+        }
+    }
+    Ok(db)
+}
+
+impl Database {
+    /// All MSRs, listed ones first
+    pub fn msrs(&self) -> impl Iterator<Item = &Msr> {
+        self.categories
+            .iter()
+            .flat_map(|c| &c.msrs)
+            .chain(&self.unlisted)
+    }
+
+    /// Consistency problems: duplicate names and addresses, malformed arrays
+    /// and overlapping or invalid fields. Empty if the database is sound.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let mut categories: HashMap<&str, usize> = HashMap::new();
+        for category in &self.categories {
+            if let Some(first) = categories.insert(&category.name, category.line) {
+                problems.push(format!(
+                    "line {}: category \"{}\" already defined on line {}",
+                    category.line, category.name, first
+                ));
+            }
+        }
+
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        let mut addresses: HashMap<u64, (&str, usize)> = HashMap::new();
+        for msr in self.msrs() {
+            if let Some(first) = names.insert(&msr.name, msr.line) {
+                problems.push(format!(
+                    "line {}: {} already defined on line {}",
+                    msr.line, msr.name, first
+                ));
+            }
+            for address in msr.addresses() {
+                if address > u32::MAX as u64 {
+                    problems.push(format!(
+                        "line {}: {} extends past 0xFFFFFFFF",
+                        msr.line, msr.name
+                    ));
+                    break;
+                }
+                if let Some((other, line)) = addresses.insert(address, (&msr.name, msr.line)) {
+                    problems.push(format!(
+                        "line {}: {} at 0x{:X} overlaps {} on line {}",
+                        msr.line, msr.name, address, other, line
+                    ));
+                }
+            }
+            problems.extend(check_msr(msr));
+        }
+        problems
+    }
+
+    /// Rust source of the static tables, expecting `CategoryDef`, `MsrDef`,
+    /// `Variant`, `Condition`, `Feature`, `Source`, `BitField` and the `FieldKind`
+    /// variants in scope
+    pub fn to_rust(&self) -> String {
+        let mut out = String::new();
+        out.push_str("// Generated by kernel/build.rs from kernel/msrs.def, do not edit\n\n");
+
+        out.push_str("pub static CATEGORIES: &[CategoryDef] = &[\n");
+        for category in &self.categories {
+            let source = match &category.source {
+                Some(source) => format!("Some(Source::{})", camel_case(source)),
+                None => "None".into(),
+            };
+            let _ = writeln!(out, "    CategoryDef {{");
+            let _ = writeln!(out, "        name: {:?},", category.name);
+            let _ = writeln!(
+                out,
+                "        requires: {},",
+                conditions_rust(&category.requires)
+            );
+            let _ = writeln!(out, "        source: {},", source);
+            let _ = writeln!(out, "        msrs: &[");
+            for msr in &category.msrs {
+                msr_rust(&mut out, msr, "            ");
+            }
+            let _ = writeln!(out, "        ],");
+            let _ = writeln!(out, "    }},");
+        }
+        out.push_str("];\n\n");
+
+        out.push_str("pub static UNLISTED: &[MsrDef] = &[\n");
+        for msr in &self.unlisted {
+            msr_rust(&mut out, msr, "    ");
+        }
+        out.push_str("];\n");
+        out
+    }
+}
+
+fn check_msr(msr: &Msr) -> Vec<String> {
+    let mut problems = Vec::new();
+    let problem =
+        |line: usize, message: String| format!("line {}: {}: {}", line, msr.name, message);
+
+    let templated = msr.name.contains("{n}");
+    if msr.count == 0 || msr.stride == 0 {
+        problems.push(problem(
+            msr.line,
+            "count and stride must be non-zero".into(),
+        ));
+    }
+    if msr.count > 1 && !templated {
+        problems.push(problem(msr.line, "array name needs an {n} index".into()));
+    }
+    if msr.count == 1 && templated {
+        problems.push(problem(msr.line, "{n} in the name of a single MSR".into()));
+    }
+
+    problems.extend(check_fields(msr, &msr.fields));
+    for (i, variant) in msr.variants.iter().enumerate() {
+        if variant.fields.is_empty() {
+            problems.push(problem(
+                variant.line,
+                format!("layout for bit {} has no fields", variant.bit),
+            ));
+        }
+        if let Some(other) = msr.variants[..i]
+            .iter()
+            .find(|other| (other.bit, other.set) == (variant.bit, variant.set))
+        {
+            problems.push(problem(
+                variant.line,
+                format!(
+                    "layout for bit {} already defined on line {}",
+                    variant.bit, other.line
+                ),
+            ));
+        }
+        problems.extend(check_fields(msr, &variant.fields));
+    }
+    problems
+}
+
+/// Invalid, duplicate and overlapping fields of one layout
+fn check_fields(msr: &Msr, fields: &[Field]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let problem =
+        |line: usize, message: String| format!("line {}: {}: {}", line, msr.name, message);
+
+    for (i, field) in fields.iter().enumerate() {
+        if field.lsb > field.msb {
+            problems.push(problem(
+                field.line,
+                format!("{} has lsb above msb", field.name),
+            ));
+            continue;
+        }
+        if field.kind == Kind::Flag && field.lsb != field.msb {
+            problems.push(problem(
+                field.line,
+                format!("flag {} spans several bits", field.name),
+            ));
+        }
+        for other in &fields[..i] {
+            if other.lsb > other.msb {
+                continue;
+            }
+            if other.name == field.name && !field.name.is_empty() {
+                problems.push(problem(
+                    field.line,
+                    format!(
+                        "field {} already defined on line {}",
+                        field.name, other.line
+                    ),
+                ));
+            }
+            if other.mask() & field.mask() != 0 {
+                problems.push(problem(
+                    field.line,
+                    format!(
+                        "field {} overlaps {} on line {}",
+                        field.name, other.name, other.line
+                    ),
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// `tsc_adjust` -> `TscAdjust`
+fn camel_case(identifier: &str) -> String {
+    identifier
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn conditions_rust(conditions: &[Condition]) -> String {
+    let items: Vec<String> = conditions
+        .iter()
+        .map(|c| {
+            let feature = camel_case(&c.feature);
+            if c.present {
+                format!("Condition::Has(Feature::{})", feature)
+            } else {
+                format!("Condition::Lacks(Feature::{})", feature)
+            }
+        })
+        .collect();
+    format!("&[{}]", items.join(", "))
+}
+
+fn msr_rust(out: &mut String, msr: &Msr, indent: &str) {
+    let _ = writeln!(out, "{}MsrDef {{", indent);
+    let _ = writeln!(out, "{}    name: {:?},", indent, msr.name);
+    let _ = writeln!(out, "{}    address: 0x{:X},", indent, msr.address);
+    let _ = writeln!(out, "{}    count: {},", indent, msr.count);
+    let _ = writeln!(out, "{}    stride: {},", indent, msr.stride);
+    let _ = writeln!(
+        out,
+        "{}    requires: {},",
+        indent,
+        conditions_rust(&msr.requires)
+    );
+    let _ = writeln!(out, "{}    fields: &[", indent);
+    fields_rust(out, &msr.fields, &format!("{}        ", indent));
+    let _ = writeln!(out, "{}    ],", indent);
+    let _ = writeln!(out, "{}    variants: &[", indent);
+    for variant in &msr.variants {
+        let _ = writeln!(out, "{}        Variant {{", indent);
+        let _ = writeln!(out, "{}            bit: {},", indent, variant.bit);
+        let _ = writeln!(out, "{}            set: {},", indent, variant.set);
+        let _ = writeln!(out, "{}            fields: &[", indent);
+        fields_rust(out, &variant.fields, &format!("{}                ", indent));
+        let _ = writeln!(out, "{}            ],", indent);
+        let _ = writeln!(out, "{}        }},", indent);
+    }
+    let _ = writeln!(out, "{}    ],", indent);
+    let _ = writeln!(out, "{}}},", indent);
+}
+
+fn fields_rust(out: &mut String, fields: &[Field], indent: &str) {
+    for field in fields {
+        let _ = writeln!(
+            out,
+            "{}BitField::new({:?}, {}, {}, FieldKind::{}, {:?}),",
+            indent,
+            field.name,
+            field.lsb,
+            field.msb,
+            field.kind.variant(),
+            field.desc
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_categories_and_fields() {
+        let db = parse(
+            "# comment\n\
+             category \"Long Mode\" requires=lm\n\
+             msr IA32_EFER 0xC000_0080\n\
+             \x20   field 0 SCE flag \"SYSCALL enable\" # trailing comment\n\
+             \x20   field 63:12 BASE address\n\
+             category \"AMD\" requires=amd,!perfctr_core source=amd_perf\n\
+             unlisted\n\
+             msr IA32_XSS 0xDA0\n",
+        )
+        .unwrap();
+
+        assert_eq!(db.categories.len(), 2);
+        let long_mode = &db.categories[0];
+        assert_eq!(long_mode.name, "Long Mode");
+        assert_eq!(long_mode.msrs[0].address, 0xC000_0080);
+        let fields = &long_mode.msrs[0].fields;
+        assert_eq!((fields[0].lsb, fields[0].msb), (0, 0));
+        assert_eq!(fields[0].desc, "SYSCALL enable");
+        assert_eq!((fields[1].lsb, fields[1].msb), (12, 63));
+        assert_eq!(fields[1].kind, Kind::Address);
+
+        let amd = &db.categories[1];
+        assert_eq!(amd.source.as_deref(), Some("amd_perf"));
+        assert!(!amd.requires[1].present);
+        assert_eq!(db.unlisted[0].name, "IA32_XSS");
+        assert!(db.check().is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("msr IA32_TSC 0x10").unwrap_err();
+        assert_eq!(err.line, 1);
+
+        let err = parse("category \"A\"\nmsr A 0x10\nfield 0 X bogus").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("bogus"));
+
+        let err = parse("category \"A\"\nmsr A 0x10 count=two").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        assert!(parse("category \"A\"\nmsr A 0x10\nfield 64 X flag").is_err());
+        assert!(parse("category \"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_addresses() {
+        let db = parse(
+            "category \"A\"\n\
+             msr MC{n}_STATUS 0x401 count=4 stride=4\n\
+             msr MC{n}_ADDR 0x402 count=4 stride=4\n\
+             msr OTHER 0x40D\n",
+        )
+        .unwrap();
+
+        let problems = db.check();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("0x40D overlaps MC{n}_STATUS"));
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let db = parse("category \"A\"\nmsr A 0x10\ncategory \"A\"\nunlisted\nmsr A 0x11").unwrap();
+        assert_eq!(db.check().len(), 2);
+    }
+
+    #[test]
+    fn test_overlapping_fields() {
+        let db = parse(
+            "category \"A\"\n\
+             msr A 0x10\n\
+             field 7:0 TYPE mtrr\n\
+             field 11 V flag\n\
+             field 12:8 B number\n\
+             field 3:2 F flag\n",
+        )
+        .unwrap();
+
+        let problems = db.check();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("B overlaps V"));
+        assert!(problems[1].contains("flag F spans several bits"));
+        assert!(problems[2].contains("F overlaps TYPE"));
+    }
+
+    #[test]
+    fn test_variants() {
+        let db = parse(
+            "category \"A\"\n\
+             msr A 0x10\n\
+             field 7:0 LOW number\n\
+             when 63=1\n\
+             field 15:0 WIDE number\n\
+             field 63 OPEN flag\n",
+        )
+        .unwrap();
+
+        let msr = &db.categories[0].msrs[0];
+        assert_eq!(msr.fields.len(), 1);
+        assert_eq!((msr.variants[0].bit, msr.variants[0].set), (63, true));
+        assert_eq!(msr.variants[0].fields[0].name, "WIDE");
+        assert!(db.check().is_empty());
+        let rust = db.to_rust();
+        assert!(rust.contains("bit: 63,") && rust.contains("set: true,"));
+        assert!(rust.contains("BitField::new(\"WIDE\", 0, 15, FieldKind::Number, \"\"),"));
+
+        assert!(parse("category \"A\"\nmsr A 0x10\nwhen 63=2").is_err());
+        assert!(parse("category \"A\"\nwhen 63=1").is_err());
+    }
+
+    #[test]
+    fn test_variant_problems() {
+        let db = parse(
+            "category \"A\"\n\
+             msr A 0x10\n\
+             when 0=1\n\
+             field 7:0 X number\n\
+             field 3 Y flag\n\
+             when 0=1\n\
+             field 1 Z flag\n\
+             when 1=0\n",
+        )
+        .unwrap();
+
+        let problems = db.check();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("Y overlaps X"));
+        assert!(problems[1].contains("already defined on line 3"));
+        assert!(problems[2].contains("bit 1 has no fields"));
+    }
+
+    #[test]
+    fn test_array_names() {
+        let db = parse("category \"A\"\nmsr A{n} 0x10\nmsr B 0x20 count=2").unwrap();
+        assert_eq!(db.check().len(), 2);
+    }
+
+    #[test]
+    fn test_to_rust() {
+        let db = parse(
+            "category \"A\" requires=amd,!perfctr_core\n\
+             msr A 0x10 requires=tsc_adjust\n\
+             field 0 EN flag \"Enable \\ \"\n",
+        )
+        .unwrap();
+
+        let rust = db.to_rust();
+        assert!(
+            rust.contains("Condition::Has(Feature::Amd), Condition::Lacks(Feature::PerfctrCore)")
+        );
+        assert!(rust.contains("requires: &[Condition::Has(Feature::TscAdjust)]"));
+        assert!(rust.contains("BitField::new(\"EN\", 0, 0, FieldKind::Flag, \"Enable \\\\ \"),"));
+        assert!(rust.contains("pub static UNLISTED: &[MsrDef] = &[\n];"));
+    }
+
+    #[test]
+    fn test_kernel_database() {
+        let db = parse(include_str!("../../kernel/msrs.def")).unwrap();
+        let problems = db.check();
+        assert!(problems.is_empty(), "{}", problems.join("\n"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Symbol table embedded in the kernel image for symbolized backtraces
+//!
+//! The image build script encodes the function symbols of the linked kernel
+//! and writes them into a section the kernel reserves for them. The kernel
+//! parses the table in place, nothing is allocated on lookup.
+//!
+//! Layout, little endian:
+//!
+//! ```text
+//! header   magic "KSYMTAB1", link address of the table (u64),
+//!          symbol count (u32), string bytes (u32)
+//! entries  address (u64), size (u32), offset of the name (u32),
+//!          sorted by address
+//! strings  names, each prefixed by its length (u16)
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub const MAGIC: [u8; 8] = *b"KSYMTAB1";
+pub const HEADER_SIZE: usize = 24;
+pub const ENTRY_SIZE: usize = 16;
+
+/// A function symbol to encode
+pub struct Symbol {
+    pub address: u64,
+    pub size: u32,
+    pub name: String,
+}
+
+/// Encode `symbols` for a table linked at `base`. Symbols sharing an address
+/// keep the first name, names are cut to what the length prefix holds.
+pub fn encode(base: u64, mut symbols: Vec<Symbol>) -> Vec<u8> {
+    symbols.sort_by_key(|symbol| symbol.address);
+    symbols.dedup_by_key(|symbol| symbol.address);
+
+    let mut entries = Vec::with_capacity(symbols.len() * ENTRY_SIZE);
+    let mut strings = Vec::new();
+    for symbol in &symbols {
+        let mut len = symbol.name.len().min(u16::MAX as usize);
+        while !symbol.name.is_char_boundary(len) {
+            len -= 1;
+        }
+        entries.extend_from_slice(&symbol.address.to_le_bytes());
+        entries.extend_from_slice(&symbol.size.to_le_bytes());
+        entries.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        strings.extend_from_slice(&(len as u16).to_le_bytes());
+        strings.extend_from_slice(&symbol.name.as_bytes()[..len]);
+    }
+
+    let mut table = Vec::with_capacity(HEADER_SIZE + entries.len() + strings.len());
+    table.extend_from_slice(&MAGIC);
+    table.extend_from_slice(&base.to_le_bytes());
+    table.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    table.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+    table.extend_from_slice(&entries);
+    table.extend_from_slice(&strings);
+    table
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// An encoded table, read in place
+#[derive(Clone, Copy)]
+pub struct SymbolTable<'a> {
+    base: u64,
+    entries: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Table at the start of `data`, None without the magic or if the
+    /// counts do not fit in `data`
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(..MAGIC.len())? != MAGIC {
+            return None;
+        }
+        let base = u64_at(data, 8)?;
+        let count = u32_at(data, 16)? as usize;
+        let strings_len = u32_at(data, 20)? as usize;
+        let strings_start = HEADER_SIZE + count * ENTRY_SIZE;
+        Some(Self {
+            base,
+            entries: data.get(HEADER_SIZE..strings_start)?,
+            strings: data.get(strings_start..strings_start + strings_len)?,
+        })
+    }
+
+    /// Address the table itself was linked at
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn address(&self, index: usize) -> u64 {
+        u64_at(self.entries, index * ENTRY_SIZE).unwrap_or(0)
+    }
+
+    /// Symbol containing the link time address `address`, with the offset
+    /// into it. Symbols of unknown size extend to the next one.
+    pub fn lookup(&self, address: u64) -> Option<(&'a str, u64)> {
+        // first entry above the address, the candidate is the one before
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.address(mid) <= address {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let index = low.checked_sub(1)?;
+
+        let entry = index * ENTRY_SIZE;
+        let start = self.address(index);
+        let size = u32_at(self.entries, entry + 8)? as u64;
+        if size != 0 && address - start >= size {
+            return None;
+        }
+        let name = u32_at(self.entries, entry + 12)? as usize;
+        let len = u16_at(self.strings, name)? as usize;
+        let name = core::str::from_utf8(self.strings.get(name + 2..name + 2 + len)?).ok()?;
+        Some((name, address - start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn symbol(address: u64, size: u32, name: &str) -> Symbol {
+        Symbol {
+            address,
+            size,
+            name: name.to_string(),
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        encode(
+            0x5000,
+            vec![
+                symbol(0x2000, 0x40, "kernel::b"),
+                symbol(0x1000, 0x100, "kernel::a"),
+                symbol(0x3000, 0, "kernel::c"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample();
+        let table = SymbolTable::parse(&data).unwrap();
+        assert_eq!(table.base(), 0x5000);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.lookup(0x1000), Some(("kernel::a", 0)));
+        assert_eq!(table.lookup(0x10ff), Some(("kernel::a", 0xff)));
+        assert_eq!(table.lookup(0x2010), Some(("kernel::b", 0x10)));
+    }
+
+    #[test]
+    fn test_lookup_outside_symbols() {
+        let data = sample();
+        let table = SymbolTable::parse(&data).unwrap();
+        assert_eq!(table.lookup(0xfff), None);
+        assert_eq!(table.lookup(0x1100), None);
+        assert_eq!(table.lookup(0x2040), None);
+    }
+
+    #[test]
+    fn test_unknown_size_extends_to_end() {
+        let data = sample();
+        let table = SymbolTable::parse(&data).unwrap();
+        assert_eq!(table.lookup(0x9000), Some(("kernel::c", 0x6000)));
+    }
+
+    #[test]
+    fn test_duplicate_addresses_keep_first() {
+        let data = encode(
+            0,
+            vec![symbol(0x1000, 8, "first"), symbol(0x1000, 8, "alias")],
+        );
+        let table = SymbolTable::parse(&data).unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.lookup(0x1004), Some(("first", 4)));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_data() {
+        assert!(SymbolTable::parse(&[0; 64]).is_none());
+        let data = sample();
+        assert!(SymbolTable::parse(&data[..data.len() - 1]).is_none());
+        // a reserved but unfilled section parses only once written
+        let mut reserved = vec![0; 4096];
+        reserved[..data.len()].copy_from_slice(&data);
+        assert_eq!(SymbolTable::parse(&reserved).unwrap().len(), 3);
+    }
+}